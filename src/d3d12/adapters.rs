@@ -0,0 +1,330 @@
+use crate::d3d12::*;
+use std::env;
+use std::fmt;
+use std::mem;
+use std::ptr;
+use winapi::ctypes::c_void;
+use winapi::shared::dxgi::{IDXGIAdapter1, DXGI_ADAPTER_DESC1, DXGI_ADAPTER_FLAG_SOFTWARE};
+use winapi::shared::dxgi1_4::IDXGIFactory4;
+use winapi::shared::dxgi1_6::{
+    IDXGIFactory6, DXGI_GPU_PREFERENCE, DXGI_GPU_PREFERENCE_HIGH_PERFORMANCE,
+    DXGI_GPU_PREFERENCE_MINIMUM_POWER,
+};
+use winapi::Interface;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct AdapterInfo {
+    // Position in the DXGI enumeration order, `AdapterPreference::Index` refers to it.
+    pub index: u32,
+    pub name: String,
+    pub luid: u64,
+    pub vendor_id: u32,
+    pub device_id: u32,
+    pub dedicated_video_memory: u64,
+    pub is_software: bool,
+}
+
+impl AdapterInfo {
+    pub fn from_desc(index: u32, desc: &DXGI_ADAPTER_DESC1) -> Self {
+        let len = desc
+            .Description
+            .iter()
+            .position(|&c| c == 0)
+            .unwrap_or(desc.Description.len());
+        Self {
+            index,
+            name: String::from_utf16_lossy(&desc.Description[..len]),
+            luid: ((desc.AdapterLuid.HighPart as u32 as u64) << 32)
+                | desc.AdapterLuid.LowPart as u64,
+            vendor_id: desc.VendorId,
+            device_id: desc.DeviceId,
+            dedicated_video_memory: desc.DedicatedVideoMemory as u64,
+            is_software: (desc.Flags & DXGI_ADAPTER_FLAG_SOFTWARE) != 0,
+        }
+    }
+}
+
+impl fmt::Display for AdapterInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} (vendor 0x{:04x}, device 0x{:04x}, {} MB dedicated{})",
+            self.name,
+            self.vendor_id,
+            self.device_id,
+            self.dedicated_video_memory / (1024 * 1024),
+            if self.is_software { ", software" } else { "" },
+        )
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum AdapterPreference {
+    // First hardware adapter in the DXGI enumeration order.
+    Default,
+    HighPerformance,
+    MinimumPower,
+    Warp,
+    // Case-insensitive substring of the adapter name.
+    Name(String),
+    Index(u32),
+}
+
+impl AdapterPreference {
+    // Accepts `high-performance`, `minimum-power`, `warp`, `default`, an adapter index or a name.
+    pub fn parse(value: &str) -> Self {
+        match value.to_lowercase().as_str() {
+            "default" => AdapterPreference::Default,
+            "high-performance" => AdapterPreference::HighPerformance,
+            "minimum-power" => AdapterPreference::MinimumPower,
+            "warp" => AdapterPreference::Warp,
+            _ => match value.parse::<u32>() {
+                Ok(index) => AdapterPreference::Index(index),
+                Err(_) => AdapterPreference::Name(value.to_string()),
+            },
+        }
+    }
+
+    // Looks for `--adapter <value>` or `--adapter=<value>` in the process arguments.
+    pub fn from_command_line() -> Option<Self> {
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            if arg == "--adapter" {
+                return args.next().map(|value| Self::parse(&value));
+            }
+            if let Some(value) = arg.strip_prefix("--adapter=") {
+                return Some(Self::parse(value));
+            }
+        }
+        None
+    }
+
+    pub fn matches(&self, info: &AdapterInfo) -> bool {
+        match self {
+            AdapterPreference::Name(name) => {
+                let name = name.to_lowercase();
+                info.name.to_lowercase().contains(&name)
+            }
+            AdapterPreference::Index(index) => info.index == *index,
+            _ => !info.is_software,
+        }
+    }
+}
+
+impl Default for AdapterPreference {
+    fn default() -> Self {
+        AdapterPreference::Default
+    }
+}
+
+// All adapters in the DXGI enumeration order, including the software ones.
+pub fn enumerate_adapters() -> Result<Vec<AdapterInfo>> {
+    let mut factory = create_dxgi_factory(false)?;
+    let adapters = enum_adapters(factory);
+    factory.release();
+    Ok(adapters?
+        .into_iter()
+        .map(|(mut adapter, info)| {
+            adapter.release();
+            info
+        })
+        .collect())
+}
+
+fn enum_adapters(
+    factory: WeakPtr<IDXGIFactory4>,
+) -> Result<Vec<(WeakPtr<IDXGIAdapter1>, AdapterInfo)>> {
+    let mut adapters = Vec::new();
+    loop {
+        let mut radapter: *mut IDXGIAdapter1 = ptr::null_mut();
+        let hr = unsafe { factory.EnumAdapters1(adapters.len() as u32, &mut radapter) };
+        if hr != 0 {
+            break;
+        }
+        let adapter = WeakPtr::from_raw(radapter);
+        let info = AdapterInfo::from_desc(adapters.len() as u32, &adapter_desc(adapter)?);
+        adapters.push((adapter, info));
+    }
+    Ok(adapters)
+}
+
+fn adapter_desc(adapter: WeakPtr<IDXGIAdapter1>) -> Result<DXGI_ADAPTER_DESC1> {
+    let mut desc: DXGI_ADAPTER_DESC1 = unsafe { mem::zeroed() };
+    hr!(adapter.GetDesc1(&mut desc))?;
+    Ok(desc)
+}
+
+// LUID of the first hardware adapter in the given GPU preference order. `None` when the system
+// has no IDXGIFactory6.
+fn adapter_luid_by_gpu_preference(
+    factory: WeakPtr<IDXGIFactory4>,
+    preference: DXGI_GPU_PREFERENCE,
+) -> Result<Option<u64>> {
+    let mut rfactory6: *mut IDXGIFactory6 = ptr::null_mut();
+    let hr = unsafe {
+        factory.QueryInterface(
+            &IDXGIFactory6::uuidof(),
+            &mut rfactory6 as *mut *mut _ as *mut *mut c_void,
+        )
+    };
+    if hr != 0 {
+        return Ok(None);
+    }
+    let mut factory6 = WeakPtr::from_raw(rfactory6);
+    let mut luid = None;
+    for index in 0.. {
+        let mut radapter: *mut IDXGIAdapter1 = ptr::null_mut();
+        let hr = unsafe {
+            factory6.EnumAdapterByGpuPreference(
+                index,
+                preference,
+                &IDXGIAdapter1::uuidof(),
+                &mut radapter as *mut *mut _ as *mut *mut c_void,
+            )
+        };
+        if hr != 0 {
+            break;
+        }
+        let mut adapter = WeakPtr::from_raw(radapter);
+        let desc = adapter_desc(adapter);
+        adapter.release();
+        let info = AdapterInfo::from_desc(index, &desc?);
+        if !info.is_software {
+            luid = Some(info.luid);
+            break;
+        }
+    }
+    factory6.release();
+    Ok(luid)
+}
+
+fn warp_adapter(
+    factory: WeakPtr<IDXGIFactory4>,
+    adapters: &[(WeakPtr<IDXGIAdapter1>, AdapterInfo)],
+) -> Result<(WeakPtr<IDXGIAdapter1>, AdapterInfo)> {
+    let mut radapter: *mut IDXGIAdapter1 = ptr::null_mut();
+    hr!(factory.EnumWarpAdapter(
+        &IDXGIAdapter1::uuidof(),
+        &mut radapter as *mut *mut _ as *mut *mut c_void,
+    ))?;
+    let adapter = WeakPtr::from_raw(radapter);
+    let mut info = AdapterInfo::from_desc(adapters.len() as u32, &adapter_desc(adapter)?);
+    if let Some((_, listed)) = adapters.iter().find(|(_, listed)| listed.luid == info.luid) {
+        info.index = listed.index;
+    }
+    Ok((adapter, info))
+}
+
+// Falls back to the first hardware adapter when nothing matches the preference, and to WARP when
+// there is no hardware adapter.
+pub(crate) fn select_adapter(
+    factory: WeakPtr<IDXGIFactory4>,
+    preference: &AdapterPreference,
+    log_hook: Option<LogHook>,
+) -> Result<(WeakPtr<IDXGIAdapter1>, AdapterInfo)> {
+    let mut adapters = enum_adapters(factory)?;
+    let selected = if *preference == AdapterPreference::Warp {
+        Some(warp_adapter(factory, &adapters)?)
+    } else {
+        let gpu_preference_luid = match preference {
+            AdapterPreference::HighPerformance => {
+                adapter_luid_by_gpu_preference(factory, DXGI_GPU_PREFERENCE_HIGH_PERFORMANCE)?
+            }
+            AdapterPreference::MinimumPower => {
+                adapter_luid_by_gpu_preference(factory, DXGI_GPU_PREFERENCE_MINIMUM_POWER)?
+            }
+            _ => None,
+        };
+        let position = match gpu_preference_luid {
+            Some(luid) => adapters.iter().position(|(_, info)| info.luid == luid),
+            None => adapters
+                .iter()
+                .position(|(_, info)| preference.matches(info)),
+        };
+        let position = position.or_else(|| {
+            log(
+                log_hook,
+                LogLevel::Warning,
+                &format!("no adapter matches {:?}, using the default one", preference),
+            );
+            adapters.iter().position(|(_, info)| !info.is_software)
+        });
+        position.map(|position| adapters.remove(position))
+    };
+    let selected = match selected {
+        Some(selected) => Ok(selected),
+        None => {
+            log(
+                log_hook,
+                LogLevel::Warning,
+                "no hardware adapter found, using WARP",
+            );
+            warp_adapter(factory, &adapters)
+        }
+    };
+    for (mut adapter, _) in adapters {
+        adapter.release();
+    }
+    selected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn adapter(index: u32, name: &str, is_software: bool) -> AdapterInfo {
+        AdapterInfo {
+            index,
+            name: name.to_string(),
+            luid: index as u64,
+            vendor_id: 0,
+            device_id: 0,
+            dedicated_video_memory: 0,
+            is_software,
+        }
+    }
+
+    #[test]
+    fn parse_preferences() {
+        assert_eq!(
+            AdapterPreference::parse("High-Performance"),
+            AdapterPreference::HighPerformance
+        );
+        assert_eq!(
+            AdapterPreference::parse("minimum-power"),
+            AdapterPreference::MinimumPower
+        );
+        assert_eq!(AdapterPreference::parse("WARP"), AdapterPreference::Warp);
+        assert_eq!(
+            AdapterPreference::parse("default"),
+            AdapterPreference::Default
+        );
+        assert_eq!(AdapterPreference::parse("1"), AdapterPreference::Index(1));
+        assert_eq!(
+            AdapterPreference::parse("GeForce"),
+            AdapterPreference::Name("GeForce".to_string())
+        );
+    }
+
+    #[test]
+    fn names_match_case_insensitive_substrings() {
+        let preference = AdapterPreference::parse("radeon");
+        assert!(preference.matches(&adapter(0, "AMD Radeon RX 6800", false)));
+        assert!(!preference.matches(&adapter(0, "NVIDIA GeForce RTX 3080", false)));
+    }
+
+    #[test]
+    fn indices_match_the_enumeration_order() {
+        let preference = AdapterPreference::Index(1);
+        assert!(!preference.matches(&adapter(0, "A", false)));
+        assert!(preference.matches(&adapter(1, "B", true)));
+    }
+
+    #[test]
+    fn other_preferences_match_hardware_adapters() {
+        let software = adapter(1, "Microsoft Basic Render Driver", true);
+        assert!(AdapterPreference::Default.matches(&adapter(0, "A", false)));
+        assert!(!AdapterPreference::Default.matches(&software));
+        assert!(!AdapterPreference::HighPerformance.matches(&software));
+    }
+}
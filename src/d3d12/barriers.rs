@@ -0,0 +1,259 @@
+use crate::d3d12::{ResourceHandle, SubresourceTransition};
+use std::mem;
+use winapi::um::d3d12::*;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum QueuedBarrier {
+    Transition {
+        resource: ResourceHandle,
+        transition: SubresourceTransition,
+        flags: D3D12_RESOURCE_BARRIER_FLAGS,
+    },
+    // `None` means any UAV access.
+    Uav {
+        resource: Option<ResourceHandle>,
+    },
+    // `None` means any placed or reserved resource.
+    Aliasing {
+        resource_before: Option<ResourceHandle>,
+        resource_after: Option<ResourceHandle>,
+    },
+}
+
+impl QueuedBarrier {
+    fn references(&self, handle: ResourceHandle) -> bool {
+        match *self {
+            QueuedBarrier::Transition { resource, .. } => resource == handle,
+            QueuedBarrier::Uav { resource } => resource == Some(handle),
+            QueuedBarrier::Aliasing {
+                resource_before,
+                resource_after,
+            } => resource_before == Some(handle) || resource_after == Some(handle),
+        }
+    }
+}
+
+// Barriers waiting for the next flush. Consecutive (non-split) transitions of the same subresource
+// are merged into one, and dropped entirely when they end in the state they started from.
+pub struct BarrierQueue {
+    barriers: Vec<QueuedBarrier>,
+    // Every batch taken while recording is enabled, for tests.
+    recorded_batches: Option<Vec<Vec<QueuedBarrier>>>,
+}
+
+impl BarrierQueue {
+    pub fn new() -> Self {
+        Self {
+            barriers: Vec::new(),
+            recorded_batches: None,
+        }
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.barriers.is_empty()
+    }
+
+    pub fn push_transition(
+        &mut self,
+        resource: ResourceHandle,
+        transition: SubresourceTransition,
+        flags: D3D12_RESOURCE_BARRIER_FLAGS,
+    ) {
+        if flags == D3D12_RESOURCE_BARRIER_FLAG_NONE {
+            let last = self.barriers.iter().rposition(|b| b.references(resource));
+            if let Some(index) = last {
+                if let QueuedBarrier::Transition {
+                    transition: ref mut queued,
+                    flags: D3D12_RESOURCE_BARRIER_FLAG_NONE,
+                    ..
+                } = self.barriers[index]
+                {
+                    if queued.subresource == transition.subresource {
+                        assert!(queued.state_after == transition.state_before);
+                        if queued.state_before == transition.state_after {
+                            self.barriers.remove(index);
+                        } else {
+                            queued.state_after = transition.state_after;
+                        }
+                        return;
+                    }
+                }
+            }
+        }
+        self.barriers.push(QueuedBarrier::Transition {
+            resource,
+            transition,
+            flags,
+        });
+    }
+
+    pub fn push_uav(&mut self, resource: Option<ResourceHandle>) {
+        let barrier = QueuedBarrier::Uav { resource };
+        let last = match resource {
+            Some(handle) => self.barriers.iter().rev().find(|b| b.references(handle)),
+            None => self.barriers.last(),
+        };
+        if last != Some(&barrier) {
+            self.barriers.push(barrier);
+        }
+    }
+
+    pub fn push_aliasing(
+        &mut self,
+        resource_before: Option<ResourceHandle>,
+        resource_after: Option<ResourceHandle>,
+    ) {
+        self.barriers.push(QueuedBarrier::Aliasing {
+            resource_before,
+            resource_after,
+        });
+    }
+
+    pub fn remove_resource(&mut self, resource: ResourceHandle) {
+        self.barriers.retain(|b| !b.references(resource));
+    }
+
+    // Takes the batch to flush.
+    pub fn take(&mut self) -> Vec<QueuedBarrier> {
+        let batch = mem::replace(&mut self.barriers, Vec::new());
+        if let Some(recorded) = self.recorded_batches.as_mut() {
+            if !batch.is_empty() {
+                recorded.push(batch.clone());
+            }
+        }
+        batch
+    }
+
+    // Enabling (or disabling) recording drops the batches recorded so far.
+    pub fn set_record_batches(&mut self, enable: bool) {
+        self.recorded_batches = if enable { Some(Vec::new()) } else { None };
+    }
+
+    pub fn clear_recorded_batches(&mut self) {
+        if let Some(recorded) = self.recorded_batches.as_mut() {
+            recorded.clear();
+        }
+    }
+
+    pub fn recorded_batches(&self) -> &[Vec<QueuedBarrier>] {
+        self.recorded_batches
+            .as_ref()
+            .map_or(&[], |recorded| recorded.as_slice())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COMMON: D3D12_RESOURCE_STATES = D3D12_RESOURCE_STATE_COMMON;
+    const SRV: D3D12_RESOURCE_STATES = D3D12_RESOURCE_STATE_NON_PIXEL_SHADER_RESOURCE;
+    const UAV: D3D12_RESOURCE_STATES = D3D12_RESOURCE_STATE_UNORDERED_ACCESS;
+    const ALL: u32 = D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES;
+    const NONE: D3D12_RESOURCE_BARRIER_FLAGS = D3D12_RESOURCE_BARRIER_FLAG_NONE;
+
+    fn transition(
+        subresource: u32,
+        state_before: D3D12_RESOURCE_STATES,
+        state_after: D3D12_RESOURCE_STATES,
+    ) -> SubresourceTransition {
+        SubresourceTransition {
+            subresource,
+            state_before,
+            state_after,
+        }
+    }
+
+    fn queued(
+        resource: ResourceHandle,
+        transition: SubresourceTransition,
+        flags: D3D12_RESOURCE_BARRIER_FLAGS,
+    ) -> QueuedBarrier {
+        QueuedBarrier::Transition {
+            resource,
+            transition,
+            flags,
+        }
+    }
+
+    #[test]
+    fn round_trip_is_eliminated() {
+        let a = ResourceHandle::for_test(1);
+        let mut queue = BarrierQueue::new();
+        queue.push_transition(a, transition(ALL, COMMON, SRV), NONE);
+        queue.push_transition(a, transition(ALL, SRV, COMMON), NONE);
+        assert!(queue.is_empty());
+
+        // A -> B -> C is merged into A -> C.
+        queue.push_transition(a, transition(ALL, COMMON, SRV), NONE);
+        queue.push_transition(a, transition(ALL, SRV, UAV), NONE);
+        assert_eq!(
+            queue.take(),
+            [queued(a, transition(ALL, COMMON, UAV), NONE)]
+        );
+    }
+
+    #[test]
+    fn round_trip_of_one_subresource_keeps_the_others() {
+        let a = ResourceHandle::for_test(1);
+        let mut queue = BarrierQueue::new();
+        queue.push_transition(a, transition(0, SRV, UAV), NONE);
+        queue.push_transition(a, transition(1, SRV, UAV), NONE);
+        queue.push_transition(a, transition(1, UAV, SRV), NONE);
+        assert_eq!(queue.take(), [queued(a, transition(0, SRV, UAV), NONE)]);
+    }
+
+    #[test]
+    fn barriers_in_between_prevent_elimination() {
+        let a = ResourceHandle::for_test(1);
+        let mut queue = BarrierQueue::new();
+        queue.push_transition(a, transition(ALL, SRV, UAV), NONE);
+        queue.push_uav(Some(a));
+        queue.push_transition(a, transition(ALL, UAV, SRV), NONE);
+        assert_eq!(queue.take().len(), 3);
+
+        // Split barriers are never merged.
+        let begin = D3D12_RESOURCE_BARRIER_FLAG_BEGIN_ONLY;
+        queue.push_transition(a, transition(ALL, SRV, UAV), begin);
+        queue.push_transition(a, transition(ALL, UAV, SRV), NONE);
+        assert_eq!(queue.take().len(), 2);
+    }
+
+    #[test]
+    fn one_batch_per_flush() {
+        let a = ResourceHandle::for_test(1);
+        let b = ResourceHandle::for_test(2);
+        let mut queue = BarrierQueue::new();
+        queue.set_record_batches(true);
+
+        queue.push_transition(a, transition(ALL, COMMON, SRV), NONE);
+        queue.push_transition(b, transition(ALL, COMMON, UAV), NONE);
+        queue.take();
+        // Nothing queued: no batch.
+        queue.take();
+        queue.push_transition(a, transition(ALL, SRV, COMMON), NONE);
+        queue.push_transition(a, transition(ALL, COMMON, SRV), NONE);
+        queue.take();
+        queue.push_uav(None);
+        queue.take();
+
+        assert_eq!(
+            queue.recorded_batches(),
+            [
+                vec![
+                    queued(a, transition(ALL, COMMON, SRV), NONE),
+                    queued(b, transition(ALL, COMMON, UAV), NONE),
+                ],
+                vec![QueuedBarrier::Uav { resource: None }],
+            ]
+        );
+
+        queue.clear_recorded_batches();
+        assert!(queue.recorded_batches().is_empty());
+        queue.set_record_batches(false);
+        queue.push_uav(None);
+        queue.take();
+        assert!(queue.recorded_batches().is_empty());
+    }
+}
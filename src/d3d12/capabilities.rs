@@ -0,0 +1,249 @@
+use crate::d3d12::Device;
+use std::iter;
+use std::mem;
+use winapi::ctypes::c_void;
+use winapi::shared::minwindef::BOOL;
+use winapi::um::d3d12::*;
+use winapi::um::d3dcommon::*;
+
+// Newer than what winapi 0.3 knows about; values and layouts from d3d12.h.
+const D3D_FEATURE_LEVEL_12_2: D3D_FEATURE_LEVEL = 0xc200;
+const D3D12_FEATURE_D3D12_OPTIONS5: D3D12_FEATURE = 27;
+const D3D12_FEATURE_D3D12_OPTIONS6: D3D12_FEATURE = 30;
+const D3D12_FEATURE_D3D12_OPTIONS7: D3D12_FEATURE = 32;
+const HIGHEST_SHADER_MODEL: u32 = 0x67;
+
+#[repr(C)]
+#[derive(Default)]
+struct FeatureDataOptions5 {
+    srv_only_tiled_resource_tier3: BOOL,
+    render_passes_tier: u32,
+    raytracing_tier: u32,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct FeatureDataOptions6 {
+    additional_shading_rates_supported: BOOL,
+    per_primitive_shading_rate_supported_with_viewport_indexing: BOOL,
+    variable_shading_rate_tier: u32,
+    shading_rate_image_tile_size: u32,
+    background_processing_supported: BOOL,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct FeatureDataOptions7 {
+    mesh_shader_tier: u32,
+    sampler_feedback_tier: u32,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct ShaderModel {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl ShaderModel {
+    pub const fn new(major: u32, minor: u32) -> Self {
+        Self { major, minor }
+    }
+
+    fn from_d3d(model: D3D_SHADER_MODEL) -> Self {
+        Self::new(model >> 4, model & 0xf)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum RaytracingTier {
+    NotSupported,
+    Tier1_0,
+    Tier1_1,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum MeshShaderTier {
+    NotSupported,
+    Tier1,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum VariableShadingRateTier {
+    NotSupported,
+    Tier1,
+    Tier2,
+}
+
+// What the device supports, queried once at context creation. Code paths that depend on optional
+// features check it instead of calling CheckFeatureSupport themselves, so they can be exercised
+// headless with `minimal()`, `full()` or a hand-made set.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct DeviceCapabilities {
+    pub feature_level: D3D_FEATURE_LEVEL,
+    pub shader_model: ShaderModel,
+    pub resource_binding_tier: D3D12_RESOURCE_BINDING_TIER,
+    pub root_signature_version: D3D_ROOT_SIGNATURE_VERSION,
+    pub tiled_resources_tier: D3D12_TILED_RESOURCES_TIER,
+    pub raytracing_tier: RaytracingTier,
+    pub mesh_shader_tier: MeshShaderTier,
+    pub variable_shading_rate_tier: VariableShadingRateTier,
+    pub wave_ops: bool,
+    pub wave_lane_count_min: u32,
+    pub wave_lane_count_max: u32,
+}
+
+impl DeviceCapabilities {
+    // The least the context can run on.
+    pub fn minimal() -> Self {
+        Self {
+            feature_level: D3D_FEATURE_LEVEL_11_1,
+            shader_model: ShaderModel::new(5, 1),
+            resource_binding_tier: D3D12_RESOURCE_BINDING_TIER_1,
+            root_signature_version: D3D_ROOT_SIGNATURE_VERSION_1_0,
+            tiled_resources_tier: D3D12_TILED_RESOURCES_TIER_NOT_SUPPORTED,
+            raytracing_tier: RaytracingTier::NotSupported,
+            mesh_shader_tier: MeshShaderTier::NotSupported,
+            variable_shading_rate_tier: VariableShadingRateTier::NotSupported,
+            wave_ops: false,
+            wave_lane_count_min: 0,
+            wave_lane_count_max: 0,
+        }
+    }
+
+    // Everything this module knows about.
+    pub fn full() -> Self {
+        Self {
+            feature_level: D3D_FEATURE_LEVEL_12_2,
+            shader_model: ShaderModel::from_d3d(HIGHEST_SHADER_MODEL),
+            resource_binding_tier: D3D12_RESOURCE_BINDING_TIER_3,
+            root_signature_version: D3D_ROOT_SIGNATURE_VERSION_1_1,
+            tiled_resources_tier: D3D12_TILED_RESOURCES_TIER_3,
+            raytracing_tier: RaytracingTier::Tier1_1,
+            mesh_shader_tier: MeshShaderTier::Tier1,
+            variable_shading_rate_tier: VariableShadingRateTier::Tier2,
+            wave_ops: true,
+            wave_lane_count_min: 32,
+            wave_lane_count_max: 32,
+        }
+    }
+
+    pub fn query(device: Device) -> Self {
+        let mut caps = Self::minimal();
+
+        // Like the shader model, a level the runtime doesn't know fails the whole query, so drop
+        // requested levels from the top until it succeeds.
+        let levels = [
+            D3D_FEATURE_LEVEL_11_0,
+            D3D_FEATURE_LEVEL_11_1,
+            D3D_FEATURE_LEVEL_12_0,
+            D3D_FEATURE_LEVEL_12_1,
+            D3D_FEATURE_LEVEL_12_2,
+        ];
+        for num_levels in (1..=levels.len()).rev() {
+            let mut feature_levels = D3D12_FEATURE_DATA_FEATURE_LEVELS {
+                NumFeatureLevels: num_levels as u32,
+                pFeatureLevelsRequested: levels.as_ptr(),
+                MaxSupportedFeatureLevel: 0,
+            };
+            if check_feature(device, D3D12_FEATURE_FEATURE_LEVELS, &mut feature_levels) {
+                caps.feature_level = feature_levels.MaxSupportedFeatureLevel;
+                break;
+            }
+        }
+
+        // Fails with E_INVALIDARG when the runtime doesn't know the requested model, so step down
+        // until it accepts one; it then reports the highest the driver supports.
+        let models = (0x60..=HIGHEST_SHADER_MODEL).rev().chain(iter::once(0x51));
+        for model in models {
+            let mut shader_model = D3D12_FEATURE_DATA_SHADER_MODEL {
+                HighestShaderModel: model,
+            };
+            if check_feature(device, D3D12_FEATURE_SHADER_MODEL, &mut shader_model) {
+                caps.shader_model = ShaderModel::from_d3d(shader_model.HighestShaderModel);
+                break;
+            }
+        }
+
+        let mut root_signature = D3D12_FEATURE_DATA_ROOT_SIGNATURE {
+            HighestVersion: D3D_ROOT_SIGNATURE_VERSION_1_1,
+        };
+        if check_feature(device, D3D12_FEATURE_ROOT_SIGNATURE, &mut root_signature) {
+            caps.root_signature_version = root_signature.HighestVersion;
+        }
+
+        let mut options: D3D12_FEATURE_DATA_D3D12_OPTIONS = unsafe { mem::zeroed() };
+        if check_feature(device, D3D12_FEATURE_D3D12_OPTIONS, &mut options) {
+            caps.resource_binding_tier = options.ResourceBindingTier;
+            caps.tiled_resources_tier = options.TiledResourcesTier;
+        }
+
+        let mut options1: D3D12_FEATURE_DATA_D3D12_OPTIONS1 = unsafe { mem::zeroed() };
+        if check_feature(device, D3D12_FEATURE_D3D12_OPTIONS1, &mut options1) {
+            caps.wave_ops = options1.WaveOps != 0;
+            caps.wave_lane_count_min = options1.WaveLaneCountMin;
+            caps.wave_lane_count_max = options1.WaveLaneCountMax;
+        }
+
+        let mut options5 = FeatureDataOptions5::default();
+        if check_feature(device, D3D12_FEATURE_D3D12_OPTIONS5, &mut options5) {
+            caps.raytracing_tier = match options5.raytracing_tier {
+                0 => RaytracingTier::NotSupported,
+                10 => RaytracingTier::Tier1_0,
+                _ => RaytracingTier::Tier1_1,
+            };
+        }
+
+        let mut options6 = FeatureDataOptions6::default();
+        if check_feature(device, D3D12_FEATURE_D3D12_OPTIONS6, &mut options6) {
+            caps.variable_shading_rate_tier = match options6.variable_shading_rate_tier {
+                0 => VariableShadingRateTier::NotSupported,
+                1 => VariableShadingRateTier::Tier1,
+                _ => VariableShadingRateTier::Tier2,
+            };
+        }
+
+        let mut options7 = FeatureDataOptions7::default();
+        if check_feature(device, D3D12_FEATURE_D3D12_OPTIONS7, &mut options7) {
+            caps.mesh_shader_tier = match options7.mesh_shader_tier {
+                0 => MeshShaderTier::NotSupported,
+                _ => MeshShaderTier::Tier1,
+            };
+        }
+
+        caps
+    }
+
+    #[inline]
+    pub fn supports_raytracing(&self) -> bool {
+        self.raytracing_tier != RaytracingTier::NotSupported
+    }
+
+    #[inline]
+    pub fn supports_mesh_shaders(&self) -> bool {
+        self.mesh_shader_tier != MeshShaderTier::NotSupported
+    }
+
+    #[inline]
+    pub fn supports_variable_shading_rate(&self) -> bool {
+        self.variable_shading_rate_tier != VariableShadingRateTier::NotSupported
+    }
+
+    // Tables over the whole bindless region need tier 2; tier 1 limits SRV tables to 128
+    // descriptors. Tier 2 still limits UAVs, tier 3 lifts that too.
+    #[inline]
+    pub fn supports_bindless(&self) -> bool {
+        self.resource_binding_tier >= D3D12_RESOURCE_BINDING_TIER_2
+    }
+}
+
+// Unknown features (older runtimes) fail the call and leave `data` untouched.
+fn check_feature<T>(device: Device, feature: D3D12_FEATURE, data: &mut T) -> bool {
+    let hr = unsafe {
+        device.CheckFeatureSupport(
+            feature,
+            data as *mut T as *mut c_void,
+            mem::size_of::<T>() as u32,
+        )
+    };
+    hr == 0
+}
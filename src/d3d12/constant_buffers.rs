@@ -0,0 +1,176 @@
+use std::marker::PhantomData;
+use std::mem;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct ConstantBufferField {
+    pub name: &'static str,
+    pub offset: usize,
+    pub size: usize,
+    // Element count and element size for array fields. Arrays of 4-byte scalars are taken to be
+    // vectors or matrices (`[f32; 3]`, `[f32; 16]`), other arrays to be HLSL arrays.
+    pub array: Option<(usize, usize)>,
+}
+
+// Lets `constant_buffer!` tell array fields apart: `(&ArrayProbe::<T>(PhantomData))
+// .array_elements()` resolves to `ArrayElements` for arrays and to `NotAnArray` otherwise.
+#[doc(hidden)]
+pub struct ArrayProbe<T>(pub PhantomData<T>);
+
+#[doc(hidden)]
+pub trait ArrayElements {
+    fn array_elements(&self) -> Option<(usize, usize)>;
+}
+
+impl<T, const N: usize> ArrayElements for ArrayProbe<[T; N]> {
+    fn array_elements(&self) -> Option<(usize, usize)> {
+        Some((N, mem::size_of::<T>()))
+    }
+}
+
+#[doc(hidden)]
+pub trait NotAnArray {
+    fn array_elements(&self) -> Option<(usize, usize)> {
+        None
+    }
+}
+
+impl<T> NotAnArray for &ArrayProbe<T> {}
+
+// Plain data that can be copied into a constant buffer. Implemented by `constant_buffer!`, which
+// also makes the type `#[repr(C)]` and lists its fields for the packing check.
+pub unsafe trait ConstantBufferData: Copy {
+    fn fields() -> Vec<ConstantBufferField>;
+}
+
+// Declares a `#[repr(C)]` struct usable with `Context::upload_constant_buffer`:
+//
+//     constant_buffer! {
+//         pub struct PerDraw {
+//             pub clip_from_object: [f32; 16],
+//             pub color: [f32; 3],
+//             pub roughness: f32,
+//         }
+//     }
+#[macro_export]
+macro_rules! constant_buffer {
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident {
+            $($(#[$field_attr:meta])* $field_vis:vis $field:ident : $field_ty:ty),* $(,)?
+        }
+    ) => {
+        $(#[$attr])*
+        #[repr(C)]
+        #[derive(Copy, Clone)]
+        $vis struct $name {
+            $($(#[$field_attr])* $field_vis $field: $field_ty),*
+        }
+
+        unsafe impl $crate::d3d12::ConstantBufferData for $name {
+            fn fields() -> Vec<$crate::d3d12::ConstantBufferField> {
+                let value = std::mem::MaybeUninit::<$name>::uninit();
+                let base = value.as_ptr();
+                vec![$($crate::d3d12::ConstantBufferField {
+                    name: stringify!($field),
+                    offset: unsafe { std::ptr::addr_of!((*base).$field) as usize - base as usize },
+                    size: std::mem::size_of::<$field_ty>(),
+                    array: {
+                        #[allow(unused_imports)]
+                        use $crate::d3d12::{ArrayElements, NotAnArray};
+                        (&$crate::d3d12::ArrayProbe::<$field_ty>(std::marker::PhantomData))
+                            .array_elements()
+                    },
+                }),*]
+            }
+        }
+    };
+}
+
+// HLSL packs cbuffer members into 16-byte registers: a member may not straddle a register
+// boundary, and members larger than a register (matrices, structs) start on a new one and occupy
+// whole registers. Arrays start on a new register too and every element takes at least one, so
+// Rust and HLSL only agree on arrays of 16-byte multiples (`[[f32; 4]; 8]`, not `[[f32; 3]; 8]`).
+// Returns one message per field whose Rust offset would not match.
+pub fn hlsl_packing_errors(fields: &[ConstantBufferField], size: usize) -> Vec<String> {
+    let mut errors = Vec::new();
+    let mut hlsl_offset = 0;
+    for field in fields {
+        let register_offset = hlsl_offset % 16;
+        let array = field.array.filter(|&(_, element_size)| element_size > 4);
+        if let Some((count, element_size)) = array {
+            hlsl_offset = (hlsl_offset + 15) & !15;
+            if count > 1 && element_size % 16 != 0 {
+                errors.push(format!(
+                    "`{}` is an array of {}-byte elements; HLSL puts every element in its own \
+                     16-byte register, so elements must be 16-byte multiples",
+                    field.name, element_size
+                ));
+            }
+        } else if field.size > 16 {
+            hlsl_offset = (hlsl_offset + 15) & !15;
+            if field.size % 16 != 0 {
+                errors.push(format!(
+                    "`{}` is {} bytes; members larger than 16 bytes must fill whole 16-byte \
+                     registers",
+                    field.name, field.size
+                ));
+            }
+        } else if register_offset + field.size > 16 {
+            hlsl_offset = (hlsl_offset + 15) & !15;
+        }
+        if field.offset != hlsl_offset {
+            errors.push(format!(
+                "`{}` is at offset {} but HLSL places it at {}; add explicit padding",
+                field.name, field.offset, hlsl_offset
+            ));
+            hlsl_offset = field.offset;
+        }
+        hlsl_offset += field.size;
+    }
+    if size > (hlsl_offset + 15) & !15 {
+        errors.push(format!(
+            "size is {} bytes but the HLSL cbuffer is only {}",
+            size,
+            (hlsl_offset + 15) & !15
+        ));
+    }
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    constant_buffer! {
+        struct Vectors {
+            clip_from_object: [f32; 16],
+            color: [f32; 3],
+            roughness: f32,
+        }
+    }
+
+    constant_buffer! {
+        struct Arrays {
+            positions: [[f32; 4]; 8],
+            colors: [[f32; 3]; 8],
+        }
+    }
+
+    fn errors<T: ConstantBufferData>() -> Vec<String> {
+        hlsl_packing_errors(&T::fields(), mem::size_of::<T>())
+    }
+
+    #[test]
+    fn scalar_arrays_are_vectors_and_matrices() {
+        assert_eq!(Vectors::fields()[1].array, Some((3, 4)));
+        assert!(errors::<Vectors>().is_empty());
+    }
+
+    #[test]
+    fn arrays_need_16_byte_elements() {
+        let errors = errors::<Arrays>();
+        assert!(!errors.is_empty());
+        assert!(errors[0].contains("`colors` is an array of 12-byte elements"));
+        assert!(errors.iter().all(|e| !e.contains("`positions`")));
+    }
+}
@@ -0,0 +1,211 @@
+use crate::d3d12::{
+    AdapterPreference, DeviceCapabilities, Error, LogHook, Result, SwapchainConfig,
+    MAX_NUM_FRAMES_IN_FLIGHT,
+};
+use winapi::um::d3dcommon::*;
+
+// Creation options of `Context`. Defaults match what the renderer needs for the sample; the
+// debug layer and GPU-based validation default to on in debug builds only.
+#[derive(Clone, Debug)]
+pub struct ContextDesc {
+    pub adapter: AdapterPreference,
+    pub swapchain: SwapchainConfig,
+    pub num_frames_in_flight: u32,
+    pub feature_level: D3D_FEATURE_LEVEL,
+    pub debug_layer: bool,
+    // Requires the debug layer.
+    pub gpu_validation: bool,
+    pub rtv_heap_capacity: u32,
+    pub dsv_heap_capacity: u32,
+    pub cbv_srv_uav_heap_capacity: u32,
+    // Ignored (no bindless region) when the device doesn't support bindless tables.
+    pub num_bindless_descriptors: u32,
+    // Per frame in flight.
+    pub num_transient_descriptors: u32,
+    // Size of the pages the per-frame upload memory is suballocated from; larger allocations get
+    // a dedicated page.
+    pub upload_page_size: u32,
+    // Used instead of the capabilities queried from the device, e.g. to exercise fallback paths
+    // on a capable GPU.
+    pub capabilities_override: Option<DeviceCapabilities>,
+    // Receives adapter fallbacks and state validation errors.
+    pub log_hook: Option<LogHook>,
+}
+
+impl ContextDesc {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn adapter(mut self, preference: AdapterPreference) -> Self {
+        self.adapter = preference;
+        self
+    }
+
+    pub fn swapchain(mut self, config: SwapchainConfig) -> Self {
+        self.swapchain = config;
+        self
+    }
+
+    pub fn num_frames_in_flight(mut self, count: u32) -> Self {
+        self.num_frames_in_flight = count;
+        self
+    }
+
+    pub fn feature_level(mut self, level: D3D_FEATURE_LEVEL) -> Self {
+        self.feature_level = level;
+        self
+    }
+
+    pub fn debug_layer(mut self, enable: bool) -> Self {
+        self.debug_layer = enable;
+        self
+    }
+
+    pub fn gpu_validation(mut self, enable: bool) -> Self {
+        self.gpu_validation = enable;
+        self
+    }
+
+    pub fn rtv_heap_capacity(mut self, capacity: u32) -> Self {
+        self.rtv_heap_capacity = capacity;
+        self
+    }
+
+    pub fn dsv_heap_capacity(mut self, capacity: u32) -> Self {
+        self.dsv_heap_capacity = capacity;
+        self
+    }
+
+    pub fn cbv_srv_uav_heap_capacity(mut self, capacity: u32) -> Self {
+        self.cbv_srv_uav_heap_capacity = capacity;
+        self
+    }
+
+    pub fn num_bindless_descriptors(mut self, count: u32) -> Self {
+        self.num_bindless_descriptors = count;
+        self
+    }
+
+    pub fn num_transient_descriptors(mut self, count: u32) -> Self {
+        self.num_transient_descriptors = count;
+        self
+    }
+
+    pub fn upload_page_size(mut self, size: u32) -> Self {
+        self.upload_page_size = size;
+        self
+    }
+
+    pub fn capabilities_override(mut self, capabilities: DeviceCapabilities) -> Self {
+        self.capabilities_override = Some(capabilities);
+        self
+    }
+
+    pub fn log_hook(mut self, hook: LogHook) -> Self {
+        self.log_hook = Some(hook);
+        self
+    }
+
+    // Size of the bindless region `Context::new` creates on a device with `capabilities`.
+    pub fn num_bindless_descriptors_for(&self, capabilities: &DeviceCapabilities) -> u32 {
+        if capabilities.supports_bindless() {
+            self.num_bindless_descriptors
+        } else {
+            0
+        }
+    }
+
+    // Checked by `Context::new`.
+    pub fn validate(&self) -> Result<()> {
+        let invalid = |message: String| Err(Error::InvalidDesc(message));
+        if self.num_frames_in_flight < 1 || self.num_frames_in_flight > MAX_NUM_FRAMES_IN_FLIGHT {
+            return invalid(format!(
+                "num_frames_in_flight is {}, must be 1 to {}",
+                self.num_frames_in_flight, MAX_NUM_FRAMES_IN_FLIGHT
+            ));
+        }
+        if self.gpu_validation && !self.debug_layer {
+            return invalid("gpu_validation requires debug_layer".to_string());
+        }
+        if self.swapchain.buffer_count < 2 || self.swapchain.buffer_count > 16 {
+            return invalid(format!(
+                "swapchain.buffer_count is {}, must be 2 to 16",
+                self.swapchain.buffer_count
+            ));
+        }
+        for (name, value) in &[
+            ("rtv_heap_capacity", self.rtv_heap_capacity),
+            ("dsv_heap_capacity", self.dsv_heap_capacity),
+            ("cbv_srv_uav_heap_capacity", self.cbv_srv_uav_heap_capacity),
+            ("upload_page_size", self.upload_page_size),
+        ] {
+            if *value == 0 {
+                return invalid(format!("{} must not be 0", name));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for ContextDesc {
+    fn default() -> Self {
+        Self {
+            adapter: AdapterPreference::default(),
+            swapchain: SwapchainConfig::default(),
+            num_frames_in_flight: 2,
+            feature_level: D3D_FEATURE_LEVEL_11_1,
+            debug_layer: cfg!(debug_assertions),
+            gpu_validation: cfg!(debug_assertions),
+            rtv_heap_capacity: 1024,
+            dsv_heap_capacity: 1024,
+            cbv_srv_uav_heap_capacity: 16 * 1024,
+            num_bindless_descriptors: 32 * 1024,
+            num_transient_descriptors: 16 * 1024,
+            upload_page_size: 1024 * 1024,
+            capabilities_override: None,
+            log_hook: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use winapi::um::d3d12::D3D12_RESOURCE_BINDING_TIER_2;
+
+    #[test]
+    fn default_desc_is_valid() {
+        assert_eq!(ContextDesc::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn bindless_region_needs_binding_tier_2() {
+        let desc = ContextDesc::new().num_bindless_descriptors(1024);
+        let mut capabilities = DeviceCapabilities::minimal();
+        assert_eq!(desc.num_bindless_descriptors_for(&capabilities), 0);
+        capabilities.resource_binding_tier = D3D12_RESOURCE_BINDING_TIER_2;
+        assert_eq!(desc.num_bindless_descriptors_for(&capabilities), 1024);
+        assert_eq!(
+            desc.num_bindless_descriptors_for(&DeviceCapabilities::full()),
+            1024
+        );
+    }
+
+    #[test]
+    fn invalid_desc_is_an_error() {
+        let descs = [
+            ContextDesc::new().num_frames_in_flight(0),
+            ContextDesc::new().num_frames_in_flight(MAX_NUM_FRAMES_IN_FLIGHT + 1),
+            ContextDesc::new().debug_layer(false).gpu_validation(true),
+            ContextDesc::new().upload_page_size(0),
+            ContextDesc::new().rtv_heap_capacity(0),
+        ];
+        for desc in &descs {
+            match desc.validate() {
+                Err(Error::InvalidDesc(_)) => {}
+                result => panic!("{:?} for {:?}", result, desc),
+            }
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use winapi::shared::dxgiformat::*;
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum DepthFormat {
+    D32,
+    D24S8,
+    D32S8,
+}
+
+impl DepthFormat {
+    pub fn dsv_format(self) -> DXGI_FORMAT {
+        match self {
+            DepthFormat::D32 => DXGI_FORMAT_D32_FLOAT,
+            DepthFormat::D24S8 => DXGI_FORMAT_D24_UNORM_S8_UINT,
+            DepthFormat::D32S8 => DXGI_FORMAT_D32_FLOAT_S8X24_UINT,
+        }
+    }
+
+    // Depth buffers that are read in shaders are created with this format.
+    pub fn typeless_format(self) -> DXGI_FORMAT {
+        match self {
+            DepthFormat::D32 => DXGI_FORMAT_R32_TYPELESS,
+            DepthFormat::D24S8 => DXGI_FORMAT_R24G8_TYPELESS,
+            DepthFormat::D32S8 => DXGI_FORMAT_R32G8X24_TYPELESS,
+        }
+    }
+
+    pub fn depth_srv_format(self) -> DXGI_FORMAT {
+        match self {
+            DepthFormat::D32 => DXGI_FORMAT_R32_FLOAT,
+            DepthFormat::D24S8 => DXGI_FORMAT_R24_UNORM_X8_TYPELESS,
+            DepthFormat::D32S8 => DXGI_FORMAT_R32_FLOAT_X8X24_TYPELESS,
+        }
+    }
+
+    pub fn stencil_srv_format(self) -> Option<DXGI_FORMAT> {
+        match self {
+            DepthFormat::D32 => None,
+            DepthFormat::D24S8 => Some(DXGI_FORMAT_X24_TYPELESS_G8_UINT),
+            DepthFormat::D32S8 => Some(DXGI_FORMAT_X32_TYPELESS_G8X24_UINT),
+        }
+    }
+
+    #[inline]
+    pub fn has_stencil(self) -> bool {
+        self != DepthFormat::D32
+    }
+
+    // Accepts both the DSV format and the typeless format of the resource.
+    pub fn from_resource_format(format: DXGI_FORMAT) -> Option<Self> {
+        match format {
+            DXGI_FORMAT_D32_FLOAT | DXGI_FORMAT_R32_TYPELESS => Some(DepthFormat::D32),
+            DXGI_FORMAT_D24_UNORM_S8_UINT | DXGI_FORMAT_R24G8_TYPELESS => Some(DepthFormat::D24S8),
+            DXGI_FORMAT_D32_FLOAT_S8X24_UINT | DXGI_FORMAT_R32G8X24_TYPELESS => {
+                Some(DepthFormat::D32S8)
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,158 @@
+// First-fit allocator of descriptor ranges inside one heap. Free ranges are kept sorted by start
+// index and merged with their neighbours when released.
+pub struct DescriptorAllocator {
+    capacity: u32,
+    free_ranges: Vec<(u32, u32)>,
+    num_allocated: u32,
+}
+
+impl DescriptorAllocator {
+    // A zero capacity allocator never allocates, e.g. for a bindless region the device can't use.
+    pub fn new(capacity: u32) -> Self {
+        Self {
+            capacity,
+            free_ranges: if capacity > 0 {
+                vec![(0, capacity)]
+            } else {
+                Vec::new()
+            },
+            num_allocated: 0,
+        }
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    #[inline]
+    pub fn num_allocated(&self) -> u32 {
+        self.num_allocated
+    }
+
+    // Returns the index of the first descriptor or `None` when no free range is large enough.
+    pub fn allocate(&mut self, num: u32) -> Option<u32> {
+        assert!(num > 0);
+        let i = self
+            .free_ranges
+            .iter()
+            .position(|&(_, count)| count >= num)?;
+        let (start, count) = self.free_ranges[i];
+        if count == num {
+            self.free_ranges.remove(i);
+        } else {
+            self.free_ranges[i] = (start + num, count - num);
+        }
+        self.num_allocated += num;
+        Some(start)
+    }
+
+    pub fn free(&mut self, start: u32, num: u32) {
+        assert!(num > 0 && start + num <= self.capacity);
+        let i = self
+            .free_ranges
+            .iter()
+            .position(|&(free_start, _)| free_start > start)
+            .unwrap_or(self.free_ranges.len());
+        if i > 0 {
+            let (prev_start, prev_count) = self.free_ranges[i - 1];
+            assert!(
+                prev_start + prev_count <= start,
+                "descriptor range freed twice"
+            );
+        }
+        if i < self.free_ranges.len() {
+            assert!(
+                start + num <= self.free_ranges[i].0,
+                "descriptor range freed twice"
+            );
+        }
+
+        let merges_prev = i > 0 && {
+            let (prev_start, prev_count) = self.free_ranges[i - 1];
+            prev_start + prev_count == start
+        };
+        let merges_next = i < self.free_ranges.len() && start + num == self.free_ranges[i].0;
+        match (merges_prev, merges_next) {
+            (true, true) => {
+                let (_, next_count) = self.free_ranges.remove(i);
+                self.free_ranges[i - 1].1 += num + next_count;
+            }
+            (true, false) => self.free_ranges[i - 1].1 += num,
+            (false, true) => {
+                self.free_ranges[i].0 = start;
+                self.free_ranges[i].1 += num;
+            }
+            (false, false) => self.free_ranges.insert(i, (start, num)),
+        }
+        self.num_allocated -= num;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allocates_first_fit() {
+        let mut allocator = DescriptorAllocator::new(16);
+        assert_eq!(allocator.allocate(4), Some(0));
+        assert_eq!(allocator.allocate(4), Some(4));
+        assert_eq!(allocator.allocate(4), Some(8));
+        allocator.free(0, 4);
+        allocator.free(8, 4);
+        // Both holes fit; the lowest one is used.
+        assert_eq!(allocator.allocate(2), Some(0));
+        assert_eq!(allocator.allocate(4), Some(8));
+        assert_eq!(allocator.num_allocated(), 10);
+    }
+
+    #[test]
+    fn coalesces_adjacent_frees() {
+        let mut allocator = DescriptorAllocator::new(12);
+        assert_eq!(allocator.allocate(4), Some(0));
+        assert_eq!(allocator.allocate(4), Some(4));
+        assert_eq!(allocator.allocate(4), Some(8));
+        allocator.free(0, 4);
+        allocator.free(8, 4);
+        allocator.free(4, 4);
+        assert_eq!(allocator.free_ranges, vec![(0, 12)]);
+        assert_eq!(allocator.num_allocated(), 0);
+        assert_eq!(allocator.allocate(12), Some(0));
+    }
+
+    #[test]
+    fn zero_capacity_never_allocates() {
+        let mut allocator = DescriptorAllocator::new(0);
+        assert_eq!(allocator.allocate(1), None);
+    }
+
+    #[test]
+    fn exhaustion_returns_none() {
+        let mut allocator = DescriptorAllocator::new(8);
+        assert_eq!(allocator.allocate(8), Some(0));
+        assert_eq!(allocator.allocate(1), None);
+        allocator.free(2, 2);
+        // Free descriptors exist but not as a large enough range.
+        assert_eq!(allocator.allocate(3), None);
+        assert_eq!(allocator.allocate(2), Some(2));
+    }
+
+    #[test]
+    #[should_panic(expected = "descriptor range freed twice")]
+    fn double_free_is_detected() {
+        let mut allocator = DescriptorAllocator::new(8);
+        assert_eq!(allocator.allocate(4), Some(0));
+        allocator.free(0, 4);
+        allocator.free(0, 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "descriptor range freed twice")]
+    fn overlapping_free_is_detected() {
+        let mut allocator = DescriptorAllocator::new(8);
+        assert_eq!(allocator.allocate(8), Some(0));
+        allocator.free(4, 4);
+        allocator.free(2, 4);
+    }
+}
@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+use winapi::um::d3d12::*;
+
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+pub struct DescriptorCacheStats {
+    pub hits: u32,
+    pub misses: u32,
+}
+
+// Descriptor tables already copied into one frame's transient region, keyed by the source CPU
+// handles. Has to be cleared whenever the region is reset or a source descriptor is rewritten.
+pub struct DescriptorTableCache {
+    tables: HashMap<Vec<usize>, D3D12_GPU_DESCRIPTOR_HANDLE>,
+    stats: DescriptorCacheStats,
+}
+
+impl DescriptorTableCache {
+    pub fn new() -> Self {
+        Self {
+            tables: HashMap::new(),
+            stats: DescriptorCacheStats::default(),
+        }
+    }
+
+    // Stats are kept, they cover every lookup since the last `reset_stats`.
+    pub fn clear(&mut self) {
+        self.tables.clear();
+    }
+
+    pub fn lookup(
+        &mut self,
+        src_cpu_handles: &[D3D12_CPU_DESCRIPTOR_HANDLE],
+    ) -> Option<D3D12_GPU_DESCRIPTOR_HANDLE> {
+        let key: Vec<usize> = src_cpu_handles.iter().map(|h| h.ptr).collect();
+        match self.tables.get(&key) {
+            Some(gpu_base) => {
+                self.stats.hits += 1;
+                Some(*gpu_base)
+            }
+            None => {
+                self.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    pub fn insert(
+        &mut self,
+        src_cpu_handles: &[D3D12_CPU_DESCRIPTOR_HANDLE],
+        gpu_base: D3D12_GPU_DESCRIPTOR_HANDLE,
+    ) {
+        let key = src_cpu_handles.iter().map(|h| h.ptr).collect();
+        self.tables.insert(key, gpu_base);
+    }
+
+    #[inline]
+    pub fn stats(&self) -> DescriptorCacheStats {
+        self.stats
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats = DescriptorCacheStats::default();
+    }
+}
+
+// Splits `handles` into runs of adjacent descriptors for `CopyDescriptors`.
+pub fn contiguous_descriptor_ranges(
+    handles: &[D3D12_CPU_DESCRIPTOR_HANDLE],
+    descriptor_size: usize,
+) -> (Vec<D3D12_CPU_DESCRIPTOR_HANDLE>, Vec<u32>) {
+    let mut starts: Vec<D3D12_CPU_DESCRIPTOR_HANDLE> = Vec::new();
+    let mut sizes: Vec<u32> = Vec::new();
+    for handle in handles {
+        if let (Some(start), Some(size)) = (starts.last(), sizes.last_mut()) {
+            if start.ptr + *size as usize * descriptor_size == handle.ptr {
+                *size += 1;
+                continue;
+            }
+        }
+        starts.push(*handle);
+        sizes.push(1);
+    }
+    (starts, sizes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DESCRIPTOR_SIZE: usize = 32;
+
+    fn handles(ptrs: &[usize]) -> Vec<D3D12_CPU_DESCRIPTOR_HANDLE> {
+        ptrs.iter()
+            .map(|&ptr| D3D12_CPU_DESCRIPTOR_HANDLE { ptr })
+            .collect()
+    }
+
+    fn ranges(ptrs: &[usize]) -> (Vec<usize>, Vec<u32>) {
+        let (starts, sizes) = contiguous_descriptor_ranges(&handles(ptrs), DESCRIPTOR_SIZE);
+        (starts.iter().map(|h| h.ptr).collect(), sizes)
+    }
+
+    #[test]
+    fn contiguous_ranges() {
+        assert_eq!(ranges(&[]), (vec![], vec![]));
+        assert_eq!(ranges(&[64]), (vec![64], vec![1]));
+        assert_eq!(ranges(&[0, 32, 64, 96]), (vec![0], vec![4]));
+        assert_eq!(
+            ranges(&[96, 64, 32, 0]),
+            (vec![96, 64, 32, 0], vec![1, 1, 1, 1])
+        );
+        assert_eq!(
+            ranges(&[0, 32, 128, 160, 192, 64]),
+            (vec![0, 128, 64], vec![2, 3, 1])
+        );
+        // Repeated handles aren't adjacent to themselves.
+        assert_eq!(ranges(&[0, 0]), (vec![0, 0], vec![1, 1]));
+    }
+
+    #[test]
+    fn table_cache() {
+        let a = handles(&[0, 32, 64]);
+        let b = handles(&[64, 32, 0]);
+        let mut cache = DescriptorTableCache::new();
+        assert!(cache.lookup(&a).is_none());
+
+        cache.insert(&a, D3D12_GPU_DESCRIPTOR_HANDLE { ptr: 1024 });
+        assert_eq!(cache.lookup(&a).map(|h| h.ptr), Some(1024));
+        assert!(cache.lookup(&b).is_none());
+        assert!(cache.lookup(&a[..2]).is_none());
+
+        cache.insert(&a, D3D12_GPU_DESCRIPTOR_HANDLE { ptr: 2048 });
+        assert_eq!(cache.lookup(&a).map(|h| h.ptr), Some(2048));
+        assert_eq!(cache.stats(), DescriptorCacheStats { hits: 2, misses: 3 });
+
+        cache.clear();
+        assert!(cache.lookup(&a).is_none());
+        assert_eq!(cache.stats(), DescriptorCacheStats { hits: 2, misses: 4 });
+
+        cache.reset_stats();
+        assert_eq!(cache.stats(), DescriptorCacheStats::default());
+    }
+}
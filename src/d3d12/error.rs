@@ -0,0 +1,352 @@
+use crate::d3d12::Device;
+use std::error;
+use std::fmt;
+use std::io;
+use std::result;
+use winapi::shared::winerror::*;
+
+// Not in winapi 0.3; values from d3d12.h.
+const D3D12_ERROR_ADAPTER_NOT_FOUND: HRESULT = 0x887e0001u32 as HRESULT;
+const D3D12_ERROR_DRIVER_VERSION_MISMATCH: HRESULT = 0x887e0002u32 as HRESULT;
+
+// Evaluates an HRESULT-returning call to `Result<()>`. Pass the device as the second argument to
+// record why it was removed when that's the cause of the failure.
+macro_rules! hr {
+    ($call:expr) => {
+        match unsafe { $call } {
+            hr if hr < 0 => Err($crate::d3d12::Error::new(
+                hr,
+                stringify!($call),
+                file!(),
+                line!(),
+            )),
+            _ => Ok(()),
+        }
+    };
+    ($call:expr, $device:expr) => {{
+        let device: $crate::d3d12::Device = $device;
+        hr!($call).map_err(|err| err.with_device(device))
+    }};
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Error {
+    // A D3D12 or DXGI call failed.
+    Hresult(HresultError),
+    // A `ContextDesc` that `Context::new` can't be created with; the message names the field.
+    InvalidDesc(String),
+    // A compiled shader couldn't be read from `path`.
+    ShaderLoad { path: String, kind: io::ErrorKind },
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct HresultError {
+    pub hr: HRESULT,
+    // Source text of the failing call and where it is.
+    pub call: &'static str,
+    pub file: &'static str,
+    pub line: u32,
+    // `GetDeviceRemovedReason` at the time of the failure, if the device was removed.
+    pub device_removed_reason: Option<HRESULT>,
+}
+
+impl Error {
+    pub fn new(hr: HRESULT, call: &'static str, file: &'static str, line: u32) -> Self {
+        Error::Hresult(HresultError {
+            hr,
+            call,
+            file,
+            line,
+            device_removed_reason: None,
+        })
+    }
+
+    pub fn with_device(mut self, device: Device) -> Self {
+        if let Error::Hresult(ref mut error) = self {
+            let reason = unsafe { device.GetDeviceRemovedReason() };
+            if reason != S_OK {
+                error.device_removed_reason = Some(reason);
+            }
+        }
+        self
+    }
+
+    pub fn hr(&self) -> Option<HRESULT> {
+        match self {
+            Error::Hresult(error) => Some(error.hr),
+            _ => None,
+        }
+    }
+
+    // Name of the function or method that failed, e.g. `CreateCommittedResource`.
+    pub fn call_name(&self) -> Option<&'static str> {
+        match self {
+            Error::Hresult(error) => Some(call_name(error.call)),
+            _ => None,
+        }
+    }
+
+    pub fn is_device_removed(&self) -> bool {
+        match self {
+            Error::Hresult(error) => {
+                error.device_removed_reason.is_some()
+                    || error.hr == DXGI_ERROR_DEVICE_REMOVED
+                    || error.hr == DXGI_ERROR_DEVICE_HUNG
+                    || error.hr == DXGI_ERROR_DEVICE_RESET
+            }
+            _ => false,
+        }
+    }
+
+    pub fn is_out_of_memory(&self) -> bool {
+        self.hr() == Some(E_OUTOFMEMORY)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Hresult(error) => {
+                write!(
+                    f,
+                    "{} failed with {} ({}:{})",
+                    call_name(error.call),
+                    DisplayHresult(error.hr),
+                    error.file,
+                    error.line
+                )?;
+                if let Some(reason) = error.device_removed_reason {
+                    write!(f, "; device removed: {}", DisplayHresult(reason))?;
+                }
+                Ok(())
+            }
+            Error::InvalidDesc(message) => write!(f, "invalid context description: {}", message),
+            Error::ShaderLoad { path, kind } => write!(f, "can't read shader {}: {}", path, kind),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+struct DisplayHresult(HRESULT);
+
+impl fmt::Display for DisplayHresult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match hresult_name(self.0) {
+            Some(name) => write!(
+                f,
+                "{} (0x{:08x}): {}",
+                name,
+                self.0,
+                hresult_message(self.0)
+            ),
+            None => write!(f, "0x{:08x}", self.0),
+        }
+    }
+}
+
+pub fn hresult_name(hr: HRESULT) -> Option<&'static str> {
+    HRESULTS
+        .iter()
+        .find(|(code, _, _)| *code == hr)
+        .map(|(_, name, _)| *name)
+}
+
+pub fn hresult_message(hr: HRESULT) -> &'static str {
+    HRESULTS
+        .iter()
+        .find(|(code, _, _)| *code == hr)
+        .map_or("Unknown error", |(_, _, message)| *message)
+}
+
+const HRESULTS: [(HRESULT, &str, &str); 23] = [
+    (S_OK, "S_OK", "No error"),
+    (E_FAIL, "E_FAIL", "Unspecified failure"),
+    (
+        E_INVALIDARG,
+        "E_INVALIDARG",
+        "One or more arguments are invalid",
+    ),
+    (E_OUTOFMEMORY, "E_OUTOFMEMORY", "Out of memory"),
+    (E_NOTIMPL, "E_NOTIMPL", "Not implemented"),
+    (
+        E_NOINTERFACE,
+        "E_NOINTERFACE",
+        "No such interface supported",
+    ),
+    (E_POINTER, "E_POINTER", "Invalid pointer"),
+    (E_ABORT, "E_ABORT", "Operation aborted"),
+    (E_ACCESSDENIED, "E_ACCESSDENIED", "Access denied"),
+    (
+        DXGI_ERROR_INVALID_CALL,
+        "DXGI_ERROR_INVALID_CALL",
+        "The method call is invalid, check the debug layer output",
+    ),
+    (
+        DXGI_ERROR_NOT_FOUND,
+        "DXGI_ERROR_NOT_FOUND",
+        "The object was not found",
+    ),
+    (
+        DXGI_ERROR_MORE_DATA,
+        "DXGI_ERROR_MORE_DATA",
+        "The buffer is too small",
+    ),
+    (
+        DXGI_ERROR_UNSUPPORTED,
+        "DXGI_ERROR_UNSUPPORTED",
+        "The requested functionality is not supported by the device or driver",
+    ),
+    (
+        DXGI_ERROR_DEVICE_REMOVED,
+        "DXGI_ERROR_DEVICE_REMOVED",
+        "The GPU was physically removed, the driver was upgraded or the device was lost",
+    ),
+    (
+        DXGI_ERROR_DEVICE_HUNG,
+        "DXGI_ERROR_DEVICE_HUNG",
+        "The device failed because of badly formed commands",
+    ),
+    (
+        DXGI_ERROR_DEVICE_RESET,
+        "DXGI_ERROR_DEVICE_RESET",
+        "The device failed because of a badly formed command and was reset",
+    ),
+    (
+        DXGI_ERROR_WAS_STILL_DRAWING,
+        "DXGI_ERROR_WAS_STILL_DRAWING",
+        "The GPU was busy",
+    ),
+    (
+        DXGI_ERROR_DRIVER_INTERNAL_ERROR,
+        "DXGI_ERROR_DRIVER_INTERNAL_ERROR",
+        "The driver encountered a problem",
+    ),
+    (
+        DXGI_ERROR_NOT_CURRENTLY_AVAILABLE,
+        "DXGI_ERROR_NOT_CURRENTLY_AVAILABLE",
+        "The resource or request is not currently available",
+    ),
+    (
+        DXGI_ERROR_ACCESS_LOST,
+        "DXGI_ERROR_ACCESS_LOST",
+        "The desktop duplication interface is invalid",
+    ),
+    (
+        DXGI_ERROR_SDK_COMPONENT_MISSING,
+        "DXGI_ERROR_SDK_COMPONENT_MISSING",
+        "An SDK component is missing, e.g. the debug layer is not installed",
+    ),
+    (
+        D3D12_ERROR_ADAPTER_NOT_FOUND,
+        "D3D12_ERROR_ADAPTER_NOT_FOUND",
+        "The cached pipeline was created on a different adapter",
+    ),
+    (
+        D3D12_ERROR_DRIVER_VERSION_MISMATCH,
+        "D3D12_ERROR_DRIVER_VERSION_MISMATCH",
+        "The cached pipeline was created with a different driver version",
+    ),
+];
+
+// The identifier before the argument list of the outermost call, so `self.device.CreateHeap(..)`
+// gives `CreateHeap`.
+fn call_name(call: &str) -> &str {
+    let bytes = call.as_bytes();
+    let mut depth = 0;
+    let mut open = None;
+    for (i, &c) in bytes.iter().enumerate().rev() {
+        match c {
+            b')' => depth += 1,
+            b'(' => {
+                depth -= 1;
+                if depth == 0 {
+                    open = Some(i);
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+    let callee = match open {
+        Some(open) => call[..open].trim_end(),
+        None => return call,
+    };
+    let start = callee
+        .rfind(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .map_or(0, |i| i + 1);
+    &callee[start..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    unsafe fn returns(hr: HRESULT) -> HRESULT {
+        hr
+    }
+
+    #[test]
+    fn call_names() {
+        assert_eq!(
+            call_name("self.device.CreateHeap(&desc, f(x), ptr::null_mut())"),
+            "CreateHeap"
+        );
+        assert_eq!(
+            call_name("D3D12CreateDevice(adapter, level, &uuid, &mut rdevice)"),
+            "D3D12CreateDevice"
+        );
+        assert_eq!(call_name("cmdlist.Close ()"), "Close");
+        assert_eq!(call_name("UploadRing::allocate"), "UploadRing::allocate");
+    }
+
+    #[test]
+    fn hresult_names() {
+        assert_eq!(hresult_name(E_OUTOFMEMORY), Some("E_OUTOFMEMORY"));
+        assert_eq!(
+            hresult_name(D3D12_ERROR_ADAPTER_NOT_FOUND),
+            Some("D3D12_ERROR_ADAPTER_NOT_FOUND")
+        );
+        assert_eq!(hresult_name(0x8badf00du32 as HRESULT), None);
+        assert_eq!(
+            hresult_message(E_INVALIDARG),
+            "One or more arguments are invalid"
+        );
+        assert_eq!(hresult_message(0x8badf00du32 as HRESULT), "Unknown error");
+    }
+
+    #[test]
+    fn display() {
+        let line = line!() + 1;
+        let error = hr!(returns(E_OUTOFMEMORY)).unwrap_err();
+        assert_eq!(error.hr(), Some(E_OUTOFMEMORY));
+        assert_eq!(error.call_name(), Some("returns"));
+        assert!(error.is_out_of_memory() && !error.is_device_removed());
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "returns failed with E_OUTOFMEMORY (0x8007000e): Out of memory ({}:{})",
+                file!(),
+                line
+            )
+        );
+
+        let mut error = Error::new(0x8badf00du32 as HRESULT, "Present(0, 0)", "main.rs", 7);
+        if let Error::Hresult(ref mut error) = error {
+            error.device_removed_reason = Some(DXGI_ERROR_DEVICE_HUNG);
+        }
+        assert!(error.is_device_removed());
+        assert_eq!(
+            error.to_string(),
+            "Present failed with 0x8badf00d (main.rs:7); device removed: DXGI_ERROR_DEVICE_HUNG \
+             (0x887a0006): The device failed because of badly formed commands"
+        );
+
+        assert!(hr!(returns(S_OK)).is_ok());
+        let error = Error::InvalidDesc("num_frames_in_flight".to_string());
+        assert_eq!(error.hr(), None);
+        assert_eq!(error.call_name(), None);
+    }
+}
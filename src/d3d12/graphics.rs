@@ -1,35 +1,59 @@
 use crate::d3d12::*;
 use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
+use std::collections::VecDeque;
 use std::ffi::CStr;
 use std::ffi::CString;
 use std::fs;
 use std::hash::Hasher;
+use std::io;
+use std::iter;
 use std::mem;
 use std::ops::Deref;
+use std::panic::Location;
 use std::ptr;
 use std::slice;
 use winapi::ctypes::c_void;
-use winapi::shared::dxgi::{IDXGISwapChain, DXGI_SWAP_CHAIN_DESC, DXGI_SWAP_EFFECT_FLIP_DISCARD};
+use winapi::shared::dxgi::{
+    IDXGISwapChain, DXGI_PRESENT_ALLOW_TEARING, DXGI_SWAP_CHAIN_DESC,
+    DXGI_SWAP_CHAIN_FLAG_ALLOW_TEARING, DXGI_SWAP_EFFECT_FLIP_DISCARD,
+};
 use winapi::shared::dxgi1_3::{CreateDXGIFactory2, DXGI_CREATE_FACTORY_DEBUG};
-use winapi::shared::dxgi1_4::{IDXGIFactory4, IDXGISwapChain3};
+use winapi::shared::dxgi1_4::{
+    IDXGIFactory4, IDXGISwapChain3, DXGI_SWAP_CHAIN_COLOR_SPACE_SUPPORT_FLAG_PRESENT,
+};
+use winapi::shared::dxgi1_5::{IDXGIFactory5, DXGI_FEATURE_PRESENT_ALLOW_TEARING};
 use winapi::shared::dxgiformat::*;
-use winapi::shared::dxgitype::{DXGI_SAMPLE_DESC, DXGI_USAGE_RENDER_TARGET_OUTPUT};
+use winapi::shared::dxgitype::{
+    DXGI_COLOR_SPACE_RGB_FULL_G22_NONE_P709, DXGI_COLOR_SPACE_TYPE, DXGI_SAMPLE_DESC,
+    DXGI_USAGE_RENDER_TARGET_OUTPUT,
+};
+use winapi::shared::minwindef::BOOL;
 use winapi::shared::windef::{HWND, RECT};
+use winapi::shared::winerror::E_OUTOFMEMORY;
 use winapi::um::d3d12::*;
-#[cfg(debug_assertions)]
 use winapi::um::d3d12sdklayers::{ID3D12Debug, ID3D12Debug1};
-use winapi::um::d3dcommon::D3D_FEATURE_LEVEL_11_1;
+use winapi::um::d3dcommon::ID3DBlob;
+use winapi::um::d3dcompiler::{
+    D3DCompile, D3DCOMPILE_ENABLE_STRICTNESS, D3DCOMPILE_OPTIMIZATION_LEVEL3,
+};
 use winapi::um::handleapi::CloseHandle;
 use winapi::um::synchapi::{CreateEventExA, WaitForSingleObject};
 use winapi::um::unknwnbase::IUnknown;
 use winapi::um::winbase::INFINITE;
 use winapi::um::winnt::{EVENT_ALL_ACCESS, HANDLE};
-use winapi::um::winuser::GetClientRect;
+use winapi::um::winuser::{GetClientRect, IsIconic};
 use winapi::Interface;
 
+// Largest shader-visible sampler heap; the transient samplers of all frames in flight share
+// what is left after the persistent ones.
+const MAX_NUM_GPU_SAMPLERS: u32 = 2048;
+const NUM_SAMPLERS: u32 = 1024;
+pub(crate) const MAX_NUM_FRAMES_IN_FLIGHT: u32 = 4;
+const STREAMING_STAGING_CAPACITY: u32 = 64 * 1024 * 1024;
 const MAX_NUM_RESOURCES: usize = 256;
 const MAX_NUM_PIPELINES: usize = 256;
+const GENERATE_MIPS_HLSL: &str = include_str!("../shaders/generate_mips.hlsl");
 const INVALID_PIPELINE: PipelineHandle = PipelineHandle {
     index: 0,
     generation: 0,
@@ -42,40 +66,156 @@ pub struct Context {
     pub resolution: [u32; 2],
     pub window: HWND,
     cmdqueue: CommandQueue,
-    cmdallocs: [WeakPtr<ID3D12CommandAllocator>; 2],
+    compute_cmdqueue: CommandQueue,
+    copy_cmdqueue: CommandQueue,
+    queue_fences: [WeakPtr<ID3D12Fence>; 3],
+    queue_fence_values: [u64; 3],
+    uploader: Option<StreamingUploader>,
+    readbacks: HashMap<u64, Readback>,
+    next_readback_id: u64,
+    cmdallocs: Vec<WeakPtr<ID3D12CommandAllocator>>,
     swapchain: WeakPtr<IDXGISwapChain3>,
-    rtv_heap: DescriptorHeap,
-    dsv_heap: DescriptorHeap,
-    cpu_cbv_srv_uav_heap: DescriptorHeap,
-    gpu_cbv_srv_uav_heaps: [DescriptorHeap; 2],
-    gpu_upload_memory_heaps: [GpuMemoryHeap; 2],
-    swap_buffers: [ResourceHandle; 4],
+    rtv_heap: CpuDescriptorHeap,
+    dsv_heap: CpuDescriptorHeap,
+    cpu_cbv_srv_uav_heap: CpuDescriptorHeap,
+    // The shader-visible heap is split into a persistent bindless region followed by one transient
+    // region per frame; the regions below share its `heap`.
+    gpu_cbv_srv_uav_heap: DescriptorHeap,
+    bindless_heap: DescriptorHeap,
+    bindless_allocator: DescriptorAllocator,
+    gpu_cbv_srv_uav_heaps: Vec<DescriptorHeap>,
+    descriptor_table_caches: Vec<DescriptorTableCache>,
+    // Sampler `i` lives at index `i` of both `cpu_sampler_heap` (the copy source for tables) and
+    // `sampler_heap`, the persistent region of the shader-visible sampler heap.
+    cpu_sampler_heap: DescriptorHeap,
+    gpu_sampler_heap: DescriptorHeap,
+    sampler_heap: DescriptorHeap,
+    gpu_sampler_heaps: Vec<DescriptorHeap>,
+    sampler_cache: SamplerCache,
+    sampler_table_caches: Vec<DescriptorTableCache>,
+    upload_ring: UploadRing,
+    swapchain_config: SwapchainConfig,
+    tearing_supported: bool,
+    debug_layer: bool,
+    adapter_info: AdapterInfo,
+    capabilities: DeviceCapabilities,
+    swap_buffers: Vec<ResourceHandle>,
+    swap_buffer_rtvs: DescriptorAllocation,
     frame_fence: WeakPtr<ID3D12Fence>,
     frame_fence_event: HANDLE,
     num_frames: u64,
+    num_frames_in_flight: u32,
     back_buffer_index: u32,
     resource_pool: ResourcePool,
     pipeline_pool: PipelinePool,
     current_pipeline: PipelineHandle,
+    mipgen_pipeline: PipelineHandle,
+    deferred_destroys: Vec<Vec<ResourceHandle>>,
+    window_depth_buffers: Vec<ResourceHandle>,
+    barrier_queue: BarrierQueue,
+    state_validation: bool,
+    state_validation_errors: Vec<String>,
+    log_hook: Option<LogHook>,
+    bound_graphics_resources: Vec<BoundResource>,
+    bound_compute_resources: Vec<BoundResource>,
+    bound_render_targets: Vec<BoundResource>,
 }
 
-#[derive(Copy, Clone, PartialEq)]
+// Records commands independently of the Context (and of other CommandContexts), so several of
+// them can be recorded in parallel, one per thread. State transitions whose starting state is
+// unknown while recording are resolved by `Context::execute_command_contexts`.
+pub struct CommandContext {
+    pub cmdlist: GraphicsCommandList,
+    queue: QueueType,
+    fixup_cmdlist: GraphicsCommandList,
+    cmdallocs: Vec<WeakPtr<ID3D12CommandAllocator>>,
+    allocator_frame: Option<u64>,
+    local_states: LocalStates,
+    barrier_queue: BarrierQueue,
+    current_pipeline: PipelineHandle,
+}
+
+// What threads recording CommandContexts need from the Context: the device, the current frame,
+// the shader-visible heaps and resource and pipeline lookups. Returned by `Context::recording_view`;
+// the borrow keeps the Context from changing while recording threads use it.
+#[derive(Copy, Clone)]
+pub struct RecordingView<'a> {
+    device: Device,
+    frame_index: usize,
+    num_frames: u64,
+    shader_visible_heaps: [*mut ID3D12DescriptorHeap; 2],
+    resource_pool: &'a ResourcePool,
+    pipeline_pool: &'a PipelinePool,
+}
+
+// D3D12 objects are free-threaded. A RecordingView only reads, and each CommandContext is used by
+// one thread at a time.
+unsafe impl Send for RecordingView<'_> {}
+unsafe impl Sync for RecordingView<'_> {}
+unsafe impl Send for CommandContext {}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
 pub struct ResourceHandle {
     index: u16,
     generation: u16,
 }
 
+impl ResourceHandle {
+    // Handles for unit tests of the trackers, which never look the resource up.
+    #[cfg(test)]
+    pub fn for_test(index: u16) -> Self {
+        Self {
+            index,
+            generation: 1,
+        }
+    }
+}
+
+// A range of non shader-visible descriptors. Released with `free_cpu_descriptors`, or by
+// `destroy_resource` when allocated with `allocate_resource_descriptors`.
+#[derive(Copy, Clone)]
+pub struct DescriptorAllocation {
+    pub cpu_handle: D3D12_CPU_DESCRIPTOR_HANDLE,
+    pub heap_type: D3D12_DESCRIPTOR_HEAP_TYPE,
+    pub num: u32,
+    page: u32,
+    index: u32,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub struct ReadbackHandle {
+    id: u64,
+}
+
 #[derive(Copy, Clone, PartialEq)]
 pub struct PipelineHandle {
     index: u16,
     generation: u16,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 struct ResourceState {
     ptr: WeakPtr<ID3D12Resource>,
-    state: D3D12_RESOURCE_STATES,
-    format: DXGI_FORMAT,
+    states: SubresourceStates,
+    // Split transitions begun and not yet ended, by the subresource they were begun for.
+    pending_splits: Vec<(u32, Vec<SubresourceTransition>)>,
+    desc: D3D12_RESOURCE_DESC,
+    // Buffers and simultaneous-access textures in GPU heaps go back to COMMON after every
+    // ExecuteCommandLists that uses them.
+    decays_to_common: bool,
+    name: String,
+    descriptors: Vec<DescriptorAllocation>,
+    bindless_indices: Vec<u32>,
+    // Views created by `create_view`; their descriptors are also in `descriptors`.
+    views: HashMap<ViewDesc, D3D12_CPU_DESCRIPTOR_HANDLE>,
+}
+
+#[derive(Copy, Clone)]
+struct BoundResource {
+    slot: u32,
+    resource: ResourceHandle,
+    usage: ResourceUsage,
+    location: &'static Location<'static>,
 }
 
 #[derive(Copy, Clone)]
@@ -104,6 +244,14 @@ struct DescriptorHeap {
     descriptor_size: u32,
 }
 
+// Non shader-visible descriptors of one type. Grows by adding heaps (pages) of equal capacity.
+struct CpuDescriptorHeap {
+    heap_type: D3D12_DESCRIPTOR_HEAP_TYPE,
+    page_capacity: u32,
+    descriptor_size: u32,
+    pages: Vec<(DescriptorHeap, DescriptorAllocator)>,
+}
+
 struct GpuMemoryHeap {
     heap: WeakPtr<ID3D12Resource>,
     cpu_base: *mut u8,
@@ -112,6 +260,38 @@ struct GpuMemoryHeap {
     capacity: u32,
 }
 
+// CPU-written memory for the frame being recorded. Pages are filled linearly and a new one is added
+// when the current one is full. Pages are retired at the end of a frame with the frame fence value
+// and reused once the GPU has passed it; oversized pages are released instead.
+struct UploadRing {
+    page_size: u32,
+    current: Option<GpuMemoryHeap>,
+    used: Vec<GpuMemoryHeap>,
+    retired: VecDeque<(GpuMemoryHeap, u64)>,
+    free: Vec<GpuMemoryHeap>,
+}
+
+// Destination of a single buffer or texture subresource readback. Texture rows are `row_pitch`
+// bytes apart in `buffer`; buffers are read back as a single row.
+struct Readback {
+    buffer: GpuMemoryHeap,
+    // Set once the command list with the copy has been submitted and signalled.
+    sync_point: Option<SyncPoint>,
+    row_size: usize,
+    row_pitch: usize,
+    num_rows: usize,
+}
+
+// Copies data to DEFAULT heap resources on the copy queue through a staging ring. Command
+// allocators are recycled once the copy queue fence passes the value they were submitted with.
+struct StreamingUploader {
+    staging: GpuMemoryHeap,
+    ring: StagingRing,
+    cmdlist: GraphicsCommandList,
+    cmdallocs: VecDeque<(WeakPtr<ID3D12CommandAllocator>, u64)>,
+    is_recording: bool,
+}
+
 pub struct ResourceBarrier;
 pub struct RasterizerDesc;
 pub struct BlendDesc;
@@ -119,12 +299,27 @@ pub struct DepthStencilDesc;
 pub struct ResourceDesc;
 pub struct HeapProperties;
 pub struct InputElementDesc;
+pub struct TextureCopyLocation;
 
 impl ResourceBarrier {
     pub fn transition(
         resource: Resource,
         state_before: D3D12_RESOURCE_STATES,
         state_after: D3D12_RESOURCE_STATES,
+    ) -> D3D12_RESOURCE_BARRIER {
+        Self::transition_subresource(
+            resource,
+            state_before,
+            state_after,
+            D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES,
+        )
+    }
+
+    pub fn transition_subresource(
+        resource: Resource,
+        state_before: D3D12_RESOURCE_STATES,
+        state_after: D3D12_RESOURCE_STATES,
+        subresource: u32,
     ) -> D3D12_RESOURCE_BARRIER {
         let mut barrier: D3D12_RESOURCE_BARRIER = unsafe { mem::zeroed() };
         barrier.Type = D3D12_RESOURCE_BARRIER_TYPE_TRANSITION;
@@ -133,7 +328,26 @@ impl ResourceBarrier {
         transition.pResource = resource.as_raw();
         transition.StateBefore = state_before;
         transition.StateAfter = state_after;
-        transition.Subresource = D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES;
+        transition.Subresource = subresource;
+        barrier
+    }
+
+    // Pass a null `resource` to synchronize all UAV accesses.
+    pub fn uav(resource: Resource) -> D3D12_RESOURCE_BARRIER {
+        let mut barrier: D3D12_RESOURCE_BARRIER = unsafe { mem::zeroed() };
+        barrier.Type = D3D12_RESOURCE_BARRIER_TYPE_UAV;
+        barrier.Flags = D3D12_RESOURCE_BARRIER_FLAG_NONE;
+        unsafe { barrier.u.UAV_mut().pResource = resource.as_raw() };
+        barrier
+    }
+
+    pub fn aliasing(resource_before: Resource, resource_after: Resource) -> D3D12_RESOURCE_BARRIER {
+        let mut barrier: D3D12_RESOURCE_BARRIER = unsafe { mem::zeroed() };
+        barrier.Type = D3D12_RESOURCE_BARRIER_TYPE_ALIASING;
+        barrier.Flags = D3D12_RESOURCE_BARRIER_FLAG_NONE;
+        let mut aliasing = unsafe { barrier.u.Aliasing_mut() };
+        aliasing.pResourceBefore = resource_before.as_raw();
+        aliasing.pResourceAfter = resource_after.as_raw();
         barrier
     }
 }
@@ -205,6 +419,24 @@ impl ResourceDesc {
             Flags: D3D12_RESOURCE_FLAG_NONE,
         }
     }
+
+    pub fn tex2d(format: DXGI_FORMAT, width: u64, height: u32) -> D3D12_RESOURCE_DESC {
+        D3D12_RESOURCE_DESC {
+            Dimension: D3D12_RESOURCE_DIMENSION_TEXTURE2D,
+            Alignment: 0,
+            Width: width,
+            Height: height,
+            DepthOrArraySize: 1,
+            MipLevels: 1,
+            Format: format,
+            SampleDesc: DXGI_SAMPLE_DESC {
+                Count: 1,
+                Quality: 0,
+            },
+            Layout: D3D12_TEXTURE_LAYOUT_UNKNOWN,
+            Flags: D3D12_RESOURCE_FLAG_NONE,
+        }
+    }
 }
 
 impl DepthStencilDesc {
@@ -254,15 +486,56 @@ impl InputElementDesc {
     }
 }
 
+impl TextureCopyLocation {
+    pub fn subresource(resource: Resource, subresource: u32) -> D3D12_TEXTURE_COPY_LOCATION {
+        let mut location = D3D12_TEXTURE_COPY_LOCATION {
+            pResource: resource.as_raw(),
+            Type: D3D12_TEXTURE_COPY_TYPE_SUBRESOURCE_INDEX,
+            u: unsafe { mem::zeroed() },
+        };
+        unsafe { *location.u.SubresourceIndex_mut() = subresource };
+        location
+    }
+
+    pub fn placed_footprint(
+        resource: Resource,
+        footprint: &D3D12_PLACED_SUBRESOURCE_FOOTPRINT,
+    ) -> D3D12_TEXTURE_COPY_LOCATION {
+        let mut location = D3D12_TEXTURE_COPY_LOCATION {
+            pResource: resource.as_raw(),
+            Type: D3D12_TEXTURE_COPY_TYPE_PLACED_FOOTPRINT,
+            u: unsafe { mem::zeroed() },
+        };
+        unsafe { *location.u.PlacedFootprint_mut() = *footprint };
+        location
+    }
+}
+
 impl ResourceState {
     #[inline]
     fn new() -> Self {
         Self {
             ptr: WeakPtr::new(),
-            state: D3D12_RESOURCE_STATE_COMMON,
-            format: DXGI_FORMAT_UNKNOWN,
+            states: SubresourceStates::Uniform(D3D12_RESOURCE_STATE_COMMON),
+            pending_splits: Vec::new(),
+            desc: unsafe { mem::zeroed() },
+            decays_to_common: false,
+            name: String::new(),
+            descriptors: Vec::new(),
+            bindless_indices: Vec::new(),
+            views: HashMap::new(),
         }
     }
+
+    // Whether a split transition in flight covers `subresource` (or, for
+    // `D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES`, any subresource).
+    fn has_pending_split(&self, subresource: u32) -> bool {
+        self.pending_splits.iter().any(|(begun, _)| {
+            *begun == subresource
+                || *begun == D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES
+                || subresource == D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES
+        })
+    }
 }
 
 impl PipelineState {
@@ -275,7 +548,44 @@ impl PipelineState {
     }
 }
 
+// Upload and readback heap resources stay in the one state they're allowed to be in.
+fn decays_to_common(resource: WeakPtr<ID3D12Resource>, desc: &D3D12_RESOURCE_DESC) -> bool {
+    if desc.Dimension != D3D12_RESOURCE_DIMENSION_BUFFER
+        && (desc.Flags & D3D12_RESOURCE_FLAG_ALLOW_SIMULTANEOUS_ACCESS) == 0
+    {
+        return false;
+    }
+    let mut properties: D3D12_HEAP_PROPERTIES = unsafe { mem::zeroed() };
+    let hr = unsafe { resource.GetHeapProperties(&mut properties, ptr::null_mut()) };
+    // Swap chain buffers and reserved resources have no heap properties.
+    hr < 0
+        || (properties.Type != D3D12_HEAP_TYPE_UPLOAD
+            && properties.Type != D3D12_HEAP_TYPE_READBACK)
+}
+
 impl ResourcePool {
+    // Applies state decay after the graphics command list is submitted. Only that list records
+    // into the global states directly, so every decaying resource it used is covered.
+    fn decay_states(&mut self) {
+        for resource in self.resources.iter_mut().skip(1) {
+            if !resource.ptr.is_null()
+                && resource.decays_to_common
+                && resource.pending_splits.is_empty()
+            {
+                resource.states = SubresourceStates::Uniform(D3D12_RESOURCE_STATE_COMMON);
+            }
+        }
+    }
+
+    #[inline]
+    fn get(&self, handle: ResourceHandle) -> &ResourceState {
+        let index = handle.index as usize;
+        assert!(index > 0 && index <= MAX_NUM_RESOURCES);
+        assert!(handle.generation == self.generations[index]);
+        assert!(!self.resources[index].ptr.is_null());
+        &self.resources[index]
+    }
+
     fn new() -> Self {
         Self {
             resources: vec![ResourceState::new(); MAX_NUM_RESOURCES + 1],
@@ -294,7 +604,7 @@ impl ResourcePool {
         &mut self,
         resource: WeakPtr<ID3D12Resource>,
         initial_state: D3D12_RESOURCE_STATES,
-        format: DXGI_FORMAT,
+        desc: &D3D12_RESOURCE_DESC,
     ) -> ResourceHandle {
         let mut slot_idx = 0;
         for i in 1..self.resources.len() {
@@ -306,8 +616,12 @@ impl ResourcePool {
         assert!(slot_idx > 0 && slot_idx <= MAX_NUM_RESOURCES);
 
         self.resources[slot_idx].ptr = resource;
-        self.resources[slot_idx].state = initial_state;
-        self.resources[slot_idx].format = format;
+        self.resources[slot_idx].states = SubresourceStates::Uniform(initial_state);
+        self.resources[slot_idx].pending_splits.clear();
+        self.resources[slot_idx].name.clear();
+        self.resources[slot_idx].desc = *desc;
+        self.resources[slot_idx].desc.MipLevels = mip_levels(desc);
+        self.resources[slot_idx].decays_to_common = decays_to_common(resource, desc);
 
         ResourceHandle {
             index: slot_idx as u16,
@@ -317,9 +631,34 @@ impl ResourcePool {
             },
         }
     }
+
+    fn find(&self, resource: Resource) -> Option<ResourceHandle> {
+        if resource.is_null() {
+            return None;
+        }
+        for i in 1..self.resources.len() {
+            if self.resources[i].ptr.as_raw() == resource.as_raw() {
+                return Some(ResourceHandle {
+                    index: i as u16,
+                    generation: self.generations[i],
+                });
+            }
+        }
+        None
+    }
 }
 
 impl PipelinePool {
+    #[inline]
+    fn get(&self, handle: PipelineHandle) -> &PipelineState {
+        let index = handle.index as usize;
+        assert!(index > 0 && index <= MAX_NUM_PIPELINES);
+        assert!(handle.generation == self.generations[index]);
+        assert!(!self.pipelines[index].pso.is_null());
+        assert!(!self.pipelines[index].rsignature.is_null());
+        &self.pipelines[index]
+    }
+
     fn new() -> Self {
         Self {
             pipelines: vec![PipelineState::new(); MAX_NUM_PIPELINES + 1],
@@ -365,258 +704,285 @@ impl PipelinePool {
 }
 
 impl Context {
-    pub fn new(window: HWND) -> Self {
-        // Create DXGI factory.
-        let mut factory = {
-            let mut rfactory: *mut IDXGIFactory4 = ptr::null_mut();
-            vhr!(CreateDXGIFactory2(
-                DXGI_CREATE_FACTORY_DEBUG,
-                &IDXGIFactory4::uuidof(),
-                &mut rfactory as *mut *mut _ as *mut *mut c_void,
-            ));
-            WeakPtr::from_raw(rfactory)
-        };
+    pub fn new(window: HWND, desc: &ContextDesc) -> Result<Self> {
+        desc.validate()?;
+        let num_frames_in_flight = desc.num_frames_in_flight;
+        let num_frames_in_flight_usize = num_frames_in_flight as usize;
+        let mut swapchain_config = desc.swapchain;
+
+        // Releases everything created below if we return early.
+        let mut guard = ReleaseGuard::new();
+        let mut factory = guard.add(create_dxgi_factory(desc.debug_layer)?);
+        let tearing_supported = is_tearing_supported(factory);
 
         // Debug layer.
-        #[cfg(debug_assertions)]
-        unsafe {
-            let mut rdbg: *mut ID3D12Debug = ptr::null_mut();
-            D3D12GetDebugInterface(
-                &ID3D12Debug::uuidof(),
-                &mut rdbg as *mut *mut _ as *mut *mut c_void,
-            );
-            if !rdbg.is_null() {
-                let mut dbg = WeakPtr::from_raw(rdbg);
-                dbg.EnableDebugLayer();
-
-                let mut rdbg1: *mut ID3D12Debug1 = ptr::null_mut();
-                dbg.QueryInterface(
-                    &ID3D12Debug1::uuidof(),
-                    &mut rdbg1 as *mut *mut _ as *mut *mut c_void,
-                );
-                dbg.release();
-                if !rdbg1.is_null() {
-                    let mut dbg1 = WeakPtr::from_raw(rdbg1);
-                    dbg1.SetEnableGPUBasedValidation(1);
-                    dbg1.release();
-                }
-            }
+        if desc.debug_layer {
+            enable_debug_layer(desc.gpu_validation);
         }
 
-        // Create Direct3D12 device.
-        let device = {
-            let mut rdevice: *mut ID3D12Device2 = ptr::null_mut();
-            vhr!(D3D12CreateDevice(
-                ptr::null_mut(),
-                D3D_FEATURE_LEVEL_11_1,
-                &ID3D12Device2::uuidof(),
-                &mut rdevice as *mut *mut _ as *mut *mut c_void,
-            ));
-            WeakPtr::from_raw(rdevice)
-        };
+        let (mut adapter, adapter_info) = select_adapter(factory, &desc.adapter, desc.log_hook)?;
+        log(
+            desc.log_hook,
+            LogLevel::Info,
+            &format!("using adapter {}: {}", adapter_info.index, adapter_info),
+        );
 
-        // Create command queue.
-        let cmdqueue = {
+        // Create Direct3D12 device.
+        let mut rdevice: *mut ID3D12Device2 = ptr::null_mut();
+        let created = hr!(D3D12CreateDevice(
+            adapter.as_raw() as *mut IUnknown,
+            desc.feature_level,
+            &ID3D12Device2::uuidof(),
+            &mut rdevice as *mut *mut _ as *mut *mut c_void,
+        ));
+        adapter.release();
+        created?;
+        let device = guard.add(WeakPtr::from_raw(rdevice));
+        let capabilities = desc
+            .capabilities_override
+            .unwrap_or_else(|| DeviceCapabilities::query(device));
+
+        // Create command queues.
+        let create_cmdqueue = |queue: QueueType| {
             let mut rcmdqueue: *mut ID3D12CommandQueue = ptr::null_mut();
-            vhr!(device.CreateCommandQueue(
-                &D3D12_COMMAND_QUEUE_DESC {
-                    Flags: D3D12_COMMAND_QUEUE_FLAG_NONE,
-                    Priority: D3D12_COMMAND_QUEUE_PRIORITY_NORMAL as i32,
-                    Type: D3D12_COMMAND_LIST_TYPE_DIRECT,
-                    NodeMask: 0,
-                },
-                &ID3D12CommandQueue::uuidof(),
-                &mut rcmdqueue as *mut *mut _ as *mut *mut c_void,
-            ));
-            WeakPtr::from_raw(rcmdqueue)
-        };
-
-        // Create swap chain.
-        let swapchain = {
-            let mut swapchain1 = {
-                let mut desc: DXGI_SWAP_CHAIN_DESC = unsafe { mem::zeroed() };
-                desc.BufferCount = 4;
-                desc.BufferDesc.Format = DXGI_FORMAT_R8G8B8A8_UNORM;
-                desc.BufferUsage = DXGI_USAGE_RENDER_TARGET_OUTPUT;
-                desc.OutputWindow = window;
-                desc.SampleDesc.Count = 1;
-                desc.SwapEffect = DXGI_SWAP_EFFECT_FLIP_DISCARD;
-                desc.Windowed = 1;
-
-                let mut rswapchain1: *mut IDXGISwapChain = ptr::null_mut();
-                vhr!(factory.CreateSwapChain(
-                    cmdqueue.as_raw() as *mut _ as *mut IUnknown,
-                    &mut desc,
-                    &mut rswapchain1,
-                ));
-                factory.release();
-                WeakPtr::from_raw(rswapchain1)
-            };
-
-            let mut rswapchain3: *mut IDXGISwapChain3 = ptr::null_mut();
-            vhr!(swapchain1.QueryInterface(
-                &IDXGISwapChain3::uuidof(),
-                &mut rswapchain3 as *mut *mut _ as *mut *mut c_void,
-            ));
-            swapchain1.release();
-            WeakPtr::from_raw(rswapchain3)
+            hr!(
+                device.CreateCommandQueue(
+                    &D3D12_COMMAND_QUEUE_DESC {
+                        Flags: D3D12_COMMAND_QUEUE_FLAG_NONE,
+                        Priority: D3D12_COMMAND_QUEUE_PRIORITY_NORMAL as i32,
+                        Type: queue.command_list_type(),
+                        NodeMask: 0,
+                    },
+                    &ID3D12CommandQueue::uuidof(),
+                    &mut rcmdqueue as *mut *mut _ as *mut *mut c_void,
+                ),
+                device
+            )?;
+            Ok(WeakPtr::from_raw(rcmdqueue))
         };
+        let cmdqueue = guard.add(create_cmdqueue(QueueType::Graphics)?);
+        let compute_cmdqueue = guard.add(create_cmdqueue(QueueType::Compute)?);
+        let copy_cmdqueue = guard.add(create_cmdqueue(QueueType::Copy)?);
 
-        // Create command allocators.
-        let cmdallocs = {
-            let mut rcmdallocs: [*mut ID3D12CommandAllocator; 2] =
-                [ptr::null_mut(), ptr::null_mut()];
+        let swapchain = guard.add(create_swapchain(
+            factory,
+            cmdqueue,
+            window,
+            &swapchain_config,
+            tearing_supported,
+        )?);
+        swapchain_config.color_space = Some(set_swapchain_color_space(
+            swapchain,
+            swapchain_config.color_space(),
+        )?);
 
-            for i in 0..rcmdallocs.len() {
-                vhr!(device.CreateCommandAllocator(
-                    D3D12_COMMAND_LIST_TYPE_DIRECT,
-                    &ID3D12CommandAllocator::uuidof(),
-                    &mut rcmdallocs[i] as *mut *mut _ as *mut *mut c_void,
-                ));
-            }
-            [
-                WeakPtr::from_raw(rcmdallocs[0]),
-                WeakPtr::from_raw(rcmdallocs[1]),
-            ]
-        };
+        // Create command allocators, one per frame in flight.
+        let cmdallocs = create_command_allocators(
+            device,
+            D3D12_COMMAND_LIST_TYPE_DIRECT,
+            num_frames_in_flight,
+        )?;
+        for cmdalloc in cmdallocs.iter() {
+            guard.add(*cmdalloc);
+        }
 
         // Create descriptor heaps.
-        let mut rtv_heap = DescriptorHeap::new(
+        let mut rtv_heap = CpuDescriptorHeap::new(
             device,
-            1024,
             D3D12_DESCRIPTOR_HEAP_TYPE_RTV,
-            D3D12_DESCRIPTOR_HEAP_FLAG_NONE,
+            desc.rtv_heap_capacity,
         );
-        let dsv_heap = DescriptorHeap::new(
+        let dsv_heap = CpuDescriptorHeap::new(
             device,
-            1024,
             D3D12_DESCRIPTOR_HEAP_TYPE_DSV,
-            D3D12_DESCRIPTOR_HEAP_FLAG_NONE,
+            desc.dsv_heap_capacity,
         );
-        let cpu_cbv_srv_uav_heap = DescriptorHeap::new(
+        let cpu_cbv_srv_uav_heap = CpuDescriptorHeap::new(
             device,
-            16 * 1024,
             D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV,
-            D3D12_DESCRIPTOR_HEAP_FLAG_NONE,
+            desc.cbv_srv_uav_heap_capacity,
         );
-        let gpu_cbv_srv_uav_heaps = [
-            DescriptorHeap::new(
-                device,
-                16 * 1024,
-                D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV,
-                D3D12_DESCRIPTOR_HEAP_FLAG_SHADER_VISIBLE,
-            ),
-            DescriptorHeap::new(
-                device,
-                16 * 1024,
-                D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV,
-                D3D12_DESCRIPTOR_HEAP_FLAG_SHADER_VISIBLE,
-            ),
-        ];
-
-        // Create upload memory heaps.
-        let gpu_upload_memory_heaps = [
-            GpuMemoryHeap::new(device, 32 * 1024, D3D12_HEAP_TYPE_UPLOAD),
-            GpuMemoryHeap::new(device, 32 * 1024, D3D12_HEAP_TYPE_UPLOAD),
-        ];
+        let num_bindless_descriptors = desc.num_bindless_descriptors_for(&capabilities);
+        let num_transient_descriptors = desc.num_transient_descriptors;
+        let gpu_cbv_srv_uav_heap = DescriptorHeap::new(
+            device,
+            num_bindless_descriptors + num_frames_in_flight * num_transient_descriptors,
+            D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV,
+            D3D12_DESCRIPTOR_HEAP_FLAG_SHADER_VISIBLE,
+        )?;
+        guard.add(gpu_cbv_srv_uav_heap.heap);
+        let bindless_heap = gpu_cbv_srv_uav_heap.region(0, num_bindless_descriptors);
+        let gpu_cbv_srv_uav_heaps = (0..num_frames_in_flight)
+            .map(|i| {
+                gpu_cbv_srv_uav_heap.region(
+                    num_bindless_descriptors + i * num_transient_descriptors,
+                    num_transient_descriptors,
+                )
+            })
+            .collect();
+        let cpu_sampler_heap = DescriptorHeap::new(
+            device,
+            NUM_SAMPLERS,
+            D3D12_DESCRIPTOR_HEAP_TYPE_SAMPLER,
+            D3D12_DESCRIPTOR_HEAP_FLAG_NONE,
+        )?;
+        guard.add(cpu_sampler_heap.heap);
+        let gpu_sampler_heap = DescriptorHeap::new(
+            device,
+            MAX_NUM_GPU_SAMPLERS,
+            D3D12_DESCRIPTOR_HEAP_TYPE_SAMPLER,
+            D3D12_DESCRIPTOR_HEAP_FLAG_SHADER_VISIBLE,
+        )?;
+        guard.add(gpu_sampler_heap.heap);
+        let sampler_heap = gpu_sampler_heap.region(0, NUM_SAMPLERS);
+        let num_transient_samplers = (MAX_NUM_GPU_SAMPLERS - NUM_SAMPLERS) / num_frames_in_flight;
+        let gpu_sampler_heaps = (0..num_frames_in_flight)
+            .map(|i| {
+                gpu_sampler_heap.region(
+                    NUM_SAMPLERS + i * num_transient_samplers,
+                    num_transient_samplers,
+                )
+            })
+            .collect();
 
         let mut resource_pool = ResourcePool::new();
         let pipeline_pool = PipelinePool::new();
 
-        let swap_buffers = {
-            let mut rbuffers: [*mut ID3D12Resource; 4] = [ptr::null_mut(); 4];
-            let mut handle = rtv_heap.allocate_cpu_descriptors(rbuffers.len() as u32);
-
-            for i in 0..rbuffers.len() {
-                vhr!(swapchain.GetBuffer(
-                    i as u32,
-                    &ID3D12Resource::uuidof(),
-                    &mut rbuffers[i] as *mut *mut _ as *mut *mut c_void,
-                ));
-                unsafe { device.CreateRenderTargetView(rbuffers[i], ptr::null(), handle) };
-                handle.ptr += rtv_heap.descriptor_size as usize;
-            }
-            [
-                resource_pool.add(
-                    WeakPtr::from_raw(rbuffers[0]),
-                    D3D12_RESOURCE_STATE_PRESENT,
-                    DXGI_FORMAT_R8G8B8A8_UNORM,
-                ),
-                resource_pool.add(
-                    WeakPtr::from_raw(rbuffers[1]),
-                    D3D12_RESOURCE_STATE_PRESENT,
-                    DXGI_FORMAT_R8G8B8A8_UNORM,
-                ),
-                resource_pool.add(
-                    WeakPtr::from_raw(rbuffers[2]),
-                    D3D12_RESOURCE_STATE_PRESENT,
-                    DXGI_FORMAT_R8G8B8A8_UNORM,
-                ),
-                resource_pool.add(
-                    WeakPtr::from_raw(rbuffers[3]),
-                    D3D12_RESOURCE_STATE_PRESENT,
-                    DXGI_FORMAT_R8G8B8A8_UNORM,
-                ),
-            ]
+        let resolution = unsafe {
+            let mut rect: RECT = mem::zeroed();
+            GetClientRect(window, &mut rect as *mut RECT);
+            [rect.right as u32, rect.bottom as u32]
         };
 
         let cmdlist = {
             let mut rcmdlist: *mut ID3D12GraphicsCommandList1 = ptr::null_mut();
-            vhr!(device.CreateCommandList(
-                0,
-                D3D12_COMMAND_LIST_TYPE_DIRECT,
-                cmdallocs[0].as_raw(),
-                ptr::null_mut(),
-                &ID3D12GraphicsCommandList1::uuidof(),
-                &mut rcmdlist as *mut *mut _ as *mut *mut c_void,
-            ));
-            GraphicsCommandList::from_raw(rcmdlist)
+            hr!(
+                device.CreateCommandList(
+                    0,
+                    D3D12_COMMAND_LIST_TYPE_DIRECT,
+                    cmdallocs[0].as_raw(),
+                    ptr::null_mut(),
+                    &ID3D12GraphicsCommandList1::uuidof(),
+                    &mut rcmdlist as *mut *mut _ as *mut *mut c_void,
+                ),
+                device
+            )?;
+            guard.add(GraphicsCommandList::from_raw(rcmdlist))
         };
-        vhr!(cmdlist.Close());
+        hr!(cmdlist.Close(), device)?;
 
-        let frame_fence = {
+        let create_fence = || {
             let mut rfence: *mut ID3D12Fence = ptr::null_mut();
-            vhr!(device.CreateFence(
-                0,
-                D3D12_FENCE_FLAG_NONE,
-                &ID3D12Fence::uuidof(),
-                &mut rfence as *mut *mut _ as *mut *mut c_void
-            ));
-            WeakPtr::from_raw(rfence)
+            hr!(
+                device.CreateFence(
+                    0,
+                    D3D12_FENCE_FLAG_NONE,
+                    &ID3D12Fence::uuidof(),
+                    &mut rfence as *mut *mut _ as *mut *mut c_void
+                ),
+                device
+            )?;
+            Ok(WeakPtr::from_raw(rfence))
         };
+        let frame_fence = guard.add(create_fence()?);
+        let queue_fences = [
+            guard.add(create_fence()?),
+            guard.add(create_fence()?),
+            guard.add(create_fence()?),
+        ];
+
+        // The swap buffers are tracked by `resource_pool` and their RTVs live in pages of
+        // `rtv_heap`, so those are cleaned up here rather than by the guard.
+        let swap_buffers = rtv_heap
+            .allocate(device, swapchain_config.buffer_count)
+            .and_then(|swap_buffer_rtvs| {
+                let swap_buffers = get_swap_buffers(
+                    device,
+                    swapchain,
+                    &swapchain_config,
+                    &mut resource_pool,
+                    &swap_buffer_rtvs,
+                    rtv_heap.descriptor_size,
+                    resolution,
+                )?;
+                Ok((swap_buffer_rtvs, swap_buffers))
+            });
+        if swap_buffers.is_err() {
+            resource_pool.destroy();
+            rtv_heap.destroy();
+        }
+        let (swap_buffer_rtvs, swap_buffers) = swap_buffers?;
 
         let frame_fence_event =
             unsafe { CreateEventExA(ptr::null_mut(), ptr::null(), 0, EVENT_ALL_ACCESS) };
 
         let back_buffer_index = unsafe { swapchain.GetCurrentBackBufferIndex() };
 
-        Self {
+        // The factory was only needed to create the swap chain.
+        guard.disarm();
+        factory.release();
+
+        Ok(Self {
             device,
             cmdqueue,
+            compute_cmdqueue,
+            copy_cmdqueue,
+            queue_fences,
+            queue_fence_values: [0; 3],
+            uploader: None,
+            readbacks: HashMap::new(),
+            next_readback_id: 0,
             swapchain,
             cmdallocs,
             rtv_heap,
             dsv_heap,
             cpu_cbv_srv_uav_heap,
+            gpu_cbv_srv_uav_heap,
+            bindless_heap,
+            bindless_allocator: DescriptorAllocator::new(num_bindless_descriptors),
             gpu_cbv_srv_uav_heaps,
-            gpu_upload_memory_heaps,
+            descriptor_table_caches: (0..num_frames_in_flight_usize)
+                .map(|_| DescriptorTableCache::new())
+                .collect(),
+            cpu_sampler_heap,
+            gpu_sampler_heap,
+            sampler_heap,
+            gpu_sampler_heaps,
+            sampler_cache: SamplerCache::new(NUM_SAMPLERS),
+            sampler_table_caches: (0..num_frames_in_flight_usize)
+                .map(|_| DescriptorTableCache::new())
+                .collect(),
+            upload_ring: UploadRing::new(desc.upload_page_size),
+            swapchain_config,
+            tearing_supported,
+            debug_layer: desc.debug_layer,
+            adapter_info,
+            capabilities,
             swap_buffers,
+            swap_buffer_rtvs,
             cmdlist,
             frame_fence,
             frame_fence_event,
             num_frames: 0,
             frame_index: 0,
+            num_frames_in_flight,
             back_buffer_index,
-            resolution: unsafe {
-                let mut rect: RECT = mem::zeroed();
-                GetClientRect(window, &mut rect as *mut RECT);
-                [rect.right as u32, rect.bottom as u32]
-            },
+            resolution,
             window,
             resource_pool,
             pipeline_pool,
             current_pipeline: INVALID_PIPELINE,
-        }
+            mipgen_pipeline: INVALID_PIPELINE,
+            deferred_destroys: vec![Vec::new(); num_frames_in_flight_usize],
+            window_depth_buffers: Vec::new(),
+            barrier_queue: BarrierQueue::new(),
+            state_validation: cfg!(debug_assertions),
+            state_validation_errors: Vec::new(),
+            log_hook: desc.log_hook,
+            bound_graphics_resources: Vec::new(),
+            bound_compute_resources: Vec::new(),
+            bound_render_targets: Vec::new(),
+        })
     }
 
     pub fn destroy(&mut self) {
@@ -624,16 +990,32 @@ impl Context {
         self.pipeline_pool.destroy();
         self.device.release();
         self.cmdqueue.release();
+        self.compute_cmdqueue.release();
+        self.copy_cmdqueue.release();
+        for fence in self.queue_fences.iter_mut() {
+            fence.release();
+        }
+        for readback in self.readbacks.values_mut() {
+            readback.buffer.heap.release();
+        }
+        if let Some(uploader) = self.uploader.as_mut() {
+            uploader.staging.heap.release();
+            uploader.cmdlist.release();
+            for (cmdalloc, _) in uploader.cmdallocs.iter_mut() {
+                cmdalloc.release();
+            }
+        }
         self.swapchain.release();
-        self.cmdallocs[0].release();
-        self.cmdallocs[1].release();
-        self.rtv_heap.heap.release();
-        self.dsv_heap.heap.release();
-        self.cpu_cbv_srv_uav_heap.heap.release();
-        self.gpu_cbv_srv_uav_heaps[0].heap.release();
-        self.gpu_cbv_srv_uav_heaps[1].heap.release();
-        self.gpu_upload_memory_heaps[0].heap.release();
-        self.gpu_upload_memory_heaps[1].heap.release();
+        for cmdalloc in self.cmdallocs.iter_mut() {
+            cmdalloc.release();
+        }
+        self.rtv_heap.destroy();
+        self.dsv_heap.destroy();
+        self.cpu_cbv_srv_uav_heap.destroy();
+        self.gpu_cbv_srv_uav_heap.heap.release();
+        self.cpu_sampler_heap.heap.release();
+        self.gpu_sampler_heap.heap.release();
+        self.upload_ring.destroy();
         self.cmdlist.release();
         self.frame_fence.release();
         unsafe { CloseHandle(self.frame_fence_event) };
@@ -642,31 +1024,36 @@ impl Context {
 
     #[inline]
     fn validate_resource_state(&self, handle: ResourceHandle) {
-        let index = handle.index as usize;
-        assert!(index > 0 && index <= MAX_NUM_RESOURCES);
-        assert!(handle.generation == self.resource_pool.generations[index]);
-        assert!(!self.resource_pool.resources[index].ptr.is_null());
+        self.resource_pool.get(handle);
     }
 
     #[inline]
     fn validate_pipeline_state(&self, handle: PipelineHandle) {
+        self.pipeline_pool.get(handle);
+    }
+
+    #[inline]
+    fn is_resource_alive(&self, handle: ResourceHandle) -> bool {
         let index = handle.index as usize;
-        assert!(index > 0 && index <= MAX_NUM_PIPELINES);
-        assert!(handle.generation == self.pipeline_pool.generations[index]);
-        assert!(!self.pipeline_pool.pipelines[index].pso.is_null());
-        assert!(!self.pipeline_pool.pipelines[index].rsignature.is_null());
+        index > 0
+            && index <= MAX_NUM_RESOURCES
+            && handle.generation == self.resource_pool.generations[index]
+            && !self.resource_pool.resources[index].ptr.is_null()
     }
 
     #[inline]
     pub fn resource(&self, handle: ResourceHandle) -> WeakPtr<ID3D12Resource> {
-        self.validate_resource_state(handle);
-        self.resource_pool.resources[handle.index as usize].ptr
+        self.resource_pool.get(handle).ptr
+    }
+
+    #[inline]
+    fn resource_state(&self, handle: ResourceHandle) -> &ResourceState {
+        self.resource_pool.get(handle)
     }
 
     #[inline]
     fn pipeline_state(&self, handle: PipelineHandle) -> &PipelineState {
-        self.validate_pipeline_state(handle);
-        &self.pipeline_pool.pipelines[handle.index as usize]
+        self.pipeline_pool.get(handle)
     }
 
     #[inline]
@@ -682,35 +1069,134 @@ impl Context {
         desc: &D3D12_RESOURCE_DESC,
         initial_state: D3D12_RESOURCE_STATES,
         clear_value: Option<&D3D12_CLEAR_VALUE>,
-    ) -> ResourceHandle {
+    ) -> Result<ResourceHandle> {
         let resource = {
             let mut resource_raw: *mut ID3D12Resource = ptr::null_mut();
-            vhr!(self.device.CreateCommittedResource(
-                &HeapProperties::new(heap_type),
-                heap_flags,
-                desc,
-                initial_state,
-                if clear_value.is_none() {
-                    ptr::null()
-                } else {
-                    clear_value.unwrap()
+            hr!(
+                self.device.CreateCommittedResource(
+                    &HeapProperties::new(heap_type),
+                    heap_flags,
+                    desc,
+                    initial_state,
+                    if clear_value.is_none() {
+                        ptr::null()
+                    } else {
+                        clear_value.unwrap()
+                    },
+                    &ID3D12Resource::uuidof(),
+                    &mut resource_raw as *mut *mut _ as *mut *mut c_void
+                ),
+                self.device
+            )?;
+            WeakPtr::from_raw(resource_raw)
+        };
+        Ok(self.resource_pool.add(resource, initial_state, desc))
+    }
+
+    pub fn create_heap(
+        &self,
+        size: u64,
+        heap_type: D3D12_HEAP_TYPE,
+        heap_flags: D3D12_HEAP_FLAGS,
+    ) -> Result<Heap> {
+        let mut heap_raw: *mut ID3D12Heap = ptr::null_mut();
+        hr!(
+            self.device.CreateHeap(
+                &D3D12_HEAP_DESC {
+                    SizeInBytes: size,
+                    Properties: HeapProperties::new(heap_type),
+                    Alignment: 0,
+                    Flags: heap_flags,
                 },
-                &ID3D12Resource::uuidof(),
-                &mut resource_raw as *mut *mut _ as *mut *mut c_void
-            ));
+                &ID3D12Heap::uuidof(),
+                &mut heap_raw as *mut *mut _ as *mut *mut c_void
+            ),
+            self.device
+        )?;
+        Ok(WeakPtr::from_raw(heap_raw))
+    }
+
+    // Placed resources that share memory must be activated with `cmd_aliasing_barrier` before use.
+    pub fn create_placed_resource(
+        &mut self,
+        heap: Heap,
+        heap_offset: u64,
+        desc: &D3D12_RESOURCE_DESC,
+        initial_state: D3D12_RESOURCE_STATES,
+        clear_value: Option<&D3D12_CLEAR_VALUE>,
+    ) -> Result<ResourceHandle> {
+        let resource = {
+            let mut resource_raw: *mut ID3D12Resource = ptr::null_mut();
+            hr!(
+                self.device.CreatePlacedResource(
+                    heap.as_raw(),
+                    heap_offset,
+                    desc,
+                    initial_state,
+                    if clear_value.is_none() {
+                        ptr::null()
+                    } else {
+                        clear_value.unwrap()
+                    },
+                    &ID3D12Resource::uuidof(),
+                    &mut resource_raw as *mut *mut _ as *mut *mut c_void
+                ),
+                self.device
+            )?;
             WeakPtr::from_raw(resource_raw)
         };
-        self.resource_pool.add(resource, initial_state, desc.Format)
+        Ok(self.resource_pool.add(resource, initial_state, desc))
     }
 
     pub fn destroy_resource(&mut self, handle: ResourceHandle) {
+        let refcount = self.remove_resource(handle);
+        assert!(refcount == 0);
+    }
+
+    // Returns the reference count left after releasing the resource.
+    fn remove_resource(&mut self, handle: ResourceHandle) -> u32 {
+        self.barrier_queue.remove_resource(handle);
+        self.window_depth_buffers.retain(|h| *h != handle);
         let mut resource = self.resource_state_mut(handle);
 
         let refcount = resource.ptr.release();
-        assert!(refcount == 0);
 
-        resource.state = D3D12_RESOURCE_STATE_COMMON;
-        resource.format = DXGI_FORMAT_UNKNOWN;
+        resource.states = SubresourceStates::Uniform(D3D12_RESOURCE_STATE_COMMON);
+        resource.pending_splits.clear();
+        resource.name.clear();
+        resource.desc = unsafe { mem::zeroed() };
+        resource.views.clear();
+        let descriptors = mem::replace(&mut resource.descriptors, Vec::new());
+        let bindless_indices = mem::replace(&mut resource.bindless_indices, Vec::new());
+
+        for allocation in descriptors {
+            self.free_cpu_descriptors(allocation);
+        }
+        for index in bindless_indices {
+            self.free_bindless_descriptor(index);
+        }
+        refcount
+    }
+
+    // The name shows up in state validation errors and in debug layer messages.
+    pub fn set_resource_name(&mut self, handle: ResourceHandle, name: &str) {
+        let resource = self.resource_state_mut(handle);
+        resource.name = name.to_string();
+        let wide_name: Vec<u16> = name.encode_utf16().chain(iter::once(0)).collect();
+        unsafe { resource.ptr.SetName(wide_name.as_ptr()) };
+    }
+
+    // Destroys the resource once the GPU is done with the current frame.
+    pub fn destroy_resource_deferred(&mut self, handle: ResourceHandle) {
+        self.validate_resource_state(handle);
+        self.deferred_destroys[self.frame_index as usize].push(handle);
+    }
+
+    fn flush_deferred_destroys(&mut self, frame_index: usize) {
+        let handles = mem::replace(&mut self.deferred_destroys[frame_index], Vec::new());
+        for handle in handles {
+            self.destroy_resource(handle);
+        }
     }
 
     pub fn cmd_transition_barrier(
@@ -718,45 +1204,817 @@ impl Context {
         resource_handle: ResourceHandle,
         state_after: D3D12_RESOURCE_STATES,
     ) {
-        let cmdlist = self.cmdlist;
-        let mut resource = self.resource_state_mut(resource_handle);
-        if resource.state != state_after {
-            unsafe {
-                cmdlist.ResourceBarrier(
-                    1,
-                    &ResourceBarrier::transition(resource.ptr, resource.state, state_after),
-                )
-            };
-            resource.state = state_after;
-        }
+        self.cmd_transition_subresource_barrier(
+            resource_handle,
+            D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES,
+            state_after,
+        );
     }
 
-    pub fn cmd_set_graphics_pipeline(&mut self, handle: PipelineHandle) {
-        let pipeline_state = self.pipeline_state(handle);
-        if handle != self.current_pipeline {
-            unsafe {
-                self.cmdlist.SetPipelineState(pipeline_state.pso.as_raw());
-                self.cmdlist
-                    .SetGraphicsRootSignature(pipeline_state.rsignature.as_raw());
-                self.current_pipeline = handle;
-            }
+    pub fn cmd_transition_subresource_barrier(
+        &mut self,
+        resource_handle: ResourceHandle,
+        subresource: u32,
+        state_after: D3D12_RESOURCE_STATES,
+    ) {
+        let resource = self.resource_state_mut(resource_handle);
+        assert!(
+            !resource.has_pending_split(subresource),
+            "transition of a subresource with a split transition in flight"
+        );
+        let num_subresources = num_subresources(&resource.desc);
+        let transitions = resource
+            .states
+            .transition(num_subresources, subresource, state_after);
+
+        for transition in transitions {
+            self.barrier_queue.push_transition(
+                resource_handle,
+                transition,
+                D3D12_RESOURCE_BARRIER_FLAG_NONE,
+            );
         }
     }
 
-    pub fn create_graphics_pipeline(
+    // Starts a split transition. The tracked state is updated immediately, but the subresource
+    // must not be used until the matching `cmd_end_split_transition`. Splits of different
+    // subresources (e.g. the mips of a chain) can be in flight at the same time.
+    pub fn cmd_begin_split_transition(
         &mut self,
-        pso_desc: &mut D3D12_GRAPHICS_PIPELINE_STATE_DESC,
-        vs_name: &str,
-        ps_name: &str,
-    ) -> PipelineHandle {
-        let vs_bytecode = fs::read(format!("data/shaders/{}", vs_name)).unwrap();
-        let ps_bytecode = fs::read(format!("data/shaders/{}", ps_name)).unwrap();
+        resource_handle: ResourceHandle,
+        subresource: u32,
+        state_after: D3D12_RESOURCE_STATES,
+    ) {
+        let resource = self.resource_state_mut(resource_handle);
+        assert!(
+            !resource.has_pending_split(subresource),
+            "split transition of a subresource with a split transition in flight"
+        );
+        let num_subresources = num_subresources(&resource.desc);
+        let transitions = resource
+            .states
+            .transition(num_subresources, subresource, state_after);
+        resource
+            .pending_splits
+            .push((subresource, transitions.clone()));
+
+        for transition in transitions {
+            self.barrier_queue.push_transition(
+                resource_handle,
+                transition,
+                D3D12_RESOURCE_BARRIER_FLAG_BEGIN_ONLY,
+            );
+        }
+    }
 
-        pso_desc.VS = D3D12_SHADER_BYTECODE {
-            pShaderBytecode: vs_bytecode.as_ptr() as *const c_void,
-            BytecodeLength: vs_bytecode.len(),
-        };
-        pso_desc.PS = D3D12_SHADER_BYTECODE {
+    // `subresource` is the one passed to `cmd_begin_split_transition`.
+    pub fn cmd_end_split_transition(&mut self, resource_handle: ResourceHandle, subresource: u32) {
+        let resource = self.resource_state_mut(resource_handle);
+        let index = resource
+            .pending_splits
+            .iter()
+            .position(|(begun, _)| *begun == subresource)
+            .expect("no split transition in flight for this subresource");
+        let (_, transitions) = resource.pending_splits.remove(index);
+
+        for transition in transitions {
+            self.barrier_queue.push_transition(
+                resource_handle,
+                transition,
+                D3D12_RESOURCE_BARRIER_FLAG_END_ONLY,
+            );
+        }
+    }
+
+    // Orders UAV accesses between dependent dispatches or draws. `None` covers all UAVs.
+    pub fn cmd_uav_barrier(&mut self, resource_handle: Option<ResourceHandle>) {
+        if let Some(handle) = resource_handle {
+            let resource = self.resource_state(handle);
+            let num_subresources = num_subresources(&resource.desc);
+            assert!(
+                (0..num_subresources)
+                    .any(|i| resource.states.state(i) == D3D12_RESOURCE_STATE_UNORDERED_ACCESS),
+                "UAV barrier on a resource that is not in UNORDERED_ACCESS state"
+            );
+        }
+        self.barrier_queue.push_uav(resource_handle);
+    }
+
+    // Activates `resource_after` in memory shared with `resource_before`. `None` on either side
+    // means any placed or reserved resource.
+    pub fn cmd_aliasing_barrier(
+        &mut self,
+        resource_before: Option<ResourceHandle>,
+        resource_after: Option<ResourceHandle>,
+    ) {
+        for handle in resource_before.iter().chain(resource_after.iter()) {
+            assert!(self.resource_state(*handle).pending_splits.is_empty());
+        }
+        self.barrier_queue
+            .push_aliasing(resource_before, resource_after);
+    }
+
+    // Issues all queued barriers as a single ResourceBarrier call. Called automatically by the
+    // cmd_* draw, dispatch, copy and clear functions and by end_frame; call it explicitly before
+    // recording such work directly on `cmdlist`.
+    pub fn cmd_flush_barriers(&mut self) {
+        if self.barrier_queue.is_empty() {
+            return;
+        }
+        let queued = self.barrier_queue.take();
+        let resource_or_null = |handle: Option<ResourceHandle>| match handle {
+            Some(h) => self.resource(h),
+            None => WeakPtr::new(),
+        };
+        let barriers: Vec<D3D12_RESOURCE_BARRIER> = queued
+            .iter()
+            .map(|b| match *b {
+                QueuedBarrier::Transition {
+                    resource,
+                    transition,
+                    flags,
+                } => {
+                    let mut barrier = ResourceBarrier::transition_subresource(
+                        self.resource(resource),
+                        transition.state_before,
+                        transition.state_after,
+                        transition.subresource,
+                    );
+                    barrier.Flags = flags;
+                    barrier
+                }
+                QueuedBarrier::Uav { resource } => ResourceBarrier::uav(resource_or_null(resource)),
+                QueuedBarrier::Aliasing {
+                    resource_before,
+                    resource_after,
+                } => ResourceBarrier::aliasing(
+                    resource_or_null(resource_before),
+                    resource_or_null(resource_after),
+                ),
+            })
+            .collect();
+        self.cmdlist.resource_barrier(&barriers);
+    }
+
+    // When enabled, every flushed batch is kept until the next begin_frame.
+    pub fn set_record_barrier_batches(&mut self, enable: bool) {
+        self.barrier_queue.set_record_batches(enable);
+    }
+
+    pub fn recorded_barrier_batches(&self) -> &[Vec<QueuedBarrier>] {
+        self.barrier_queue.recorded_batches()
+    }
+
+    // When enabled, every bind, draw, dispatch, copy and clear issued through the Context checks
+    // the tracked state of the resources it references. Enabled by default in debug builds.
+    pub fn set_state_validation(&mut self, enable: bool) {
+        self.state_validation = enable;
+    }
+
+    pub fn take_state_validation_errors(&mut self) -> Vec<String> {
+        mem::replace(&mut self.state_validation_errors, Vec::new())
+    }
+
+    fn resource_label(&self, handle: ResourceHandle) -> String {
+        let name = &self.resource_pool.resources[handle.index as usize].name;
+        if name.is_empty() {
+            format!("resource #{}", handle.index)
+        } else {
+            format!("resource '{}' (#{})", name, handle.index)
+        }
+    }
+
+    fn report_state_validation_error(&mut self, location: &Location, message: String) {
+        let message = format!("{}: {}", location, message);
+        log(self.log_hook, LogLevel::Error, &message);
+        self.state_validation_errors.push(message);
+    }
+
+    fn check_resource_usage(
+        &mut self,
+        handle: ResourceHandle,
+        subresource: u32,
+        usage: ResourceUsage,
+        operation: &str,
+        location: &Location,
+    ) {
+        if !self.state_validation {
+            return;
+        }
+        if !self.is_resource_alive(handle) {
+            self.report_state_validation_error(
+                location,
+                format!(
+                    "{} references a destroyed resource #{}",
+                    operation, handle.index
+                ),
+            );
+            return;
+        }
+
+        let resource = self.resource_state(handle);
+        let is_buffer = resource.desc.Dimension == D3D12_RESOURCE_DIMENSION_BUFFER;
+        let subresources = if subresource == D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES {
+            0..num_subresources(&resource.desc)
+        } else {
+            subresource..subresource + 1
+        };
+
+        let error = if resource.has_pending_split(subresource) {
+            Some(format!(
+                "{} uses {} while a split transition is in flight",
+                operation,
+                self.resource_label(handle)
+            ))
+        } else {
+            subresources
+                .map(|i| (i, resource.states.state(i)))
+                .find(|(_, state)| !usage.is_satisfied_by(*state, is_buffer))
+                .map(|(i, state)| {
+                    format!(
+                        "{} needs {} (subresource {}) as {:?} ({}), but it is in {}",
+                        operation,
+                        self.resource_label(handle),
+                        i,
+                        usage,
+                        resource_state_name(usage.required_states()),
+                        resource_state_name(state)
+                    )
+                })
+        };
+        if let Some(message) = error {
+            self.report_state_validation_error(location, message);
+        }
+    }
+
+    fn check_resource_usage_by_ptr(
+        &mut self,
+        resource: Resource,
+        subresource: u32,
+        usage: ResourceUsage,
+        operation: &str,
+        location: &Location,
+    ) {
+        if !self.state_validation {
+            return;
+        }
+        // Untracked resources (e.g. upload heaps) are not validated.
+        if let Some(handle) = self.resource_pool.find(resource) {
+            self.check_resource_usage(handle, subresource, usage, operation, location);
+        }
+    }
+
+    // Every graphics queue submission (`execute_command_contexts`, `signal_queue`) reopens
+    // `cmdlist`, which drops the pipeline and root arguments set before it.
+    #[track_caller]
+    fn check_pipeline_set(&self, operation: &str) {
+        assert!(
+            self.current_pipeline != INVALID_PIPELINE,
+            "{} without a pipeline; the pipeline and root arguments have to be set again after \
+             the graphics queue is submitted",
+            operation
+        );
+    }
+
+    fn check_bound_resources(&mut self, compute: bool, operation: &str, location: &Location) {
+        if !self.state_validation {
+            return;
+        }
+        let bound: Vec<BoundResource> = if compute {
+            self.bound_compute_resources.clone()
+        } else {
+            self.bound_graphics_resources
+                .iter()
+                .chain(self.bound_render_targets.iter())
+                .cloned()
+                .collect()
+        };
+        for b in bound {
+            self.check_resource_usage(
+                b.resource,
+                D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES,
+                b.usage,
+                &format!("{} (slot {} bound at {})", operation, b.slot, b.location),
+                location,
+            );
+        }
+    }
+
+    fn bind_resources(
+        &mut self,
+        compute: bool,
+        slot: u32,
+        resources: &[(ResourceHandle, ResourceUsage)],
+        operation: &str,
+        location: &'static Location<'static>,
+    ) {
+        if !self.state_validation {
+            return;
+        }
+        let bound = if compute {
+            &mut self.bound_compute_resources
+        } else {
+            &mut self.bound_graphics_resources
+        };
+        bound.retain(|b| b.slot != slot);
+        for &(resource, usage) in resources {
+            bound.push(BoundResource {
+                slot,
+                resource,
+                usage,
+                location,
+            });
+        }
+        for &(resource, usage) in resources {
+            self.check_resource_usage(
+                resource,
+                D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES,
+                usage,
+                operation,
+                location,
+            );
+        }
+    }
+
+    // `resources` lists every resource referenced by the table, for state validation.
+    #[track_caller]
+    pub fn cmd_set_graphics_root_descriptor_table(
+        &mut self,
+        root_parameter_index: u32,
+        base_descriptor: D3D12_GPU_DESCRIPTOR_HANDLE,
+        resources: &[(ResourceHandle, ResourceUsage)],
+    ) {
+        self.bind_resources(
+            false,
+            root_parameter_index,
+            resources,
+            "SetGraphicsRootDescriptorTable",
+            Location::caller(),
+        );
+        self.cmdlist
+            .set_graphics_root_descriptor_table(root_parameter_index, base_descriptor);
+    }
+
+    // `resources` lists every resource referenced by the table, for state validation.
+    #[track_caller]
+    pub fn cmd_set_compute_root_descriptor_table(
+        &mut self,
+        root_parameter_index: u32,
+        base_descriptor: D3D12_GPU_DESCRIPTOR_HANDLE,
+        resources: &[(ResourceHandle, ResourceUsage)],
+    ) {
+        self.bind_resources(
+            true,
+            root_parameter_index,
+            resources,
+            "SetComputeRootDescriptorTable",
+            Location::caller(),
+        );
+        self.cmdlist
+            .set_compute_root_descriptor_table(root_parameter_index, base_descriptor);
+    }
+
+    pub fn cmd_set_graphics_root_sampler_table(
+        &mut self,
+        root_parameter_index: u32,
+        base_descriptor: D3D12_GPU_DESCRIPTOR_HANDLE,
+    ) {
+        self.cmdlist
+            .set_graphics_root_descriptor_table(root_parameter_index, base_descriptor);
+    }
+
+    pub fn cmd_set_compute_root_sampler_table(
+        &mut self,
+        root_parameter_index: u32,
+        base_descriptor: D3D12_GPU_DESCRIPTOR_HANDLE,
+    ) {
+        self.cmdlist
+            .set_compute_root_descriptor_table(root_parameter_index, base_descriptor);
+    }
+
+    #[track_caller]
+    pub fn cmd_set_graphics_root_shader_resource_view(
+        &mut self,
+        root_parameter_index: u32,
+        resource_handle: ResourceHandle,
+    ) {
+        self.bind_resources(
+            false,
+            root_parameter_index,
+            &[(resource_handle, ResourceUsage::ShaderResource)],
+            "SetGraphicsRootShaderResourceView",
+            Location::caller(),
+        );
+        self.cmdlist.set_graphics_root_shader_resource_view(
+            root_parameter_index,
+            self.resource(resource_handle).get_gpu_virtual_address(),
+        );
+    }
+
+    #[track_caller]
+    pub fn cmd_set_render_target(
+        &mut self,
+        render_target: ResourceHandle,
+        render_target_view: D3D12_CPU_DESCRIPTOR_HANDLE,
+        depth_stencil: Option<(ResourceHandle, D3D12_CPU_DESCRIPTOR_HANDLE)>,
+    ) {
+        let location = Location::caller();
+        if self.state_validation {
+            self.bound_render_targets.clear();
+            self.bound_render_targets.push(BoundResource {
+                slot: 0,
+                resource: render_target,
+                usage: ResourceUsage::RenderTarget,
+                location,
+            });
+            if let Some((depth_stencil, _)) = depth_stencil {
+                self.bound_render_targets.push(BoundResource {
+                    slot: D3D12_SIMULTANEOUS_RENDER_TARGET_COUNT,
+                    resource: depth_stencil,
+                    usage: ResourceUsage::DepthWrite,
+                    location,
+                });
+            }
+            let bound = self.bound_render_targets.clone();
+            for b in bound {
+                self.check_resource_usage(
+                    b.resource,
+                    D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES,
+                    b.usage,
+                    "OMSetRenderTargets",
+                    location,
+                );
+            }
+        }
+        self.cmdlist
+            .om_set_render_target(render_target_view, depth_stencil.map(|(_, dsv)| dsv));
+    }
+
+    #[track_caller]
+    pub fn cmd_draw_instanced(
+        &mut self,
+        vertex_count_per_instance: u32,
+        instance_count: u32,
+        start_vertex_location: u32,
+        start_instance_location: u32,
+    ) {
+        self.check_pipeline_set("DrawInstanced");
+        self.check_bound_resources(false, "DrawInstanced", Location::caller());
+        self.cmd_flush_barriers();
+        self.cmdlist.draw_instanced(
+            vertex_count_per_instance,
+            instance_count,
+            start_vertex_location,
+            start_instance_location,
+        );
+    }
+
+    #[track_caller]
+    pub fn cmd_draw_indexed_instanced(
+        &mut self,
+        index_count_per_instance: u32,
+        instance_count: u32,
+        start_index_location: u32,
+        base_vertex_location: i32,
+        start_instance_location: u32,
+    ) {
+        self.check_pipeline_set("DrawIndexedInstanced");
+        self.check_bound_resources(false, "DrawIndexedInstanced", Location::caller());
+        self.cmd_flush_barriers();
+        self.cmdlist.draw_indexed_instanced(
+            index_count_per_instance,
+            instance_count,
+            start_index_location,
+            base_vertex_location,
+            start_instance_location,
+        );
+    }
+
+    #[track_caller]
+    pub fn cmd_dispatch(
+        &mut self,
+        thread_group_count_x: u32,
+        thread_group_count_y: u32,
+        thread_group_count_z: u32,
+    ) {
+        self.check_pipeline_set("Dispatch");
+        self.check_bound_resources(true, "Dispatch", Location::caller());
+        self.cmd_flush_barriers();
+        self.cmdlist.dispatch(
+            thread_group_count_x,
+            thread_group_count_y,
+            thread_group_count_z,
+        );
+    }
+
+    #[track_caller]
+    pub fn cmd_copy_buffer_region(
+        &mut self,
+        dst_buffer: Resource,
+        dst_offset: u64,
+        src_buffer: Resource,
+        src_offset: u64,
+        num_bytes: u64,
+    ) {
+        let location = Location::caller();
+        self.check_resource_usage_by_ptr(
+            dst_buffer,
+            D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES,
+            ResourceUsage::CopyDest,
+            "CopyBufferRegion",
+            location,
+        );
+        self.check_resource_usage_by_ptr(
+            src_buffer,
+            D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES,
+            ResourceUsage::CopySource,
+            "CopyBufferRegion",
+            location,
+        );
+        self.cmd_flush_barriers();
+        self.cmdlist
+            .copy_buffer_region(dst_buffer, dst_offset, src_buffer, src_offset, num_bytes);
+    }
+
+    #[track_caller]
+    pub fn cmd_copy_texture_region(
+        &mut self,
+        dst: &D3D12_TEXTURE_COPY_LOCATION,
+        dst_x: u32,
+        dst_y: u32,
+        dst_z: u32,
+        src: &D3D12_TEXTURE_COPY_LOCATION,
+        src_box: Option<&D3D12_BOX>,
+    ) {
+        let location = Location::caller();
+        for (copy_location, usage) in &[
+            (dst, ResourceUsage::CopyDest),
+            (src, ResourceUsage::CopySource),
+        ] {
+            let subresource = if copy_location.Type == D3D12_TEXTURE_COPY_TYPE_SUBRESOURCE_INDEX {
+                unsafe { *copy_location.u.SubresourceIndex() }
+            } else {
+                D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES
+            };
+            self.check_resource_usage_by_ptr(
+                WeakPtr::from_raw(copy_location.pResource),
+                subresource,
+                *usage,
+                "CopyTextureRegion",
+                location,
+            );
+        }
+        self.cmd_flush_barriers();
+        self.cmdlist
+            .copy_texture_region(dst, dst_x, dst_y, dst_z, src, src_box);
+    }
+
+    #[track_caller]
+    pub fn cmd_clear_render_target_view(
+        &mut self,
+        render_target: ResourceHandle,
+        render_target_view: D3D12_CPU_DESCRIPTOR_HANDLE,
+        color_rgba: &[f32; 4],
+        rects: &[D3D12_RECT],
+    ) {
+        self.check_resource_usage(
+            render_target,
+            D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES,
+            ResourceUsage::RenderTarget,
+            "ClearRenderTargetView",
+            Location::caller(),
+        );
+        self.cmd_flush_barriers();
+        self.cmdlist
+            .clear_render_target_view(render_target_view, color_rgba, rects);
+    }
+
+    #[track_caller]
+    pub fn cmd_clear_depth_stencil_view(
+        &mut self,
+        depth_stencil: ResourceHandle,
+        depth_stencil_view: D3D12_CPU_DESCRIPTOR_HANDLE,
+        clear_flags: D3D12_CLEAR_FLAGS,
+        depth: f32,
+        stencil: u8,
+        rects: &[D3D12_RECT],
+    ) {
+        self.check_resource_usage(
+            depth_stencil,
+            D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES,
+            ResourceUsage::DepthWrite,
+            "ClearDepthStencilView",
+            Location::caller(),
+        );
+        self.cmd_flush_barriers();
+        self.cmdlist.clear_depth_stencil_view(
+            depth_stencil_view,
+            clear_flags,
+            depth,
+            stencil,
+            rects,
+        );
+    }
+
+    pub fn resource_subresource_state(
+        &self,
+        resource_handle: ResourceHandle,
+        subresource: u32,
+    ) -> D3D12_RESOURCE_STATES {
+        self.resource_state(resource_handle)
+            .states
+            .state(subresource)
+    }
+
+    pub fn cmd_set_graphics_pipeline(&mut self, handle: PipelineHandle) {
+        let pipeline_state = self.pipeline_state(handle);
+        if handle != self.current_pipeline {
+            unsafe {
+                self.cmdlist.SetPipelineState(pipeline_state.pso.as_raw());
+                self.cmdlist
+                    .SetGraphicsRootSignature(pipeline_state.rsignature.as_raw());
+                self.current_pipeline = handle;
+            }
+            self.bound_graphics_resources.clear();
+        }
+    }
+
+    pub fn cmd_set_compute_pipeline(&mut self, handle: PipelineHandle) {
+        let pipeline_state = self.pipeline_state(handle);
+        if handle != self.current_pipeline {
+            unsafe {
+                self.cmdlist.SetPipelineState(pipeline_state.pso.as_raw());
+                self.cmdlist
+                    .SetComputeRootSignature(pipeline_state.rsignature.as_raw());
+                self.current_pipeline = handle;
+            }
+            self.bound_compute_resources.clear();
+        }
+    }
+
+    // Fills mips 1..N of a 2D texture from mip 0. sRGB textures and textures created without
+    // ALLOW_UNORDERED_ACCESS are processed in a linear staging copy. The texture is left in
+    // NON_PIXEL_SHADER_RESOURCE state.
+    #[track_caller]
+    pub fn cmd_generate_mips(&mut self, texture_handle: ResourceHandle) -> Result<()> {
+        let desc = self.resource_state(texture_handle).desc;
+        assert!(desc.Dimension == D3D12_RESOURCE_DIMENSION_TEXTURE2D);
+        assert!(desc.DepthOrArraySize == 1 && desc.SampleDesc.Count == 1);
+
+        if desc.MipLevels <= 1 {
+            return Ok(());
+        }
+
+        if self.mipgen_pipeline == INVALID_PIPELINE {
+            // Fall back to compiling the shader source when the precompiled shader is missing.
+            let cs_bytecode = match read_shader("generate_mips.cs.cso") {
+                Err(Error::ShaderLoad { kind, .. }) if kind == io::ErrorKind::NotFound => {
+                    compile_shader(GENERATE_MIPS_HLSL, "main_cs", "cs_5_1", self.log_hook)?
+                }
+                cs_bytecode => cs_bytecode?,
+            };
+            self.mipgen_pipeline = self.create_compute_pipeline_from_bytecode(
+                &mut D3D12_COMPUTE_PIPELINE_STATE_DESC::default(),
+                &cs_bytecode,
+            )?;
+        }
+
+        let is_srgb = is_srgb_format(desc.Format);
+        let use_staging = is_srgb || (desc.Flags & D3D12_RESOURCE_FLAG_ALLOW_UNORDERED_ACCESS) == 0;
+        let format = linear_format(desc.Format);
+
+        let target_handle = if use_staging {
+            let staging_handle = self.create_committed_resource(
+                D3D12_HEAP_TYPE_DEFAULT,
+                D3D12_HEAP_FLAG_NONE,
+                &D3D12_RESOURCE_DESC {
+                    Alignment: 0,
+                    Format: format,
+                    Flags: D3D12_RESOURCE_FLAG_ALLOW_UNORDERED_ACCESS,
+                    ..desc
+                },
+                D3D12_RESOURCE_STATE_COPY_DEST,
+                None,
+            )?;
+            self.cmd_transition_barrier(texture_handle, D3D12_RESOURCE_STATE_COPY_SOURCE);
+            self.cmd_copy_texture_region(
+                &TextureCopyLocation::subresource(self.resource(staging_handle), 0),
+                0,
+                0,
+                0,
+                &TextureCopyLocation::subresource(self.resource(texture_handle), 0),
+                None,
+            );
+            staging_handle
+        } else {
+            texture_handle
+        };
+        let target = self.resource(target_handle);
+
+        self.cmd_transition_barrier(
+            target_handle,
+            D3D12_RESOURCE_STATE_NON_PIXEL_SHADER_RESOURCE,
+        );
+        self.cmd_set_compute_pipeline(self.mipgen_pipeline);
+
+        let descriptor_size =
+            self.gpu_cbv_srv_uav_heaps[self.frame_index as usize].descriptor_size as usize;
+
+        for mip in 1..desc.MipLevels as u32 {
+            let src_width = mip_dimension(desc.Width as u32, mip - 1);
+            let src_height = mip_dimension(desc.Height, mip - 1);
+            let dst_width = mip_dimension(desc.Width as u32, mip);
+            let dst_height = mip_dimension(desc.Height, mip);
+
+            let (cpu_base, gpu_base) = self.allocate_gpu_descriptors(2);
+            self.device.create_shader_resource_view(
+                Some(target),
+                Some(&D3D12_SHADER_RESOURCE_VIEW_DESC {
+                    Format: format,
+                    ViewDimension: D3D12_SRV_DIMENSION_TEXTURE2D,
+                    Shader4ComponentMapping: DEFAULT_SHADER_4_COMPONENT_MAPPING,
+                    u: unsafe {
+                        let mut u: D3D12_SHADER_RESOURCE_VIEW_DESC_u = mem::zeroed();
+                        u.Texture2D_mut().MostDetailedMip = mip - 1;
+                        u.Texture2D_mut().MipLevels = 1;
+                        u
+                    },
+                }),
+                cpu_base,
+            );
+            self.device.create_unordered_access_view(
+                Some(target),
+                None,
+                Some(&D3D12_UNORDERED_ACCESS_VIEW_DESC {
+                    Format: format,
+                    ViewDimension: D3D12_UAV_DIMENSION_TEXTURE2D,
+                    u: unsafe {
+                        let mut u: D3D12_UNORDERED_ACCESS_VIEW_DESC_u = mem::zeroed();
+                        u.Texture2D_mut().MipSlice = mip;
+                        u
+                    },
+                }),
+                D3D12_CPU_DESCRIPTOR_HANDLE {
+                    ptr: cpu_base.ptr + descriptor_size,
+                },
+            );
+
+            self.cmd_transition_subresource_barrier(
+                target_handle,
+                mip,
+                D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
+            );
+            self.cmdlist.set_compute_root_32bit_constants(
+                0,
+                &[src_width, src_height, is_srgb as u32],
+                0,
+            );
+            self.cmdlist.set_compute_root_descriptor_table(1, gpu_base);
+            self.cmd_dispatch((dst_width + 7) / 8, (dst_height + 7) / 8, 1);
+            self.cmd_transition_subresource_barrier(
+                target_handle,
+                mip,
+                D3D12_RESOURCE_STATE_NON_PIXEL_SHADER_RESOURCE,
+            );
+        }
+
+        if use_staging {
+            self.cmd_transition_barrier(target_handle, D3D12_RESOURCE_STATE_COPY_SOURCE);
+            self.cmd_transition_barrier(texture_handle, D3D12_RESOURCE_STATE_COPY_DEST);
+            let texture = self.resource(texture_handle);
+            for mip in 1..desc.MipLevels as u32 {
+                self.cmd_copy_texture_region(
+                    &TextureCopyLocation::subresource(texture, mip),
+                    0,
+                    0,
+                    0,
+                    &TextureCopyLocation::subresource(target, mip),
+                    None,
+                );
+            }
+            self.cmd_transition_barrier(
+                texture_handle,
+                D3D12_RESOURCE_STATE_NON_PIXEL_SHADER_RESOURCE,
+            );
+            self.destroy_resource_deferred(target_handle);
+        }
+        Ok(())
+    }
+
+    pub fn create_graphics_pipeline(
+        &mut self,
+        pso_desc: &mut D3D12_GRAPHICS_PIPELINE_STATE_DESC,
+        vs_name: &str,
+        ps_name: &str,
+    ) -> Result<PipelineHandle> {
+        let vs_bytecode = read_shader(vs_name)?;
+        let ps_bytecode = read_shader(ps_name)?;
+
+        pso_desc.VS = D3D12_SHADER_BYTECODE {
+            pShaderBytecode: vs_bytecode.as_ptr() as *const c_void,
+            BytecodeLength: vs_bytecode.len(),
+        };
+        pso_desc.PS = D3D12_SHADER_BYTECODE {
             pShaderBytecode: ps_bytecode.as_ptr() as *const c_void,
             BytecodeLength: ps_bytecode.len(),
         };
@@ -765,18 +2023,21 @@ impl Context {
 
         let found = self.pipeline_pool.map.get(&hash);
         if found != None {
-            return *found.unwrap();
+            return Ok(*found.unwrap());
         }
 
         let rsignature = {
             let mut rsignature_raw: *mut ID3D12RootSignature = ptr::null_mut();
-            vhr!(self.device.CreateRootSignature(
-                0,
-                vs_bytecode.as_ptr() as *const c_void,
-                vs_bytecode.len(),
-                &ID3D12RootSignature::uuidof(),
-                &mut rsignature_raw as *mut *mut _ as *mut *mut c_void
-            ));
+            hr!(
+                self.device.CreateRootSignature(
+                    0,
+                    vs_bytecode.as_ptr() as *const c_void,
+                    vs_bytecode.len(),
+                    &ID3D12RootSignature::uuidof(),
+                    &mut rsignature_raw as *mut *mut _ as *mut *mut c_void
+                ),
+                self.device
+            )?;
             WeakPtr::from_raw(rsignature_raw)
         };
 
@@ -784,26 +2045,36 @@ impl Context {
 
         let pso = {
             let mut pso_raw: *mut ID3D12PipelineState = ptr::null_mut();
-            vhr!(self.device.CreateGraphicsPipelineState(
-                pso_desc,
-                &ID3D12PipelineState::uuidof(),
-                &mut pso_raw as *mut *mut _ as *mut *mut c_void
-            ));
+            hr!(
+                self.device.CreateGraphicsPipelineState(
+                    pso_desc,
+                    &ID3D12PipelineState::uuidof(),
+                    &mut pso_raw as *mut *mut _ as *mut *mut c_void
+                ),
+                self.device
+            )?;
             WeakPtr::from_raw(pso_raw)
         };
 
         let handle = self.pipeline_pool.add(pso, rsignature);
         self.pipeline_pool.map.insert(hash, handle);
-        handle
+        Ok(handle)
     }
 
     pub fn create_compute_pipeline(
         &mut self,
         pso_desc: &mut D3D12_COMPUTE_PIPELINE_STATE_DESC,
         cs_name: &str,
-    ) -> PipelineHandle {
-        let cs_bytecode = fs::read(format!("data/shaders/{}", cs_name)).unwrap();
+    ) -> Result<PipelineHandle> {
+        let cs_bytecode = read_shader(cs_name)?;
+        self.create_compute_pipeline_from_bytecode(pso_desc, &cs_bytecode)
+    }
 
+    fn create_compute_pipeline_from_bytecode(
+        &mut self,
+        pso_desc: &mut D3D12_COMPUTE_PIPELINE_STATE_DESC,
+        cs_bytecode: &[u8],
+    ) -> Result<PipelineHandle> {
         pso_desc.CS = D3D12_SHADER_BYTECODE {
             pShaderBytecode: cs_bytecode.as_ptr() as *const c_void,
             BytecodeLength: cs_bytecode.len(),
@@ -813,18 +2084,21 @@ impl Context {
 
         let found = self.pipeline_pool.map.get(&hash);
         if found != None {
-            return *found.unwrap();
+            return Ok(*found.unwrap());
         }
 
         let rsignature = {
             let mut rsignature_raw: *mut ID3D12RootSignature = ptr::null_mut();
-            vhr!(self.device.CreateRootSignature(
-                0,
-                cs_bytecode.as_ptr() as *const c_void,
-                cs_bytecode.len(),
-                &ID3D12RootSignature::uuidof(),
-                &mut rsignature_raw as *mut *mut _ as *mut *mut c_void
-            ));
+            hr!(
+                self.device.CreateRootSignature(
+                    0,
+                    cs_bytecode.as_ptr() as *const c_void,
+                    cs_bytecode.len(),
+                    &ID3D12RootSignature::uuidof(),
+                    &mut rsignature_raw as *mut *mut _ as *mut *mut c_void
+                ),
+                self.device
+            )?;
             WeakPtr::from_raw(rsignature_raw)
         };
 
@@ -832,17 +2106,20 @@ impl Context {
 
         let pso = {
             let mut pso_raw: *mut ID3D12PipelineState = ptr::null_mut();
-            vhr!(self.device.CreateComputePipelineState(
-                pso_desc,
-                &ID3D12PipelineState::uuidof(),
-                &mut pso_raw as *mut *mut _ as *mut *mut c_void
-            ));
+            hr!(
+                self.device.CreateComputePipelineState(
+                    pso_desc,
+                    &ID3D12PipelineState::uuidof(),
+                    &mut pso_raw as *mut *mut _ as *mut *mut c_void
+                ),
+                self.device
+            )?;
             WeakPtr::from_raw(pso_raw)
         };
 
         let handle = self.pipeline_pool.add(pso, rsignature);
         self.pipeline_pool.map.insert(hash, handle);
-        handle
+        Ok(handle)
     }
 
     pub fn destroy_pipeline(&mut self, handle: PipelineHandle) {
@@ -857,161 +2134,1776 @@ impl Context {
         }
         assert!(key_to_remove != 0);
 
-        self.pipeline_pool.map.remove(&key_to_remove);
+        self.pipeline_pool.map.remove(&key_to_remove);
+
+        let pipeline = &mut self.pipeline_pool.pipelines[handle.index as usize];
+        let pso_refcount = pipeline.pso.release();
+        let rsignature_refcount = pipeline.rsignature.release();
+        assert!(pso_refcount == 0 && rsignature_refcount == 0);
+    }
+
+    fn cpu_descriptor_heap_mut(
+        &mut self,
+        heap_type: D3D12_DESCRIPTOR_HEAP_TYPE,
+    ) -> &mut CpuDescriptorHeap {
+        match heap_type {
+            D3D12_DESCRIPTOR_HEAP_TYPE_RTV => &mut self.rtv_heap,
+            D3D12_DESCRIPTOR_HEAP_TYPE_DSV => &mut self.dsv_heap,
+            D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV => &mut self.cpu_cbv_srv_uav_heap,
+            _ => panic!("unsupported CPU descriptor heap type {}", heap_type),
+        }
+    }
+
+    pub fn allocate_cpu_descriptors(
+        &mut self,
+        heap_type: D3D12_DESCRIPTOR_HEAP_TYPE,
+        num: u32,
+    ) -> Result<DescriptorAllocation> {
+        let device = self.device;
+        self.cpu_descriptor_heap_mut(heap_type)
+            .allocate(device, num)
+    }
+
+    pub fn free_cpu_descriptors(&mut self, allocation: DescriptorAllocation) {
+        if allocation.heap_type == D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV {
+            // The freed handles may be reused for different views before the frame ends.
+            self.invalidate_descriptor_tables();
+        }
+        self.cpu_descriptor_heap_mut(allocation.heap_type)
+            .free(allocation);
+    }
+
+    // Descriptors owned by `resource`; they are freed together with it.
+    pub fn allocate_resource_descriptors(
+        &mut self,
+        resource: ResourceHandle,
+        heap_type: D3D12_DESCRIPTOR_HEAP_TYPE,
+        num: u32,
+    ) -> Result<DescriptorAllocation> {
+        let allocation = self.allocate_cpu_descriptors(heap_type, num)?;
+        self.resource_state_mut(resource)
+            .descriptors
+            .push(allocation);
+        Ok(allocation)
+    }
+
+    // The view helpers below validate the view against the resource description and allocate a
+    // descriptor owned by the resource. Requesting the same view again returns the same descriptor.
+    pub fn create_buffer_srv(
+        &mut self,
+        resource: ResourceHandle,
+        view: &BufferView,
+    ) -> Result<D3D12_CPU_DESCRIPTOR_HANDLE> {
+        self.create_view(resource, &ViewDesc::BufferSrv(*view))
+    }
+
+    pub fn create_buffer_uav(
+        &mut self,
+        resource: ResourceHandle,
+        view: &BufferView,
+    ) -> Result<D3D12_CPU_DESCRIPTOR_HANDLE> {
+        self.create_view(resource, &ViewDesc::BufferUav(*view))
+    }
+
+    // `offset` and `size` are in bytes and must be multiples of 256.
+    pub fn create_constant_buffer_view(
+        &mut self,
+        resource: ResourceHandle,
+        offset: u64,
+        size: u32,
+    ) -> Result<D3D12_CPU_DESCRIPTOR_HANDLE> {
+        self.create_view(resource, &ViewDesc::ConstantBuffer { offset, size })
+    }
+
+    pub fn create_texture_srv(
+        &mut self,
+        resource: ResourceHandle,
+        view: &TextureView,
+    ) -> Result<D3D12_CPU_DESCRIPTOR_HANDLE> {
+        self.create_view(resource, &ViewDesc::TextureSrv(*view))
+    }
+
+    pub fn create_texture_uav(
+        &mut self,
+        resource: ResourceHandle,
+        view: &TextureView,
+    ) -> Result<D3D12_CPU_DESCRIPTOR_HANDLE> {
+        self.create_view(resource, &ViewDesc::TextureUav(*view))
+    }
+
+    pub fn create_render_target_view(
+        &mut self,
+        resource: ResourceHandle,
+        view: &TextureView,
+    ) -> Result<D3D12_CPU_DESCRIPTOR_HANDLE> {
+        self.create_view(resource, &ViewDesc::RenderTarget(*view))
+    }
+
+    pub fn create_depth_stencil_view(
+        &mut self,
+        resource: ResourceHandle,
+        view: &TextureView,
+        flags: D3D12_DSV_FLAGS,
+    ) -> Result<D3D12_CPU_DESCRIPTOR_HANDLE> {
+        self.create_view(resource, &ViewDesc::DepthStencil(*view, flags))
+    }
+
+    fn create_view(
+        &mut self,
+        resource_handle: ResourceHandle,
+        view: &ViewDesc,
+    ) -> Result<D3D12_CPU_DESCRIPTOR_HANDLE> {
+        let resource = self.resource_state(resource_handle);
+        if let Some(cpu_handle) = resource.views.get(view) {
+            return Ok(*cpu_handle);
+        }
+        let (ptr, desc) = (resource.ptr, resource.desc);
+        let heap_type = view.heap_type();
+        let cpu_handle = self
+            .allocate_resource_descriptors(resource_handle, heap_type, 1)?
+            .cpu_handle;
+        self.write_view(ptr, &desc, view, cpu_handle);
+        self.resource_state_mut(resource_handle)
+            .views
+            .insert(*view, cpu_handle);
+        Ok(cpu_handle)
+    }
+
+    fn write_view(
+        &self,
+        ptr: Resource,
+        desc: &D3D12_RESOURCE_DESC,
+        view: &ViewDesc,
+        cpu_handle: D3D12_CPU_DESCRIPTOR_HANDLE,
+    ) {
+        match *view {
+            ViewDesc::BufferSrv(ref buffer_view) => self.device.create_shader_resource_view(
+                Some(ptr),
+                Some(&buffer_srv_desc(desc, buffer_view)),
+                cpu_handle,
+            ),
+            ViewDesc::BufferUav(ref buffer_view) => self.device.create_unordered_access_view(
+                Some(ptr),
+                None,
+                Some(&buffer_uav_desc(desc, buffer_view)),
+                cpu_handle,
+            ),
+            ViewDesc::ConstantBuffer { offset, size } => self.device.create_constant_buffer_view(
+                &constant_buffer_view_desc(desc, ptr.get_gpu_virtual_address(), offset, size),
+                cpu_handle,
+            ),
+            ViewDesc::TextureSrv(ref texture_view) => self.device.create_shader_resource_view(
+                Some(ptr),
+                Some(&texture_srv_desc(desc, texture_view)),
+                cpu_handle,
+            ),
+            ViewDesc::TextureUav(ref texture_view) => self.device.create_unordered_access_view(
+                Some(ptr),
+                None,
+                Some(&texture_uav_desc(desc, texture_view)),
+                cpu_handle,
+            ),
+            ViewDesc::RenderTarget(ref texture_view) => self.device.create_render_target_view(
+                ptr,
+                Some(&render_target_view_desc(desc, texture_view)),
+                cpu_handle,
+            ),
+            ViewDesc::DepthStencil(ref texture_view, flags) => {
+                self.device.create_depth_stencil_view(
+                    ptr,
+                    Some(&depth_stencil_view_desc(desc, texture_view, flags)),
+                    cpu_handle,
+                )
+            }
+        }
+    }
+
+    // `shader_readable` creates the buffer typeless so that `depth_srv` can be used; otherwise
+    // shader access is denied, which lets the driver keep it compressed.
+    pub fn create_depth_buffer(
+        &mut self,
+        format: DepthFormat,
+        width: u32,
+        height: u32,
+        shader_readable: bool,
+    ) -> Result<ResourceHandle> {
+        let mut desc = ResourceDesc::tex2d(
+            if shader_readable {
+                format.typeless_format()
+            } else {
+                format.dsv_format()
+            },
+            width as u64,
+            height,
+        );
+        desc.Flags = D3D12_RESOURCE_FLAG_ALLOW_DEPTH_STENCIL;
+        if !shader_readable {
+            desc.Flags |= D3D12_RESOURCE_FLAG_DENY_SHADER_RESOURCE;
+        }
+        self.create_committed_resource(
+            D3D12_HEAP_TYPE_DEFAULT,
+            D3D12_HEAP_FLAG_NONE,
+            &desc,
+            D3D12_RESOURCE_STATE_DEPTH_WRITE,
+            Some(&depth_clear_value(format)),
+        )
+    }
+
+    // A depth buffer with the size of the swap chain, recreated by `resize_window_depth_buffers`.
+    pub fn create_window_depth_buffer(
+        &mut self,
+        format: DepthFormat,
+        shader_readable: bool,
+    ) -> Result<ResourceHandle> {
+        let handle = self.create_depth_buffer(
+            format,
+            self.resolution[0],
+            self.resolution[1],
+            shader_readable,
+        )?;
+        self.window_depth_buffers.push(handle);
+        Ok(handle)
+    }
+
+    // Brings every window depth buffer to the current `resolution`. The GPU must be idle.
+    pub fn resize_window_depth_buffers(&mut self) -> Result<()> {
+        for handle in self.window_depth_buffers.clone() {
+            self.resize_texture(handle, self.resolution[0], self.resolution[1])?;
+        }
+        Ok(())
+    }
+
+    pub fn depth_stencil_view(
+        &mut self,
+        depth_buffer: ResourceHandle,
+    ) -> Result<D3D12_CPU_DESCRIPTOR_HANDLE> {
+        let format = self.depth_format(depth_buffer);
+        self.create_depth_stencil_view(
+            depth_buffer,
+            &TextureView::whole().with_format(format.dsv_format()),
+            D3D12_DSV_FLAG_NONE,
+        )
+    }
+
+    // The depth plane of a buffer created with `shader_readable`.
+    pub fn depth_srv(
+        &mut self,
+        depth_buffer: ResourceHandle,
+    ) -> Result<D3D12_CPU_DESCRIPTOR_HANDLE> {
+        let format = self.depth_format(depth_buffer);
+        self.create_texture_srv(
+            depth_buffer,
+            &TextureView::whole().with_format(format.depth_srv_format()),
+        )
+    }
+
+    fn depth_format(&self, depth_buffer: ResourceHandle) -> DepthFormat {
+        let format = self.resource_state(depth_buffer).desc.Format;
+        DepthFormat::from_resource_format(format)
+            .unwrap_or_else(|| panic!("resource format {} is not a depth format", format))
+    }
+
+    // Recreates a committed default-heap texture with a new size, keeping its handle and the
+    // descriptors of its cached views; descriptor tables copied from them are invalidated. The GPU
+    // must be done with the old resource.
+    pub fn resize_texture(
+        &mut self,
+        handle: ResourceHandle,
+        width: u32,
+        height: u32,
+    ) -> Result<()> {
+        let resource = self.resource_state(handle);
+        let mut desc = resource.desc;
+        assert!(desc.Dimension == D3D12_RESOURCE_DIMENSION_TEXTURE2D);
+        desc.Width = width as u64;
+        desc.Height = height;
+        let depth_format = if desc.Flags & D3D12_RESOURCE_FLAG_ALLOW_DEPTH_STENCIL != 0 {
+            DepthFormat::from_resource_format(desc.Format)
+        } else {
+            None
+        };
+        let (initial_state, clear_value) = match depth_format {
+            Some(format) => (
+                D3D12_RESOURCE_STATE_DEPTH_WRITE,
+                Some(depth_clear_value(format)),
+            ),
+            None => (D3D12_RESOURCE_STATE_COMMON, None),
+        };
+
+        let ptr = {
+            let mut resource_raw: *mut ID3D12Resource = ptr::null_mut();
+            hr!(
+                self.device.CreateCommittedResource(
+                    &HeapProperties::new(D3D12_HEAP_TYPE_DEFAULT),
+                    D3D12_HEAP_FLAG_NONE,
+                    &desc,
+                    initial_state,
+                    match clear_value.as_ref() {
+                        Some(clear_value) => clear_value,
+                        None => ptr::null(),
+                    },
+                    &ID3D12Resource::uuidof(),
+                    &mut resource_raw as *mut *mut _ as *mut *mut c_void
+                ),
+                self.device
+            )?;
+            WeakPtr::from_raw(resource_raw)
+        };
+
+        self.barrier_queue.remove_resource(handle);
+        let resource = self.resource_state_mut(handle);
+        let refcount = resource.ptr.release();
+        assert!(refcount == 0);
+        resource.ptr = ptr;
+        resource.desc = desc;
+        resource.states = SubresourceStates::Uniform(initial_state);
+        resource.pending_splits.clear();
+        let name = resource.name.clone();
+        let views: Vec<(ViewDesc, D3D12_CPU_DESCRIPTOR_HANDLE)> = resource
+            .views
+            .iter()
+            .map(|(view, cpu_handle)| (*view, *cpu_handle))
+            .collect();
+
+        if !name.is_empty() {
+            self.set_resource_name(handle, &name);
+        }
+        for (view, cpu_handle) in views {
+            if view.heap_type() == D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV {
+                self.invalidate_descriptor_tables();
+            }
+            self.write_view(ptr, &desc, &view, cpu_handle);
+        }
+        Ok(())
+    }
+
+    // The descriptor of a view created earlier, if any.
+    pub fn find_view(
+        &self,
+        resource: ResourceHandle,
+        view: &ViewDesc,
+    ) -> Option<D3D12_CPU_DESCRIPTOR_HANDLE> {
+        self.resource_state(resource).views.get(view).copied()
+    }
+
+    // Reserves a stable slot in the bindless region of the shader-visible heap and returns its
+    // index (as seen by shaders through `bindless_descriptor_table`) and the CPU handle to create
+    // the view at. The slot belongs to `resource` and is freed when it is destroyed.
+    pub fn allocate_bindless_descriptor(
+        &mut self,
+        resource: ResourceHandle,
+    ) -> (u32, D3D12_CPU_DESCRIPTOR_HANDLE) {
+        self.validate_resource_state(resource);
+        assert!(
+            self.capabilities.supports_bindless(),
+            "bindless descriptors need resource binding tier 2"
+        );
+        let index = self
+            .bindless_allocator
+            .allocate(1)
+            .expect("bindless descriptor region is full");
+        self.resource_state_mut(resource)
+            .bindless_indices
+            .push(index);
+        let cpu_handle = D3D12_CPU_DESCRIPTOR_HANDLE {
+            ptr: self.bindless_heap.cpu_base.ptr
+                + index as usize * self.bindless_heap.descriptor_size as usize,
+        };
+        (index, cpu_handle)
+    }
+
+    // Copies an existing (non shader-visible) view into a new bindless slot owned by `resource`.
+    pub fn copy_descriptor_to_bindless_heap(
+        &mut self,
+        resource: ResourceHandle,
+        src_cpu_handle: D3D12_CPU_DESCRIPTOR_HANDLE,
+    ) -> u32 {
+        let (index, dest_cpu_handle) = self.allocate_bindless_descriptor(resource);
+        unsafe {
+            self.device.CopyDescriptorsSimple(
+                1,
+                dest_cpu_handle,
+                src_cpu_handle,
+                D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV,
+            )
+        };
+        index
+    }
+
+    fn free_bindless_descriptor(&mut self, index: u32) {
+        self.bindless_allocator.free(index, 1);
+    }
+
+    // Base of the whole bindless region, for an unbounded descriptor table, e.g.
+    // `DescriptorTable(SRV(t0, space = 1, numDescriptors = unbounded,
+    //                      flags = DESCRIPTORS_VOLATILE))`.
+    pub fn bindless_descriptor_table(&self) -> D3D12_GPU_DESCRIPTOR_HANDLE {
+        self.bindless_heap.gpu_base
+    }
+
+    pub fn allocate_gpu_descriptors(
+        &mut self,
+        num: u32,
+    ) -> (D3D12_CPU_DESCRIPTOR_HANDLE, D3D12_GPU_DESCRIPTOR_HANDLE) {
+        self.gpu_cbv_srv_uav_heaps[self.frame_index as usize].allocate_gpu_descriptors(num)
+    }
+
+    // Memory is valid until the end of the current frame. `alignment` must be a power of two
+    // (256 for constant buffers).
+    pub fn allocate_upload_memory(
+        &mut self,
+        size: u32,
+        alignment: u32,
+    ) -> Result<(*mut c_void, D3D12_GPU_VIRTUAL_ADDRESS)> {
+        let (cpu_addr, gpu_addr, _, _) = self.allocate_upload_region(size, alignment)?;
+        Ok((cpu_addr, gpu_addr))
+    }
+
+    // Copies `data` into this frame's upload memory and returns its address for
+    // set_*_root_constant_buffer_view. In debug builds the layout of `T` is checked against HLSL
+    // cbuffer packing rules.
+    pub fn upload_constant_buffer<T: ConstantBufferData>(
+        &mut self,
+        data: &T,
+    ) -> Result<D3D12_GPU_VIRTUAL_ADDRESS> {
+        if cfg!(debug_assertions) {
+            let errors = hlsl_packing_errors(&T::fields(), mem::size_of::<T>());
+            assert!(
+                errors.is_empty(),
+                "{} doesn't match HLSL cbuffer packing:\n{}",
+                std::any::type_name::<T>(),
+                errors.join("\n")
+            );
+        }
+        let size = (mem::size_of::<T>() as u32 + 255) & !0xff;
+        let (cpu_addr, gpu_addr) =
+            self.allocate_upload_memory(size, D3D12_CONSTANT_BUFFER_DATA_PLACEMENT_ALIGNMENT)?;
+        unsafe {
+            ptr::copy_nonoverlapping(
+                data as *const T as *const u8,
+                cpu_addr as *mut u8,
+                mem::size_of::<T>(),
+            )
+        };
+        Ok(gpu_addr)
+    }
+
+    pub fn allocate_upload_buffer_region(
+        &mut self,
+        size: u32,
+        alignment: u32,
+    ) -> Result<(*mut c_void, WeakPtr<ID3D12Resource>, u64)> {
+        let (cpu_addr, _, buffer, offset) = self.allocate_upload_region(size, alignment)?;
+        Ok((cpu_addr, buffer, offset))
+    }
+
+    fn allocate_upload_region(
+        &mut self,
+        size: u32,
+        alignment: u32,
+    ) -> Result<(*mut c_void, D3D12_GPU_VIRTUAL_ADDRESS, Resource, u64)> {
+        let completed_fence_value = unsafe { self.frame_fence.GetCompletedValue() };
+        self.upload_ring
+            .allocate(self.device, size, alignment, completed_fence_value)
+    }
+
+    #[inline]
+    pub fn copy_descriptors_to_gpu_heap(
+        &mut self,
+        num_descriptors: u32,
+        src_cpu_base: D3D12_CPU_DESCRIPTOR_HANDLE,
+    ) -> D3D12_GPU_DESCRIPTOR_HANDLE {
+        let (dest_cpu_base, dest_gpu_base) = self.allocate_gpu_descriptors(num_descriptors);
+        unsafe {
+            self.device.CopyDescriptorsSimple(
+                num_descriptors,
+                dest_cpu_base,
+                src_cpu_base,
+                D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV,
+            )
+        };
+        dest_gpu_base
+    }
+
+    // Copies a descriptor table made of arbitrary (not necessarily adjacent) CPU descriptors. A
+    // table with the same sources as one copied earlier in the frame reuses that copy.
+    pub fn copy_descriptor_table_to_gpu_heap(
+        &mut self,
+        src_cpu_handles: &[D3D12_CPU_DESCRIPTOR_HANDLE],
+    ) -> D3D12_GPU_DESCRIPTOR_HANDLE {
+        let frame_index = self.frame_index as usize;
+        copy_descriptor_table(
+            self.device,
+            &mut self.gpu_cbv_srv_uav_heaps[frame_index],
+            &mut self.descriptor_table_caches[frame_index],
+            D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV,
+            src_cpu_handles,
+        )
+    }
+
+    // Returns the existing sampler when one with the same description was created before.
+    pub fn create_sampler(&mut self, desc: &D3D12_SAMPLER_DESC) -> Sampler {
+        let (sampler, is_new) = self.sampler_cache.get_or_insert(desc);
+        if is_new {
+            let offset = sampler.index() as usize * self.cpu_sampler_heap.descriptor_size as usize;
+            unsafe {
+                self.device.CreateSampler(
+                    desc,
+                    D3D12_CPU_DESCRIPTOR_HANDLE {
+                        ptr: self.cpu_sampler_heap.cpu_base.ptr + offset,
+                    },
+                );
+                self.device.CreateSampler(
+                    desc,
+                    D3D12_CPU_DESCRIPTOR_HANDLE {
+                        ptr: self.sampler_heap.cpu_base.ptr + offset,
+                    },
+                );
+            }
+        }
+        sampler
+    }
+
+    // Every sampler created so far, indexed by `Sampler::index`, for an unbounded sampler table.
+    pub fn sampler_descriptor_table(&self) -> D3D12_GPU_DESCRIPTOR_HANDLE {
+        self.sampler_heap.gpu_base
+    }
+
+    // Copies a sampler table into the current frame's region of the shader-visible sampler heap.
+    // Tables are cached per frame like `copy_descriptor_table_to_gpu_heap`.
+    pub fn copy_sampler_table_to_gpu_heap(
+        &mut self,
+        samplers: &[Sampler],
+    ) -> D3D12_GPU_DESCRIPTOR_HANDLE {
+        let cpu_base = self.cpu_sampler_heap.cpu_base;
+        let descriptor_size = self.cpu_sampler_heap.descriptor_size as usize;
+        let src_cpu_handles: Vec<D3D12_CPU_DESCRIPTOR_HANDLE> = samplers
+            .iter()
+            .map(|sampler| D3D12_CPU_DESCRIPTOR_HANDLE {
+                ptr: cpu_base.ptr + sampler.index() as usize * descriptor_size,
+            })
+            .collect();
+        let frame_index = self.frame_index as usize;
+        copy_descriptor_table(
+            self.device,
+            &mut self.gpu_sampler_heaps[frame_index],
+            &mut self.sampler_table_caches[frame_index],
+            D3D12_DESCRIPTOR_HEAP_TYPE_SAMPLER,
+            &src_cpu_handles,
+        )
+    }
+
+    // Drops the cached descriptor tables of every frame. Must be called after a CBV/SRV/UAV
+    // descriptor is rewritten in place, or tables copied from it keep the old view.
+    pub fn invalidate_descriptor_tables(&mut self) {
+        for cache in &mut self.descriptor_table_caches {
+            cache.clear();
+        }
+    }
+
+    // Lookups since the last reset, over all frames.
+    pub fn descriptor_table_cache_stats(&self) -> DescriptorCacheStats {
+        let mut stats = DescriptorCacheStats::default();
+        for cache in &self.descriptor_table_caches {
+            stats.hits += cache.stats().hits;
+            stats.misses += cache.stats().misses;
+        }
+        stats
+    }
+
+    pub fn reset_descriptor_table_cache_stats(&mut self) {
+        for cache in &mut self.descriptor_table_caches {
+            cache.reset_stats();
+        }
+    }
+
+    fn reset_transient_descriptors(&mut self) {
+        let index = self.frame_index as usize;
+        self.gpu_cbv_srv_uav_heaps[index].size = 0;
+        self.descriptor_table_caches[index].clear();
+        self.gpu_sampler_heaps[index].size = 0;
+        self.sampler_table_caches[index].clear();
+    }
+
+    // Rendering should be skipped while the window is minimized; the swap chain can't be resized
+    // to zero.
+    pub fn is_minimized(&self) -> bool {
+        unsafe { IsIconic(self.window) != 0 }
+    }
+
+    // Call between frames. Resizes the swap chain when the client area of the window changed and
+    // returns `true` so that the app can rebuild its size-dependent targets.
+    #[must_use = "size-dependent targets have to be rebuilt when this returns `true`"]
+    pub fn handle_window_resize(&mut self) -> Result<bool> {
+        let (width, height) = unsafe {
+            let mut rect: RECT = mem::zeroed();
+            GetClientRect(self.window, &mut rect as *mut RECT);
+            (rect.right as u32, rect.bottom as u32)
+        };
+        if width == 0 || height == 0 || [width, height] == self.resolution {
+            return Ok(false);
+        }
+        self.resize_swapchain(width, height)?;
+        Ok(true)
+    }
+
+    pub fn resize_swapchain(&mut self, width: u32, height: u32) -> Result<()> {
+        assert!(width > 0 && height > 0);
+        self.wait_for_gpu()?;
+
+        self.release_swap_buffers();
+        hr!(
+            self.swapchain.ResizeBuffers(
+                self.swapchain_config.buffer_count,
+                width,
+                height,
+                self.swapchain_config.format.buffer_format(),
+                self.swapchain_flags(),
+            ),
+            self.device
+        )?;
+        self.resolution = [width, height];
+        self.acquire_swap_buffers()?;
+        self.resize_window_depth_buffers()
+    }
+
+    // Number of frames the CPU may record ahead of the GPU; `frame_index` cycles through them.
+    #[inline]
+    pub fn num_frames_in_flight(&self) -> u32 {
+        self.num_frames_in_flight
+    }
+
+    #[inline]
+    pub fn adapter_info(&self) -> &AdapterInfo {
+        &self.adapter_info
+    }
+
+    #[inline]
+    pub fn capabilities(&self) -> &DeviceCapabilities {
+        &self.capabilities
+    }
+
+    // `color_space` is the one applied, which is sRGB when the output can't present the requested
+    // one.
+    #[inline]
+    pub fn swapchain_config(&self) -> &SwapchainConfig {
+        &self.swapchain_config
+    }
+
+    // Pipelines rendering to the back buffer have to be recreated when the RTV format changes.
+    pub fn set_swapchain_config(&mut self, config: SwapchainConfig) -> Result<()> {
+        assert!(config.buffer_count >= 2 && config.buffer_count <= 16);
+        self.wait_for_gpu()?;
+        self.release_swap_buffers();
+        let old_config = mem::replace(&mut self.swapchain_config, config);
+
+        if old_config.allow_tearing != config.allow_tearing {
+            // The tearing flag can't be changed by `ResizeBuffers`.
+            self.swapchain.release();
+            let mut factory = create_dxgi_factory(self.debug_layer)?;
+            let swapchain = create_swapchain(
+                factory,
+                self.cmdqueue,
+                self.window,
+                &config,
+                self.tearing_supported,
+            );
+            factory.release();
+            self.swapchain = swapchain?;
+        } else {
+            hr!(
+                self.swapchain.ResizeBuffers(
+                    config.buffer_count,
+                    self.resolution[0],
+                    self.resolution[1],
+                    config.format.buffer_format(),
+                    self.swapchain_flags(),
+                ),
+                self.device
+            )?;
+        }
+        self.swapchain_config.color_space = Some(set_swapchain_color_space(
+            self.swapchain,
+            config.color_space(),
+        )?);
+        if old_config.buffer_count != config.buffer_count {
+            self.free_cpu_descriptors(self.swap_buffer_rtvs);
+            self.swap_buffer_rtvs =
+                self.allocate_cpu_descriptors(D3D12_DESCRIPTOR_HEAP_TYPE_RTV, config.buffer_count)?;
+        }
+        self.acquire_swap_buffers()
+    }
+
+    #[inline]
+    fn is_tearing_enabled(&self) -> bool {
+        self.swapchain_config.allow_tearing && self.tearing_supported
+    }
+
+    fn swapchain_flags(&self) -> u32 {
+        if self.is_tearing_enabled() {
+            DXGI_SWAP_CHAIN_FLAG_ALLOW_TEARING
+        } else {
+            0
+        }
+    }
+
+    // The swap chain can't be resized or released while any of its buffers is referenced.
+    fn release_swap_buffers(&mut self) {
+        for handle in mem::replace(&mut self.swap_buffers, Vec::new()) {
+            self.remove_resource(handle);
+        }
+    }
+
+    fn acquire_swap_buffers(&mut self) -> Result<()> {
+        self.swap_buffers = get_swap_buffers(
+            self.device,
+            self.swapchain,
+            &self.swapchain_config,
+            &mut self.resource_pool,
+            &self.swap_buffer_rtvs,
+            self.rtv_heap.descriptor_size,
+            self.resolution,
+        )?;
+        self.back_buffer_index = unsafe { self.swapchain.GetCurrentBackBufferIndex() };
+        Ok(())
+    }
+
+    pub fn end_frame(&mut self, swap_interval: u32) -> Result<()> {
+        self.cmd_flush_barriers();
+        self.cmdlist.close(self.device)?;
+        self.cmdqueue
+            .execute_command_lists(&[self.cmdlist.as_raw() as *mut _]);
+        self.resource_pool.decay_states();
+        self.tag_submitted_readbacks()?;
+
+        self.num_frames += 1;
+
+        let present_flags = if swap_interval == 0 && self.is_tearing_enabled() {
+            DXGI_PRESENT_ALLOW_TEARING
+        } else {
+            0
+        };
+        hr!(
+            self.swapchain.Present(swap_interval, present_flags),
+            self.device
+        )?;
+        hr!(
+            self.cmdqueue
+                .Signal(self.frame_fence.as_raw(), self.num_frames),
+            self.device
+        )?;
+        self.upload_ring.retire(self.num_frames);
+
+        let gpu_num_frames = unsafe { self.frame_fence.GetCompletedValue() };
+
+        if (self.num_frames - gpu_num_frames) >= self.num_frames_in_flight as u64 {
+            let gpu_num_frames = gpu_num_frames + 1;
+            hr!(
+                self.frame_fence
+                    .SetEventOnCompletion(gpu_num_frames, self.frame_fence_event),
+                self.device
+            )?;
+            unsafe {
+                WaitForSingleObject(self.frame_fence_event, INFINITE);
+            }
+        }
+
+        self.frame_index = (self.frame_index + 1) % self.num_frames_in_flight;
+        self.back_buffer_index = unsafe { self.swapchain.GetCurrentBackBufferIndex() };
+        self.reset_transient_descriptors();
+        self.flush_deferred_destroys(self.frame_index as usize);
+        Ok(())
+    }
+
+    pub fn begin_frame(&mut self) -> Result<GraphicsCommandList> {
+        hr!(
+            self.cmdallocs[self.frame_index as usize].Reset(),
+            self.device
+        )?;
+        self.reopen_cmdlist()?;
+        self.barrier_queue.clear_recorded_batches();
+        Ok(self.cmdlist)
+    }
+
+    fn shader_visible_heaps(&self) -> [*mut ID3D12DescriptorHeap; 2] {
+        [
+            self.gpu_cbv_srv_uav_heap.heap.as_raw(),
+            self.gpu_sampler_heap.heap.as_raw(),
+        ]
+    }
+
+    fn reopen_cmdlist(&mut self) -> Result<()> {
+        let index = self.frame_index as usize;
+        hr!(
+            self.cmdlist
+                .Reset(self.cmdallocs[index].as_raw(), ptr::null_mut()),
+            self.device
+        )?;
+        let mut heaps = self.shader_visible_heaps();
+        unsafe {
+            self.cmdlist
+                .SetDescriptorHeaps(heaps.len() as u32, heaps.as_mut_ptr())
+        };
+        self.current_pipeline = INVALID_PIPELINE;
+        self.bound_graphics_resources.clear();
+        self.bound_compute_resources.clear();
+        self.bound_render_targets.clear();
+        Ok(())
+    }
+
+    pub fn create_command_context(&self, queue: QueueType) -> Result<CommandContext> {
+        let cmdallocs = create_command_allocators(
+            self.device,
+            queue.command_list_type(),
+            self.num_frames_in_flight,
+        )?;
+
+        let create_cmdlist = || {
+            let mut rcmdlist: *mut ID3D12GraphicsCommandList1 = ptr::null_mut();
+            hr!(
+                self.device.CreateCommandList(
+                    0,
+                    queue.command_list_type(),
+                    cmdallocs[0].as_raw(),
+                    ptr::null_mut(),
+                    &ID3D12GraphicsCommandList1::uuidof(),
+                    &mut rcmdlist as *mut *mut _ as *mut *mut c_void,
+                ),
+                self.device
+            )?;
+            let cmdlist = GraphicsCommandList::from_raw(rcmdlist);
+            hr!(cmdlist.Close(), self.device)?;
+            Ok(cmdlist)
+        };
+
+        Ok(CommandContext {
+            cmdlist: create_cmdlist()?,
+            queue,
+            fixup_cmdlist: create_cmdlist()?,
+            cmdallocs,
+            allocator_frame: None,
+            local_states: LocalStates::new(),
+            barrier_queue: BarrierQueue::new(),
+            current_pipeline: INVALID_PIPELINE,
+        })
+    }
+
+    // Read-only access for recording CommandContexts, shareable between threads.
+    pub fn recording_view(&self) -> RecordingView<'_> {
+        RecordingView {
+            device: self.device,
+            frame_index: self.frame_index as usize,
+            num_frames: self.num_frames,
+            shader_visible_heaps: self.shader_visible_heaps(),
+            resource_pool: &self.resource_pool,
+            pipeline_pool: &self.pipeline_pool,
+        }
+    }
+
+    pub fn begin_command_context(&self, context: &mut CommandContext) -> Result<()> {
+        self.recording_view().begin_command_context(context)
+    }
+
+    // Submits `contexts` to `queue` in the given order; on the graphics queue everything recorded
+    // so far on `cmdlist` goes first and `cmdlist` is reopened afterwards. The first transition of
+    // every resource in a context is resolved against the state left by the command lists
+    // submitted before it and issued in a small command list executed right before the context.
+    //
+    // Tracked states are updated at submission time and follow the implicit decay rules: after a
+    // copy queue submission every resource it used is back in COMMON, and buffers and
+    // simultaneous-access textures are after a submission on any queue. Work on another queue
+    // that uses the same resources must wait (`queue_wait`) for a sync point signalled after this
+    // call, and resources handed to the compute or copy queue have to be in a state that queue
+    // supports (see `QueueType::supports_state`).
+    pub fn execute_command_contexts(
+        &mut self,
+        queue: QueueType,
+        contexts: &mut [CommandContext],
+    ) -> Result<()> {
+        let mut cmdlists: Vec<*mut ID3D12CommandList> = Vec::new();
+        if queue == QueueType::Graphics {
+            self.cmd_flush_barriers();
+            self.cmdlist.close(self.device)?;
+            cmdlists.push(self.cmdlist.as_raw() as *mut _);
+        }
+
+        let index = self.frame_index as usize;
+        for context in contexts.iter_mut() {
+            assert!(context.queue == queue);
+            context.cmd_flush_barriers(&self.recording_view());
+            context.cmdlist.close(self.device)?;
+
+            let mut fixups = Vec::new();
+            for pending in context.local_states.take_pending() {
+                let resource = self.resource_state_mut(pending.resource);
+                assert!(resource.pending_splits.is_empty());
+                let num_subresources = num_subresources(&resource.desc);
+                let transitions = resource.states.transition(
+                    num_subresources,
+                    pending.subresource,
+                    pending.state_after,
+                );
+                for transition in transitions {
+                    assert!(
+                        queue.supports_state(transition.state_before),
+                        "{:?} queue can't transition a resource from {}; transition it on the \
+                         graphics queue first",
+                        queue,
+                        resource_state_name(transition.state_before)
+                    );
+                    fixups.push(ResourceBarrier::transition_subresource(
+                        resource.ptr,
+                        transition.state_before,
+                        transition.state_after,
+                        transition.subresource,
+                    ));
+                }
+            }
+            for (handle, subresource, state) in context.local_states.final_states() {
+                let resource = self.resource_state_mut(handle);
+                if queue == QueueType::Copy || resource.decays_to_common {
+                    resource.states = SubresourceStates::Uniform(D3D12_RESOURCE_STATE_COMMON);
+                    continue;
+                }
+                let num_subresources = num_subresources(&resource.desc);
+                resource
+                    .states
+                    .transition(num_subresources, subresource, state);
+            }
+
+            if !fixups.is_empty() {
+                hr!(
+                    context
+                        .fixup_cmdlist
+                        .Reset(context.cmdallocs[index].as_raw(), ptr::null_mut()),
+                    self.device
+                )?;
+                context.fixup_cmdlist.resource_barrier(&fixups);
+                context.fixup_cmdlist.close(self.device)?;
+                cmdlists.push(context.fixup_cmdlist.as_raw() as *mut _);
+            }
+            cmdlists.push(context.cmdlist.as_raw() as *mut _);
+        }
+
+        if !cmdlists.is_empty() {
+            self.command_queue(queue).execute_command_lists(&cmdlists);
+        }
+        if queue == QueueType::Graphics {
+            self.resource_pool.decay_states();
+            self.reopen_cmdlist()?;
+        }
+        Ok(())
+    }
+
+    fn command_queue(&self, queue: QueueType) -> CommandQueue {
+        match queue {
+            QueueType::Graphics => self.cmdqueue,
+            QueueType::Compute => self.compute_cmdqueue,
+            QueueType::Copy => self.copy_cmdqueue,
+        }
+    }
+
+    // Signals `queue` after all work submitted to it so far (on the graphics queue this includes
+    // `cmdlist`, which is submitted first).
+    // On the graphics queue this submits `cmdlist` and reopens it, so the pipeline, root arguments
+    // and render targets have to be set again before the next draw or dispatch.
+    pub fn signal_queue(&mut self, queue: QueueType) -> Result<SyncPoint> {
+        if queue == QueueType::Graphics {
+            self.execute_command_contexts(queue, &mut [])?;
+        }
+        self.signal_submitted(queue)
+    }
+
+    fn signal_submitted(&mut self, queue: QueueType) -> Result<SyncPoint> {
+        let index = queue.index();
+        self.queue_fence_values[index] += 1;
+        hr!(
+            self.command_queue(queue).Signal(
+                self.queue_fences[index].as_raw(),
+                self.queue_fence_values[index]
+            ),
+            self.device
+        )?;
+        Ok(SyncPoint {
+            queue,
+            value: self.queue_fence_values[index],
+        })
+    }
+
+    // Makes work submitted to `queue` after this call wait on the GPU until `sync_point` is
+    // reached. On the graphics queue that includes everything recorded on `cmdlist` so far, which
+    // stays open.
+    pub fn queue_wait(&mut self, queue: QueueType, sync_point: SyncPoint) -> Result<()> {
+        assert!(queue != sync_point.queue);
+        hr!(
+            self.command_queue(queue).Wait(
+                self.queue_fences[sync_point.queue.index()].as_raw(),
+                sync_point.value
+            ),
+            self.device
+        )
+    }
+
+    pub fn is_sync_point_complete(&self, sync_point: SyncPoint) -> bool {
+        let fence = self.queue_fences[sync_point.queue.index()];
+        unsafe { fence.GetCompletedValue() >= sync_point.value }
+    }
+
+    pub fn wait_for_sync_point(&self, sync_point: SyncPoint) -> Result<()> {
+        if self.is_sync_point_complete(sync_point) {
+            return Ok(());
+        }
+        let fence = self.queue_fences[sync_point.queue.index()];
+        hr!(
+            fence.SetEventOnCompletion(sync_point.value, self.frame_fence_event),
+            self.device
+        )?;
+        unsafe {
+            WaitForSingleObject(self.frame_fence_event, INFINITE);
+        }
+        Ok(())
+    }
+
+    // Copies `num_bytes` of a buffer starting at `offset` into CPU-readable memory. The buffer is
+    // transitioned to (and left in) COPY_SOURCE. The data is available once the frame has been
+    // executed: poll with `try_take_readback` or block with `wait_readback`.
+    #[track_caller]
+    pub fn cmd_readback_buffer(
+        &mut self,
+        buffer_handle: ResourceHandle,
+        offset: u64,
+        num_bytes: u64,
+    ) -> Result<ReadbackHandle> {
+        assert!(num_bytes > 0 && num_bytes <= u32::max_value() as u64);
+        let buffer = GpuMemoryHeap::new(self.device, num_bytes as u32, D3D12_HEAP_TYPE_READBACK)?;
+
+        self.cmd_transition_barrier(buffer_handle, D3D12_RESOURCE_STATE_COPY_SOURCE);
+        let src = self.resource(buffer_handle);
+        self.cmd_copy_buffer_region(buffer.heap, 0, src, offset, num_bytes);
+
+        Ok(self.add_readback(Readback {
+            buffer,
+            sync_point: None,
+            row_size: num_bytes as usize,
+            row_pitch: num_bytes as usize,
+            num_rows: 1,
+        }))
+    }
+
+    // Reads back one texture subresource. The returned bytes are tightly packed rows (the
+    // row pitch padding of the copy is removed). The subresource is left in COPY_SOURCE.
+    #[track_caller]
+    pub fn cmd_readback_texture(
+        &mut self,
+        texture_handle: ResourceHandle,
+        subresource: u32,
+    ) -> Result<ReadbackHandle> {
+        let desc = self.resource_state(texture_handle).desc;
+        assert!(desc.Dimension != D3D12_RESOURCE_DIMENSION_BUFFER);
+        assert!(subresource < num_subresources(&desc));
+
+        let mut layout: D3D12_PLACED_SUBRESOURCE_FOOTPRINT = unsafe { mem::zeroed() };
+        let mut num_rows = 0;
+        let mut row_size = 0;
+        let mut total_size = 0;
+        unsafe {
+            self.device.GetCopyableFootprints(
+                &desc,
+                subresource,
+                1,
+                0,
+                &mut layout,
+                &mut num_rows,
+                &mut row_size,
+                &mut total_size,
+            )
+        };
+        let buffer = GpuMemoryHeap::new(self.device, total_size as u32, D3D12_HEAP_TYPE_READBACK)?;
+
+        self.cmd_transition_subresource_barrier(
+            texture_handle,
+            subresource,
+            D3D12_RESOURCE_STATE_COPY_SOURCE,
+        );
+        let src = self.resource(texture_handle);
+        self.cmd_copy_texture_region(
+            &TextureCopyLocation::placed_footprint(buffer.heap, &layout),
+            0,
+            0,
+            0,
+            &TextureCopyLocation::subresource(src, subresource),
+            None,
+        );
+
+        Ok(self.add_readback(Readback {
+            buffer,
+            sync_point: None,
+            row_size: row_size as usize,
+            row_pitch: layout.Footprint.RowPitch as usize,
+            num_rows: num_rows as usize * layout.Footprint.Depth as usize,
+        }))
+    }
+
+    fn add_readback(&mut self, readback: Readback) -> ReadbackHandle {
+        self.next_readback_id += 1;
+        self.readbacks.insert(self.next_readback_id, readback);
+        ReadbackHandle {
+            id: self.next_readback_id,
+        }
+    }
+
+    // Called right after `cmdlist` has been submitted.
+    fn tag_submitted_readbacks(&mut self) -> Result<()> {
+        if self.readbacks.values().all(|r| r.sync_point.is_some()) {
+            return Ok(());
+        }
+        let sync_point = self.signal_submitted(QueueType::Graphics)?;
+        for readback in self.readbacks.values_mut() {
+            if readback.sync_point.is_none() {
+                readback.sync_point = Some(sync_point);
+            }
+        }
+        Ok(())
+    }
+
+    // False for a readback that has already been taken.
+    pub fn is_readback_ready(&self, handle: ReadbackHandle) -> bool {
+        match self.readbacks.get(&handle.id).and_then(|r| r.sync_point) {
+            Some(sync_point) => self.is_sync_point_complete(sync_point),
+            None => false,
+        }
+    }
+
+    // Returns the data and releases the readback when the GPU is done with it, `None` otherwise
+    // (including when it has already been taken).
+    pub fn try_take_readback(&mut self, handle: ReadbackHandle) -> Result<Option<Vec<u8>>> {
+        if !self.is_readback_ready(handle) {
+            return Ok(None);
+        }
+        let mut readback = self.readbacks.remove(&handle.id).unwrap();
+        let size = readback.row_pitch * (readback.num_rows - 1) + readback.row_size;
+        let mut data = Vec::with_capacity(readback.row_size * readback.num_rows);
+
+        let mut cpu_base: *mut u8 = ptr::null_mut();
+        let mapped = hr!(
+            readback.buffer.heap.Map(
+                0,
+                &D3D12_RANGE {
+                    Begin: 0,
+                    End: size,
+                },
+                &mut cpu_base as *mut *mut _ as *mut *mut c_void
+            ),
+            self.device
+        );
+        if let Err(err) = mapped {
+            readback.buffer.heap.release();
+            return Err(err);
+        }
+        for row in 0..readback.num_rows {
+            let row_data = unsafe {
+                slice::from_raw_parts(cpu_base.add(row * readback.row_pitch), readback.row_size)
+            };
+            data.extend_from_slice(row_data);
+        }
+        unsafe {
+            readback
+                .buffer
+                .heap
+                .Unmap(0, &D3D12_RANGE { Begin: 0, End: 0 })
+        };
+        readback.buffer.heap.release();
+        Ok(Some(data))
+    }
+
+    // Blocks until the data is available. Submits `cmdlist` first if the copy hasn't been
+    // submitted yet. Returns `None` if the readback has already been taken.
+    pub fn wait_readback(&mut self, handle: ReadbackHandle) -> Result<Option<Vec<u8>>> {
+        let sync_point = match self.readbacks.get(&handle.id) {
+            Some(readback) => readback.sync_point,
+            None => return Ok(None),
+        };
+        if sync_point.is_none() {
+            self.execute_command_contexts(QueueType::Graphics, &mut [])?;
+            self.tag_submitted_readbacks()?;
+        }
+        let sync_point = self.readbacks[&handle.id].sync_point.unwrap();
+        self.wait_for_sync_point(sync_point)?;
+        self.try_take_readback(handle)
+    }
+
+    // Streaming uploads run on the copy queue and return as soon as the data is in staging memory;
+    // the returned sync point completes when the copy is done. The destination must be in COMMON
+    // state: the copy queue promotes it to COPY_DEST and it decays back to COMMON afterwards, so
+    // the graphics queue has to `queue_wait` on the sync point before transitioning or using it.
+    // Uploads block only when the staging ring is full, until the oldest upload in flight is done.
+    pub fn upload_buffer(
+        &mut self,
+        dst_handle: ResourceHandle,
+        dst_offset: u64,
+        data: &[u8],
+    ) -> Result<SyncPoint> {
+        let dst = self.upload_destination(dst_handle)?;
+        let max_chunk_size = STREAMING_STAGING_CAPACITY as u64 / 2;
+        let mut offset = 0;
+        while offset < data.len() {
+            let size = (data.len() - offset).min(max_chunk_size as usize);
+            let staging_offset = self.allocate_staging_memory(size as u64, 16)?;
+            let uploader = self.uploader.as_mut().unwrap();
+            unsafe {
+                ptr::copy_nonoverlapping(
+                    data.as_ptr().add(offset),
+                    uploader.staging.cpu_base.add(staging_offset as usize),
+                    size,
+                )
+            };
+            uploader.cmdlist.copy_buffer_region(
+                dst,
+                dst_offset + offset as u64,
+                uploader.staging.heap,
+                staging_offset,
+                size as u64,
+            );
+            offset += size;
+        }
+        self.submit_uploads()
+    }
+
+    // `data` holds one tightly packed (rows of `width * bytes per texel`) slice per subresource,
+    // starting at `first_subresource`.
+    pub fn upload_texture(
+        &mut self,
+        dst_handle: ResourceHandle,
+        first_subresource: u32,
+        data: &[&[u8]],
+    ) -> Result<SyncPoint> {
+        let dst = self.upload_destination(dst_handle)?;
+        let desc = self.resource_state(dst_handle).desc;
+        assert!(first_subresource as usize + data.len() <= num_subresources(&desc) as usize);
+        let max_chunk_size = STREAMING_STAGING_CAPACITY as u64 / 2;
+
+        for (i, subresource_data) in data.iter().enumerate() {
+            let subresource = first_subresource + i as u32;
+            let mut layout: D3D12_PLACED_SUBRESOURCE_FOOTPRINT = unsafe { mem::zeroed() };
+            let mut num_rows = 0;
+            let mut row_size = 0;
+            let mut total_size = 0;
+            unsafe {
+                self.device.GetCopyableFootprints(
+                    &desc,
+                    subresource,
+                    1,
+                    0,
+                    &mut layout,
+                    &mut num_rows,
+                    &mut row_size,
+                    &mut total_size,
+                )
+            };
+            let num_slices = layout.Footprint.Depth as usize;
+            let num_rows = num_rows as usize;
+            let row_size = row_size as usize;
+            let row_pitch = layout.Footprint.RowPitch as usize;
+            assert!(subresource_data.len() == row_size * num_rows * num_slices);
+
+            // Subresources that don't fit in a chunk are copied a range of rows at a time, one
+            // slice at a time. Rows are rows of blocks for block-compressed formats.
+            let (slices_per_chunk, rows_per_chunk) = if total_size <= max_chunk_size {
+                (num_slices, num_rows)
+            } else {
+                let rows = (max_chunk_size / row_pitch as u64) as usize;
+                assert!(rows > 0);
+                (1, rows.min(num_rows))
+            };
+            let block_height = (layout.Footprint.Height as usize / num_rows).max(1);
+
+            for first_slice in (0..num_slices).step_by(slices_per_chunk) {
+                let mut first_row = 0;
+                while first_row < num_rows {
+                    let rows = (num_rows - first_row).min(rows_per_chunk);
+                    let staging_offset = self.allocate_staging_memory(
+                        (rows * slices_per_chunk * row_pitch) as u64,
+                        D3D12_TEXTURE_DATA_PLACEMENT_ALIGNMENT as u64,
+                    )?;
+                    let uploader = self.uploader.as_mut().unwrap();
+                    for slice in 0..slices_per_chunk {
+                        for row in 0..rows {
+                            let src_row = (first_slice + slice) * num_rows + first_row + row;
+                            let dst_row = slice * rows + row;
+                            unsafe {
+                                ptr::copy_nonoverlapping(
+                                    subresource_data.as_ptr().add(src_row * row_size),
+                                    uploader
+                                        .staging
+                                        .cpu_base
+                                        .add(staging_offset as usize + dst_row * row_pitch),
+                                    row_size,
+                                )
+                            };
+                        }
+                    }
+
+                    let y = first_row * block_height;
+                    let mut chunk_layout = layout;
+                    chunk_layout.Offset = staging_offset;
+                    chunk_layout.Footprint.Height =
+                        (rows * block_height).min(layout.Footprint.Height as usize - y) as u32;
+                    chunk_layout.Footprint.Depth = slices_per_chunk as u32;
+                    uploader.cmdlist.copy_texture_region(
+                        &TextureCopyLocation::subresource(dst, subresource),
+                        0,
+                        y as u32,
+                        first_slice as u32,
+                        &TextureCopyLocation::placed_footprint(
+                            uploader.staging.heap,
+                            &chunk_layout,
+                        ),
+                        None,
+                    );
+                    first_row += rows;
+                }
+            }
+        }
+        self.submit_uploads()
+    }
+
+    fn upload_destination(&mut self, handle: ResourceHandle) -> Result<Resource> {
+        let resource = self.resource_state(handle);
+        assert!(resource.pending_splits.is_empty());
+        assert!(
+            resource.states == SubresourceStates::Uniform(D3D12_RESOURCE_STATE_COMMON),
+            "streaming upload destination must be in COMMON state"
+        );
+        let ptr = resource.ptr;
+        self.begin_uploads()?;
+        Ok(ptr)
+    }
+
+    fn begin_uploads(&mut self) -> Result<()> {
+        if self.uploader.is_none() {
+            let cmdalloc = {
+                let mut rcmdalloc: *mut ID3D12CommandAllocator = ptr::null_mut();
+                hr!(
+                    self.device.CreateCommandAllocator(
+                        D3D12_COMMAND_LIST_TYPE_COPY,
+                        &ID3D12CommandAllocator::uuidof(),
+                        &mut rcmdalloc as *mut *mut _ as *mut *mut c_void,
+                    ),
+                    self.device
+                )?;
+                WeakPtr::from_raw(rcmdalloc)
+            };
+            let cmdlist = {
+                let mut rcmdlist: *mut ID3D12GraphicsCommandList1 = ptr::null_mut();
+                hr!(
+                    self.device.CreateCommandList(
+                        0,
+                        D3D12_COMMAND_LIST_TYPE_COPY,
+                        cmdalloc.as_raw(),
+                        ptr::null_mut(),
+                        &ID3D12GraphicsCommandList1::uuidof(),
+                        &mut rcmdlist as *mut *mut _ as *mut *mut c_void,
+                    ),
+                    self.device
+                )?;
+                GraphicsCommandList::from_raw(rcmdlist)
+            };
+            hr!(cmdlist.Close(), self.device)?;
+            self.uploader = Some(StreamingUploader {
+                staging: GpuMemoryHeap::new(
+                    self.device,
+                    STREAMING_STAGING_CAPACITY,
+                    D3D12_HEAP_TYPE_UPLOAD,
+                )?,
+                ring: StagingRing::new(STREAMING_STAGING_CAPACITY as u64),
+                cmdlist,
+                cmdallocs: VecDeque::from(vec![(cmdalloc, 0)]),
+                is_recording: false,
+            });
+        }
+
+        let completed = unsafe { self.queue_fences[QueueType::Copy.index()].GetCompletedValue() };
+        let uploader = self.uploader.as_mut().unwrap();
+        if uploader.is_recording {
+            return Ok(());
+        }
+        let cmdalloc = match uploader.cmdallocs.front() {
+            Some(&(cmdalloc, fence_value)) if fence_value <= completed => {
+                uploader.cmdallocs.pop_front();
+                cmdalloc
+            }
+            _ => {
+                let mut rcmdalloc: *mut ID3D12CommandAllocator = ptr::null_mut();
+                hr!(
+                    self.device.CreateCommandAllocator(
+                        D3D12_COMMAND_LIST_TYPE_COPY,
+                        &ID3D12CommandAllocator::uuidof(),
+                        &mut rcmdalloc as *mut *mut _ as *mut *mut c_void,
+                    ),
+                    self.device
+                )?;
+                WeakPtr::from_raw(rcmdalloc)
+            }
+        };
+        hr!(cmdalloc.Reset(), self.device)?;
+        hr!(
+            uploader.cmdlist.Reset(cmdalloc.as_raw(), ptr::null_mut()),
+            self.device
+        )?;
+        // The allocator is tagged with its fence value in `submit_uploads`.
+        uploader.cmdallocs.push_back((cmdalloc, u64::max_value()));
+        uploader.is_recording = true;
+        Ok(())
+    }
+
+    fn submit_uploads(&mut self) -> Result<SyncPoint> {
+        let uploader = self.uploader.as_mut().unwrap();
+        assert!(uploader.is_recording);
+        uploader.is_recording = false;
+        uploader.cmdlist.close(self.device)?;
+        self.copy_cmdqueue
+            .execute_command_lists(&[uploader.cmdlist.as_raw() as *mut _]);
+
+        let sync_point = self.signal_queue(QueueType::Copy)?;
+        let uploader = self.uploader.as_mut().unwrap();
+        uploader.cmdallocs.back_mut().unwrap().1 = sync_point.value;
+        uploader.ring.submit(sync_point.value);
+        Ok(sync_point)
+    }
+
+    // Throttles by staging capacity: when the ring is full, the uploads recorded so far are
+    // submitted and the CPU waits for the oldest upload in flight.
+    fn allocate_staging_memory(&mut self, size: u64, alignment: u64) -> Result<u64> {
+        assert!(
+            size <= STREAMING_STAGING_CAPACITY as u64,
+            "upload of {} bytes doesn't fit in the staging ring",
+            size
+        );
+        loop {
+            let completed =
+                unsafe { self.queue_fences[QueueType::Copy.index()].GetCompletedValue() };
+            let uploader = self.uploader.as_mut().unwrap();
+            uploader.ring.reclaim(completed);
+            if let Some(offset) = uploader.ring.allocate(size, alignment) {
+                return Ok(offset);
+            }
+
+            if uploader.ring.has_unsubmitted() {
+                self.submit_uploads()?;
+                self.begin_uploads()?;
+            }
+            let uploader = self.uploader.as_ref().unwrap();
+            let fence_value = uploader.ring.oldest_fence_value().unwrap();
+            self.wait_for_sync_point(SyncPoint {
+                queue: QueueType::Copy,
+                value: fence_value,
+            })?;
+        }
+    }
+
+    pub fn wait_for_gpu(&mut self) -> Result<()> {
+        self.num_frames += 1;
+
+        hr!(
+            self.cmdqueue
+                .Signal(self.frame_fence.as_raw(), self.num_frames),
+            self.device
+        )?;
+        hr!(
+            self.frame_fence
+                .SetEventOnCompletion(self.num_frames, self.frame_fence_event),
+            self.device
+        )?;
+        unsafe {
+            WaitForSingleObject(self.frame_fence_event, INFINITE);
+        }
+        for queue in &[QueueType::Compute, QueueType::Copy] {
+            let sync_point = self.signal_queue(*queue)?;
+            self.wait_for_sync_point(sync_point)?;
+        }
+
+        self.reset_transient_descriptors();
+        for frame_index in 0..self.deferred_destroys.len() {
+            self.flush_deferred_destroys(frame_index);
+        }
+        Ok(())
+    }
+
+    pub fn back_buffer(&self) -> (ResourceHandle, D3D12_CPU_DESCRIPTOR_HANDLE) {
+        let offset = self.back_buffer_index * self.rtv_heap.descriptor_size;
+        let handle = D3D12_CPU_DESCRIPTOR_HANDLE {
+            ptr: self.swap_buffer_rtvs.cpu_handle.ptr + offset as usize,
+        };
+        (self.swap_buffers[self.back_buffer_index as usize], handle)
+    }
+}
+
+impl RecordingView<'_> {
+    #[inline]
+    pub fn resource(&self, handle: ResourceHandle) -> WeakPtr<ID3D12Resource> {
+        self.resource_pool.get(handle).ptr
+    }
+
+    #[inline]
+    pub fn resource_desc(&self, handle: ResourceHandle) -> D3D12_RESOURCE_DESC {
+        self.resource_pool.get(handle).desc
+    }
 
-        let pipeline = &mut self.pipeline_pool.pipelines[handle.index as usize];
-        let pso_refcount = pipeline.pso.release();
-        let rsignature_refcount = pipeline.rsignature.release();
-        assert!(pso_refcount == 0 && rsignature_refcount == 0);
+    // Opens `context` for recording in the current frame. The per-frame allocator is reset only on
+    // the first begin in a frame, so a context can be executed and recorded again within the same
+    // frame.
+    pub fn begin_command_context(&self, context: &mut CommandContext) -> Result<()> {
+        let index = self.frame_index;
+        if context.allocator_frame != Some(self.num_frames) {
+            hr!(context.cmdallocs[index].Reset(), self.device)?;
+            context.allocator_frame = Some(self.num_frames);
+        }
+        hr!(
+            context
+                .cmdlist
+                .Reset(context.cmdallocs[index].as_raw(), ptr::null_mut()),
+            self.device
+        )?;
+        if context.queue != QueueType::Copy {
+            let mut heaps = self.shader_visible_heaps;
+            unsafe {
+                context
+                    .cmdlist
+                    .SetDescriptorHeaps(heaps.len() as u32, heaps.as_mut_ptr())
+            };
+        }
+        context.local_states.clear();
+        context.barrier_queue = BarrierQueue::new();
+        context.current_pipeline = INVALID_PIPELINE;
+        Ok(())
     }
+}
 
-    pub fn allocate_cpu_descriptors(
-        &mut self,
-        heap_type: D3D12_DESCRIPTOR_HEAP_TYPE,
-        num: u32,
-    ) -> D3D12_CPU_DESCRIPTOR_HANDLE {
-        match heap_type {
-            D3D12_DESCRIPTOR_HEAP_TYPE_RTV => self.rtv_heap.allocate_cpu_descriptors(num),
-            D3D12_DESCRIPTOR_HEAP_TYPE_DSV => self.dsv_heap.allocate_cpu_descriptors(num),
-            D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV => {
-                self.cpu_cbv_srv_uav_heap.allocate_cpu_descriptors(num)
-            }
-            _ => {
-                assert!(false);
-                D3D12_CPU_DESCRIPTOR_HANDLE { ptr: 0 }
-            }
+impl CommandContext {
+    pub fn destroy(&mut self) {
+        self.cmdlist.release();
+        self.fixup_cmdlist.release();
+        for cmdalloc in self.cmdallocs.iter_mut() {
+            cmdalloc.release();
         }
     }
 
-    pub fn allocate_gpu_descriptors(
+    pub fn cmd_transition_barrier(
         &mut self,
-        num: u32,
-    ) -> (D3D12_CPU_DESCRIPTOR_HANDLE, D3D12_GPU_DESCRIPTOR_HANDLE) {
-        self.gpu_cbv_srv_uav_heaps[self.frame_index as usize].allocate_gpu_descriptors(num)
+        d3d: &RecordingView,
+        resource_handle: ResourceHandle,
+        state_after: D3D12_RESOURCE_STATES,
+    ) {
+        self.cmd_transition_subresource_barrier(
+            d3d,
+            resource_handle,
+            D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES,
+            state_after,
+        );
     }
 
-    pub fn allocate_upload_memory(
+    pub fn cmd_transition_subresource_barrier(
         &mut self,
-        size: u32,
-    ) -> (*mut c_void, D3D12_GPU_VIRTUAL_ADDRESS) {
-        let index = self.frame_index as usize;
+        d3d: &RecordingView,
+        resource_handle: ResourceHandle,
+        subresource: u32,
+        state_after: D3D12_RESOURCE_STATES,
+    ) {
+        assert!(
+            self.queue.supports_state(state_after),
+            "{:?} queue can't transition a resource to {}",
+            self.queue,
+            resource_state_name(state_after)
+        );
+        let num_subresources = num_subresources(&d3d.resource_desc(resource_handle));
+        let transitions = self.local_states.transition(
+            resource_handle,
+            num_subresources,
+            subresource,
+            state_after,
+        );
 
-        let (cpu_base, gpu_base) = self.gpu_upload_memory_heaps[index].allocate(size);
-        if cpu_base == ptr::null_mut() && gpu_base == 0 {
-            self.cmdlist.close();
-            self.cmdqueue
-                .execute_command_lists(&[self.cmdlist.as_raw() as *mut _]);
-            self.wait_for_gpu();
-            self.begin_frame();
+        for transition in transitions {
+            self.barrier_queue.push_transition(
+                resource_handle,
+                transition,
+                D3D12_RESOURCE_BARRIER_FLAG_NONE,
+            );
         }
-
-        let (cpu_base, gpu_base) = self.gpu_upload_memory_heaps[index].allocate(size);
-        assert!(cpu_base != ptr::null_mut() && gpu_base != 0);
-        (cpu_base, gpu_base)
     }
 
-    pub fn allocate_upload_buffer_region(
-        &mut self,
-        mut size: u32,
-    ) -> (*mut c_void, WeakPtr<ID3D12Resource>, u64) {
-        if (size & 0xff) != 0 {
-            size = (size + 255) & !0xff;
-        }
-
-        let (cpu_addr, _) = self.allocate_upload_memory(size);
-        let buffer = self.gpu_upload_memory_heaps[self.frame_index as usize].heap;
-        let offset = self.gpu_upload_memory_heaps[self.frame_index as usize].size - size;
-
-        (cpu_addr, buffer, offset as u64)
+    // Subresources not yet transitioned by this context are assumed to be in UNORDERED_ACCESS.
+    pub fn cmd_uav_barrier(&mut self, resource_handle: Option<ResourceHandle>) {
+        self.barrier_queue.push_uav(resource_handle);
     }
 
-    #[inline]
-    pub fn copy_descriptors_to_gpu_heap(
-        &mut self,
-        num_descriptors: u32,
-        src_cpu_base: D3D12_CPU_DESCRIPTOR_HANDLE,
-    ) -> D3D12_GPU_DESCRIPTOR_HANDLE {
-        let (dest_cpu_base, dest_gpu_base) = self.allocate_gpu_descriptors(num_descriptors);
-        unsafe {
-            self.device.CopyDescriptorsSimple(
-                num_descriptors,
-                dest_cpu_base,
-                src_cpu_base,
-                D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV,
-            )
-        };
-        dest_gpu_base
+    // `None` when this context has not transitioned the subresource yet.
+    pub fn resource_subresource_state(
+        &self,
+        resource_handle: ResourceHandle,
+        subresource: u32,
+    ) -> Option<D3D12_RESOURCE_STATES> {
+        self.local_states.state(resource_handle, subresource)
     }
 
-    pub fn end_frame(&mut self, swap_interval: u32) {
-        self.cmdlist.close();
-        self.cmdqueue
-            .execute_command_lists(&[self.cmdlist.as_raw() as *mut _]);
-
-        self.num_frames += 1;
-
-        vhr!(self.swapchain.Present(swap_interval, 0));
-        vhr!(self
-            .cmdqueue
-            .Signal(self.frame_fence.as_raw(), self.num_frames));
+    pub fn cmd_flush_barriers(&mut self, d3d: &RecordingView) {
+        if self.barrier_queue.is_empty() {
+            return;
+        }
+        let barriers: Vec<D3D12_RESOURCE_BARRIER> = self
+            .barrier_queue
+            .take()
+            .iter()
+            .map(|b| match *b {
+                QueuedBarrier::Transition {
+                    resource,
+                    transition,
+                    flags,
+                } => {
+                    let mut barrier = ResourceBarrier::transition_subresource(
+                        d3d.resource(resource),
+                        transition.state_before,
+                        transition.state_after,
+                        transition.subresource,
+                    );
+                    barrier.Flags = flags;
+                    barrier
+                }
+                QueuedBarrier::Uav { resource } => ResourceBarrier::uav(match resource {
+                    Some(h) => d3d.resource(h),
+                    None => WeakPtr::new(),
+                }),
+                QueuedBarrier::Aliasing { .. } => unreachable!(),
+            })
+            .collect();
+        self.cmdlist.resource_barrier(&barriers);
+    }
 
-        let gpu_num_frames = unsafe { self.frame_fence.GetCompletedValue() };
+    pub fn cmd_set_graphics_pipeline(&mut self, d3d: &RecordingView, handle: PipelineHandle) {
+        assert!(self.queue == QueueType::Graphics);
+        let pipeline_state = d3d.pipeline_pool.get(handle);
+        if handle != self.current_pipeline {
+            unsafe {
+                self.cmdlist.SetPipelineState(pipeline_state.pso.as_raw());
+                self.cmdlist
+                    .SetGraphicsRootSignature(pipeline_state.rsignature.as_raw());
+                self.current_pipeline = handle;
+            }
+        }
+    }
 
-        if (self.num_frames - gpu_num_frames) >= 2 {
-            let gpu_num_frames = gpu_num_frames + 1;
-            vhr!(self
-                .frame_fence
-                .SetEventOnCompletion(gpu_num_frames, self.frame_fence_event));
+    pub fn cmd_set_compute_pipeline(&mut self, d3d: &RecordingView, handle: PipelineHandle) {
+        assert!(self.queue != QueueType::Copy);
+        let pipeline_state = d3d.pipeline_pool.get(handle);
+        if handle != self.current_pipeline {
             unsafe {
-                WaitForSingleObject(self.frame_fence_event, INFINITE);
+                self.cmdlist.SetPipelineState(pipeline_state.pso.as_raw());
+                self.cmdlist
+                    .SetComputeRootSignature(pipeline_state.rsignature.as_raw());
+                self.current_pipeline = handle;
             }
         }
+    }
 
-        self.frame_index = (self.frame_index + 1) % 2;
-        self.back_buffer_index = unsafe { self.swapchain.GetCurrentBackBufferIndex() };
-        self.gpu_cbv_srv_uav_heaps[self.frame_index as usize].size = 0;
-        self.gpu_upload_memory_heaps[self.frame_index as usize].size = 0;
+    pub fn cmd_draw_instanced(
+        &mut self,
+        d3d: &RecordingView,
+        vertex_count_per_instance: u32,
+        instance_count: u32,
+        start_vertex_location: u32,
+        start_instance_location: u32,
+    ) {
+        assert!(self.queue == QueueType::Graphics);
+        self.cmd_flush_barriers(d3d);
+        self.cmdlist.draw_instanced(
+            vertex_count_per_instance,
+            instance_count,
+            start_vertex_location,
+            start_instance_location,
+        );
     }
 
-    pub fn begin_frame(&mut self) -> GraphicsCommandList {
-        let index = self.frame_index as usize;
-        unsafe {
-            self.cmdallocs[index].Reset();
-            self.cmdlist
-                .Reset(self.cmdallocs[index].as_raw(), ptr::null_mut());
-            self.cmdlist.SetDescriptorHeaps(
-                1,
-                &mut self.gpu_cbv_srv_uav_heaps[index].heap.as_raw()
-                    as *mut *mut ID3D12DescriptorHeap,
-            );
-        }
-        self.current_pipeline = INVALID_PIPELINE;
-        self.cmdlist
+    pub fn cmd_draw_indexed_instanced(
+        &mut self,
+        d3d: &RecordingView,
+        index_count_per_instance: u32,
+        instance_count: u32,
+        start_index_location: u32,
+        base_vertex_location: i32,
+        start_instance_location: u32,
+    ) {
+        assert!(self.queue == QueueType::Graphics);
+        self.cmd_flush_barriers(d3d);
+        self.cmdlist.draw_indexed_instanced(
+            index_count_per_instance,
+            instance_count,
+            start_index_location,
+            base_vertex_location,
+            start_instance_location,
+        );
     }
 
-    pub fn wait_for_gpu(&mut self) {
-        self.num_frames += 1;
+    pub fn cmd_dispatch(
+        &mut self,
+        d3d: &RecordingView,
+        thread_group_count_x: u32,
+        thread_group_count_y: u32,
+        thread_group_count_z: u32,
+    ) {
+        assert!(self.queue != QueueType::Copy);
+        self.cmd_flush_barriers(d3d);
+        self.cmdlist.dispatch(
+            thread_group_count_x,
+            thread_group_count_y,
+            thread_group_count_z,
+        );
+    }
 
-        vhr!(self
-            .cmdqueue
-            .Signal(self.frame_fence.as_raw(), self.num_frames));
-        vhr!(self
-            .frame_fence
-            .SetEventOnCompletion(self.num_frames, self.frame_fence_event));
-        unsafe {
-            WaitForSingleObject(self.frame_fence_event, INFINITE);
-        }
+    pub fn cmd_copy_buffer_region(
+        &mut self,
+        d3d: &RecordingView,
+        dst_buffer: Resource,
+        dst_offset: u64,
+        src_buffer: Resource,
+        src_offset: u64,
+        num_bytes: u64,
+    ) {
+        self.cmd_flush_barriers(d3d);
+        self.cmdlist
+            .copy_buffer_region(dst_buffer, dst_offset, src_buffer, src_offset, num_bytes);
+    }
 
-        self.gpu_cbv_srv_uav_heaps[self.frame_index as usize].size = 0;
-        self.gpu_upload_memory_heaps[self.frame_index as usize].size = 0;
+    pub fn cmd_clear_render_target_view(
+        &mut self,
+        d3d: &RecordingView,
+        render_target_view: D3D12_CPU_DESCRIPTOR_HANDLE,
+        color_rgba: &[f32; 4],
+        rects: &[D3D12_RECT],
+    ) {
+        assert!(self.queue == QueueType::Graphics);
+        self.cmd_flush_barriers(d3d);
+        self.cmdlist
+            .clear_render_target_view(render_target_view, color_rgba, rects);
     }
 
-    pub fn back_buffer(&self) -> (ResourceHandle, D3D12_CPU_DESCRIPTOR_HANDLE) {
-        let offset = self.back_buffer_index * self.rtv_heap.descriptor_size;
-        let handle = D3D12_CPU_DESCRIPTOR_HANDLE {
-            ptr: self.rtv_heap.cpu_base.ptr + offset as usize,
-        };
-        (self.swap_buffers[self.back_buffer_index as usize], handle)
+    pub fn cmd_clear_depth_stencil_view(
+        &mut self,
+        d3d: &RecordingView,
+        depth_stencil_view: D3D12_CPU_DESCRIPTOR_HANDLE,
+        clear_flags: D3D12_CLEAR_FLAGS,
+        depth: f32,
+        stencil: u8,
+        rects: &[D3D12_RECT],
+    ) {
+        assert!(self.queue == QueueType::Graphics);
+        self.cmd_flush_barriers(d3d);
+        self.cmdlist.clear_depth_stencil_view(
+            depth_stencil_view,
+            clear_flags,
+            depth,
+            stencil,
+            rects,
+        );
     }
 }
 
@@ -1021,19 +3913,22 @@ impl DescriptorHeap {
         capacity: u32,
         htype: D3D12_DESCRIPTOR_HEAP_TYPE,
         flags: D3D12_DESCRIPTOR_HEAP_FLAGS,
-    ) -> Self {
+    ) -> Result<Self> {
         let heap = {
             let mut rheap: *mut ID3D12DescriptorHeap = ptr::null_mut();
-            vhr!(device.CreateDescriptorHeap(
-                &D3D12_DESCRIPTOR_HEAP_DESC {
-                    NumDescriptors: capacity,
-                    Type: htype,
-                    Flags: flags,
-                    NodeMask: 0,
-                },
-                &ID3D12DescriptorHeap::uuidof(),
-                &mut rheap as *mut *mut _ as *mut *mut c_void,
-            ));
+            hr!(
+                device.CreateDescriptorHeap(
+                    &D3D12_DESCRIPTOR_HEAP_DESC {
+                        NumDescriptors: capacity,
+                        Type: htype,
+                        Flags: flags,
+                        NodeMask: 0,
+                    },
+                    &ID3D12DescriptorHeap::uuidof(),
+                    &mut rheap as *mut *mut _ as *mut *mut c_void,
+                ),
+                device
+            )?;
             WeakPtr::from_raw(rheap)
         };
         let (cpu_base, gpu_base) = unsafe {
@@ -1046,27 +3941,36 @@ impl DescriptorHeap {
                 },
             )
         };
-        Self {
+        Ok(Self {
             cpu_base,
             gpu_base,
             capacity,
             heap,
             size: 0,
             descriptor_size: unsafe { device.GetDescriptorHandleIncrementSize(htype) },
-        }
+        })
     }
 
-    fn allocate_cpu_descriptors(&mut self, num: u32) -> D3D12_CPU_DESCRIPTOR_HANDLE {
-        assert!(self.cpu_base.ptr != 0);
-        assert!(self.gpu_base.ptr == 0);
-        assert!((self.size + num) < self.capacity);
-
-        let handle = D3D12_CPU_DESCRIPTOR_HANDLE {
-            ptr: self.cpu_base.ptr + (self.size as usize) * (self.descriptor_size as usize),
-        };
-
-        self.size += num;
-        handle
+    // A view of `capacity` descriptors starting at `start`, sharing this heap.
+    fn region(&self, start: u32, capacity: u32) -> DescriptorHeap {
+        assert!(start + capacity <= self.capacity);
+        let offset = start as u64 * self.descriptor_size as u64;
+        DescriptorHeap {
+            heap: self.heap,
+            cpu_base: D3D12_CPU_DESCRIPTOR_HANDLE {
+                ptr: self.cpu_base.ptr + offset as usize,
+            },
+            gpu_base: D3D12_GPU_DESCRIPTOR_HANDLE {
+                ptr: if self.gpu_base.ptr != 0 {
+                    self.gpu_base.ptr + offset
+                } else {
+                    0
+                },
+            },
+            size: 0,
+            capacity,
+            descriptor_size: self.descriptor_size,
+        }
     }
 
     fn allocate_gpu_descriptors(
@@ -1089,6 +3993,363 @@ impl DescriptorHeap {
     }
 }
 
+impl CpuDescriptorHeap {
+    fn new(device: Device, heap_type: D3D12_DESCRIPTOR_HEAP_TYPE, page_capacity: u32) -> Self {
+        Self {
+            heap_type,
+            page_capacity,
+            descriptor_size: unsafe { device.GetDescriptorHandleIncrementSize(heap_type) },
+            pages: Vec::new(),
+        }
+    }
+
+    fn destroy(&mut self) {
+        for (heap, _) in self.pages.iter_mut() {
+            heap.heap.release();
+        }
+        self.pages.clear();
+    }
+
+    fn allocate(&mut self, device: Device, num: u32) -> Result<DescriptorAllocation> {
+        assert!(num <= self.page_capacity);
+        let found = self
+            .pages
+            .iter_mut()
+            .enumerate()
+            .find_map(|(page, (_, allocator))| allocator.allocate(num).map(|index| (page, index)));
+
+        let (page, index) = match found {
+            Some(found) => found,
+            None => {
+                let heap = DescriptorHeap::new(
+                    device,
+                    self.page_capacity,
+                    self.heap_type,
+                    D3D12_DESCRIPTOR_HEAP_FLAG_NONE,
+                )?;
+                let mut allocator = DescriptorAllocator::new(self.page_capacity);
+                let index = allocator.allocate(num).unwrap();
+                self.pages.push((heap, allocator));
+                (self.pages.len() - 1, index)
+            }
+        };
+
+        let cpu_base = self.pages[page].0.cpu_base;
+        Ok(DescriptorAllocation {
+            cpu_handle: D3D12_CPU_DESCRIPTOR_HANDLE {
+                ptr: cpu_base.ptr + index as usize * self.descriptor_size as usize,
+            },
+            heap_type: self.heap_type,
+            num,
+            page: page as u32,
+            index,
+        })
+    }
+
+    fn free(&mut self, allocation: DescriptorAllocation) {
+        assert!(allocation.heap_type == self.heap_type);
+        self.pages[allocation.page as usize]
+            .1
+            .free(allocation.index, allocation.num);
+    }
+}
+
+// Copies `src_cpu_handles` into the transient region `heap`, or returns the copy made earlier in
+// the frame.
+fn copy_descriptor_table(
+    device: Device,
+    heap: &mut DescriptorHeap,
+    cache: &mut DescriptorTableCache,
+    heap_type: D3D12_DESCRIPTOR_HEAP_TYPE,
+    src_cpu_handles: &[D3D12_CPU_DESCRIPTOR_HANDLE],
+) -> D3D12_GPU_DESCRIPTOR_HANDLE {
+    assert!(!src_cpu_handles.is_empty());
+    if let Some(gpu_base) = cache.lookup(src_cpu_handles) {
+        return gpu_base;
+    }
+
+    let num_descriptors = src_cpu_handles.len() as u32;
+    let (dest_cpu_base, dest_gpu_base) = heap.allocate_gpu_descriptors(num_descriptors);
+    let (src_starts, src_sizes) =
+        contiguous_descriptor_ranges(src_cpu_handles, heap.descriptor_size as usize);
+    unsafe {
+        device.CopyDescriptors(
+            1,
+            &dest_cpu_base,
+            &num_descriptors,
+            src_starts.len() as u32,
+            src_starts.as_ptr(),
+            src_sizes.as_ptr(),
+            heap_type,
+        )
+    };
+    cache.insert(src_cpu_handles, dest_gpu_base);
+    dest_gpu_base
+}
+
+fn create_command_allocators(
+    device: Device,
+    list_type: D3D12_COMMAND_LIST_TYPE,
+    count: u32,
+) -> Result<Vec<WeakPtr<ID3D12CommandAllocator>>> {
+    let mut guard = ReleaseGuard::new();
+    let cmdallocs = (0..count)
+        .map(|_| {
+            let mut rcmdalloc: *mut ID3D12CommandAllocator = ptr::null_mut();
+            hr!(
+                device.CreateCommandAllocator(
+                    list_type,
+                    &ID3D12CommandAllocator::uuidof(),
+                    &mut rcmdalloc as *mut *mut _ as *mut *mut c_void,
+                ),
+                device
+            )?;
+            Ok(guard.add(WeakPtr::from_raw(rcmdalloc)))
+        })
+        .collect::<Result<_>>()?;
+    guard.disarm();
+    Ok(cmdallocs)
+}
+
+fn enable_debug_layer(gpu_validation: bool) {
+    unsafe {
+        let mut rdbg: *mut ID3D12Debug = ptr::null_mut();
+        D3D12GetDebugInterface(
+            &ID3D12Debug::uuidof(),
+            &mut rdbg as *mut *mut _ as *mut *mut c_void,
+        );
+        if !rdbg.is_null() {
+            let mut dbg = WeakPtr::from_raw(rdbg);
+            dbg.EnableDebugLayer();
+
+            let mut rdbg1: *mut ID3D12Debug1 = ptr::null_mut();
+            dbg.QueryInterface(
+                &ID3D12Debug1::uuidof(),
+                &mut rdbg1 as *mut *mut _ as *mut *mut c_void,
+            );
+            dbg.release();
+            if !rdbg1.is_null() {
+                let mut dbg1 = WeakPtr::from_raw(rdbg1);
+                dbg1.SetEnableGPUBasedValidation(gpu_validation as BOOL);
+                dbg1.release();
+            }
+        }
+    }
+}
+
+fn read_shader(name: &str) -> Result<Vec<u8>> {
+    let path = format!("data/shaders/{}", name);
+    fs::read(&path).map_err(|err| Error::ShaderLoad {
+        path,
+        kind: err.kind(),
+    })
+}
+
+fn compile_shader(
+    source: &str,
+    entry_point: &str,
+    target: &str,
+    log_hook: Option<LogHook>,
+) -> Result<Vec<u8>> {
+    let entry_point = CString::new(entry_point).unwrap();
+    let target = CString::new(target).unwrap();
+    let mut rcode: *mut ID3DBlob = ptr::null_mut();
+    let mut rerrors: *mut ID3DBlob = ptr::null_mut();
+    let compiled = hr!(D3DCompile(
+        source.as_ptr() as *const c_void,
+        source.len(),
+        ptr::null(),
+        ptr::null(),
+        ptr::null_mut(),
+        entry_point.as_ptr(),
+        target.as_ptr(),
+        D3DCOMPILE_ENABLE_STRICTNESS | D3DCOMPILE_OPTIMIZATION_LEVEL3,
+        0,
+        &mut rcode,
+        &mut rerrors,
+    ));
+
+    let mut errors = WeakPtr::from_raw(rerrors);
+    if !errors.is_null() {
+        let message = unsafe {
+            slice::from_raw_parts(
+                errors.GetBufferPointer() as *const u8,
+                errors.GetBufferSize(),
+            )
+        };
+        log(
+            log_hook,
+            if compiled.is_ok() {
+                LogLevel::Warning
+            } else {
+                LogLevel::Error
+            },
+            String::from_utf8_lossy(message).trim_end_matches('\0'),
+        );
+    }
+    errors.release();
+    compiled?;
+
+    let mut code = WeakPtr::from_raw(rcode);
+    let bytecode = unsafe {
+        slice::from_raw_parts(code.GetBufferPointer() as *const u8, code.GetBufferSize())
+    }
+    .to_vec();
+    code.release();
+    Ok(bytecode)
+}
+
+pub(crate) fn create_dxgi_factory(debug: bool) -> Result<WeakPtr<IDXGIFactory4>> {
+    let mut rfactory: *mut IDXGIFactory4 = ptr::null_mut();
+    hr!(CreateDXGIFactory2(
+        if debug { DXGI_CREATE_FACTORY_DEBUG } else { 0 },
+        &IDXGIFactory4::uuidof(),
+        &mut rfactory as *mut *mut _ as *mut *mut c_void,
+    ))?;
+    Ok(WeakPtr::from_raw(rfactory))
+}
+
+fn is_tearing_supported(factory: WeakPtr<IDXGIFactory4>) -> bool {
+    let mut rfactory5: *mut IDXGIFactory5 = ptr::null_mut();
+    let hr = unsafe {
+        factory.QueryInterface(
+            &IDXGIFactory5::uuidof(),
+            &mut rfactory5 as *mut *mut _ as *mut *mut c_void,
+        )
+    };
+    if hr != 0 {
+        return false;
+    }
+    let mut factory5 = WeakPtr::from_raw(rfactory5);
+    let mut allow_tearing: BOOL = 0;
+    let hr = unsafe {
+        factory5.CheckFeatureSupport(
+            DXGI_FEATURE_PRESENT_ALLOW_TEARING,
+            &mut allow_tearing as *mut _ as *mut c_void,
+            mem::size_of::<BOOL>() as u32,
+        )
+    };
+    factory5.release();
+    hr == 0 && allow_tearing != 0
+}
+
+fn create_swapchain(
+    factory: WeakPtr<IDXGIFactory4>,
+    cmdqueue: CommandQueue,
+    window: HWND,
+    config: &SwapchainConfig,
+    tearing_supported: bool,
+) -> Result<WeakPtr<IDXGISwapChain3>> {
+    assert!(config.buffer_count >= 2 && config.buffer_count <= 16);
+    let mut swapchain1 = {
+        let mut desc: DXGI_SWAP_CHAIN_DESC = unsafe { mem::zeroed() };
+        desc.BufferCount = config.buffer_count;
+        desc.BufferDesc.Format = config.format.buffer_format();
+        desc.BufferUsage = DXGI_USAGE_RENDER_TARGET_OUTPUT;
+        desc.OutputWindow = window;
+        desc.SampleDesc.Count = 1;
+        desc.SwapEffect = DXGI_SWAP_EFFECT_FLIP_DISCARD;
+        desc.Windowed = 1;
+        if config.allow_tearing && tearing_supported {
+            desc.Flags = DXGI_SWAP_CHAIN_FLAG_ALLOW_TEARING;
+        }
+
+        let mut rswapchain1: *mut IDXGISwapChain = ptr::null_mut();
+        hr!(factory.CreateSwapChain(
+            cmdqueue.as_raw() as *mut _ as *mut IUnknown,
+            &mut desc,
+            &mut rswapchain1,
+        ))?;
+        WeakPtr::from_raw(rswapchain1)
+    };
+
+    let mut rswapchain3: *mut IDXGISwapChain3 = ptr::null_mut();
+    hr!(swapchain1.QueryInterface(
+        &IDXGISwapChain3::uuidof(),
+        &mut rswapchain3 as *mut *mut _ as *mut *mut c_void,
+    ))?;
+    swapchain1.release();
+    Ok(WeakPtr::from_raw(rswapchain3))
+}
+
+// Falls back to the SDR color space when the output can't present `color_space` (e.g. HDR10 on
+// a display without HDR enabled). Returns the color space that was set.
+fn set_swapchain_color_space(
+    swapchain: WeakPtr<IDXGISwapChain3>,
+    color_space: DXGI_COLOR_SPACE_TYPE,
+) -> Result<DXGI_COLOR_SPACE_TYPE> {
+    let mut support = 0;
+    hr!(swapchain.CheckColorSpaceSupport(color_space, &mut support))?;
+    let color_space = if support & DXGI_SWAP_CHAIN_COLOR_SPACE_SUPPORT_FLAG_PRESENT != 0 {
+        color_space
+    } else {
+        DXGI_COLOR_SPACE_RGB_FULL_G22_NONE_P709
+    };
+    hr!(swapchain.SetColorSpace1(color_space))?;
+    Ok(color_space)
+}
+
+// Wraps the buffers of `swapchain` and creates their RTVs in `rtvs`.
+fn get_swap_buffers(
+    device: Device,
+    swapchain: WeakPtr<IDXGISwapChain3>,
+    config: &SwapchainConfig,
+    resource_pool: &mut ResourcePool,
+    rtvs: &DescriptorAllocation,
+    rtv_descriptor_size: u32,
+    resolution: [u32; 2],
+) -> Result<Vec<ResourceHandle>> {
+    let mut desc = ResourceDesc::tex2d(
+        config.format.buffer_format(),
+        resolution[0] as u64,
+        resolution[1],
+    );
+    desc.Flags = D3D12_RESOURCE_FLAG_ALLOW_RENDER_TARGET;
+    let rtv_desc = render_target_view_desc(
+        &desc,
+        &TextureView::whole().with_format(config.format.rtv_format()),
+    );
+    assert!(rtvs.num == config.buffer_count);
+
+    (0..config.buffer_count)
+        .map(|i| {
+            let mut rbuffer: *mut ID3D12Resource = ptr::null_mut();
+            hr!(
+                swapchain.GetBuffer(
+                    i,
+                    &ID3D12Resource::uuidof(),
+                    &mut rbuffer as *mut *mut _ as *mut *mut c_void,
+                ),
+                device
+            )?;
+            let buffer = WeakPtr::from_raw(rbuffer);
+            device.create_render_target_view(
+                buffer,
+                Some(&rtv_desc),
+                D3D12_CPU_DESCRIPTOR_HANDLE {
+                    ptr: rtvs.cpu_handle.ptr + (i * rtv_descriptor_size) as usize,
+                },
+            );
+            Ok(resource_pool.add(buffer, D3D12_RESOURCE_STATE_PRESENT, &desc))
+        })
+        .collect()
+}
+
+// Depth buffers are cleared to 1.0 (and stencil 0) unless the caller clears to something else.
+fn depth_clear_value(format: DepthFormat) -> D3D12_CLEAR_VALUE {
+    let mut clear_value = D3D12_CLEAR_VALUE {
+        Format: format.dsv_format(),
+        u: unsafe { mem::zeroed() },
+    };
+    unsafe {
+        *clear_value.u.DepthStencil_mut() = D3D12_DEPTH_STENCIL_VALUE {
+            Depth: 1.0,
+            Stencil: 0,
+        }
+    };
+    clear_value
+}
+
 fn calc_graphics_pipeline_hash(desc: &D3D12_GRAPHICS_PIPELINE_STATE_DESC) -> u64 {
     let mut hasher = DefaultHasher::new();
 
@@ -1181,57 +4442,163 @@ fn calc_compute_pipeline_hash(desc: &D3D12_COMPUTE_PIPELINE_STATE_DESC) -> u64 {
 }
 
 impl GpuMemoryHeap {
-    fn new(device: WeakPtr<ID3D12Device2>, capacity: u32, heap_type: D3D12_HEAP_TYPE) -> Self {
-        // TODO(mziulek): Remove this limitation.
-        assert!(heap_type == D3D12_HEAP_TYPE_UPLOAD);
+    // Upload heaps are persistently mapped. Readback heaps are mapped only while reading, after
+    // the GPU is done writing, so `cpu_base` is null for them.
+    fn new(
+        device: WeakPtr<ID3D12Device2>,
+        capacity: u32,
+        heap_type: D3D12_HEAP_TYPE,
+    ) -> Result<Self> {
+        assert!(heap_type == D3D12_HEAP_TYPE_UPLOAD || heap_type == D3D12_HEAP_TYPE_READBACK);
 
         let heap = {
             let mut heap_raw: *mut ID3D12Resource = ptr::null_mut();
-            vhr!(device.CreateCommittedResource(
-                &HeapProperties::new(heap_type),
-                D3D12_HEAP_FLAG_NONE,
-                &ResourceDesc::buffer(capacity as u64),
-                D3D12_RESOURCE_STATE_GENERIC_READ,
-                ptr::null(),
-                &ID3D12Resource::uuidof(),
-                &mut heap_raw as *mut *mut _ as *mut *mut c_void
-            ));
+            hr!(
+                device.CreateCommittedResource(
+                    &HeapProperties::new(heap_type),
+                    D3D12_HEAP_FLAG_NONE,
+                    &ResourceDesc::buffer(capacity as u64),
+                    if heap_type == D3D12_HEAP_TYPE_UPLOAD {
+                        D3D12_RESOURCE_STATE_GENERIC_READ
+                    } else {
+                        D3D12_RESOURCE_STATE_COPY_DEST
+                    },
+                    ptr::null(),
+                    &ID3D12Resource::uuidof(),
+                    &mut heap_raw as *mut *mut _ as *mut *mut c_void
+                ),
+                device
+            )?;
             WeakPtr::from_raw(heap_raw)
         };
 
         let mut cpu_base: *mut u8 = ptr::null_mut();
-        vhr!(heap.Map(
-            0,
-            &D3D12_RANGE { Begin: 0, End: 0 },
-            &mut cpu_base as *mut *mut _ as *mut *mut c_void
-        ));
+        if heap_type == D3D12_HEAP_TYPE_UPLOAD {
+            hr!(
+                heap.Map(
+                    0,
+                    &D3D12_RANGE { Begin: 0, End: 0 },
+                    &mut cpu_base as *mut *mut _ as *mut *mut c_void
+                ),
+                device
+            )?;
+        }
 
         let gpu_base = unsafe { heap.GetGPUVirtualAddress() };
 
-        Self {
+        Ok(Self {
             heap,
             cpu_base,
             gpu_base,
             size: 0,
             capacity,
-        }
+        })
     }
 
-    fn allocate(&mut self, mut size: u32) -> (*mut c_void, D3D12_GPU_VIRTUAL_ADDRESS) {
+    fn allocate(
+        &mut self,
+        size: u32,
+        alignment: u32,
+    ) -> Option<(*mut c_void, D3D12_GPU_VIRTUAL_ADDRESS, u32)> {
         assert!(size > 0);
+        // Buffers are 64 KB aligned, so aligning the offset aligns the GPU address as well.
+        assert!(alignment.is_power_of_two() && alignment <= 64 * 1024);
+
+        let alignment = alignment as u64;
+        let offset = (self.size as u64 + alignment - 1) & !(alignment - 1);
+        if offset + size as u64 > self.capacity as u64 {
+            return None;
+        }
+        let offset = offset as u32;
+
+        let cpu_addr = unsafe { self.cpu_base.offset(offset as isize) as *mut c_void };
+        let gpu_addr = self.gpu_base + offset as u64;
+
+        self.size = offset + size;
+        Some((cpu_addr, gpu_addr, offset))
+    }
+}
+
+impl UploadRing {
+    fn new(page_size: u32) -> Self {
+        Self {
+            page_size,
+            current: None,
+            used: Vec::new(),
+            retired: VecDeque::new(),
+            free: Vec::new(),
+        }
+    }
+
+    fn destroy(&mut self) {
+        let pages = self
+            .current
+            .iter_mut()
+            .chain(self.used.iter_mut())
+            .chain(self.retired.iter_mut().map(|(page, _)| page))
+            .chain(self.free.iter_mut());
+        for page in pages {
+            page.heap.release();
+        }
+    }
 
-        if (size & 0xff) != 0 {
-            size = (size + 255) & !0xff;
+    fn allocate(
+        &mut self,
+        device: Device,
+        size: u32,
+        alignment: u32,
+        completed_fence_value: u64,
+    ) -> Result<(*mut c_void, D3D12_GPU_VIRTUAL_ADDRESS, Resource, u64)> {
+        if let Some(page) = self.current.as_mut() {
+            if let Some((cpu_addr, gpu_addr, offset)) = page.allocate(size, alignment) {
+                return Ok((cpu_addr, gpu_addr, page.heap, offset as u64));
+            }
+        }
+        if let Some(page) = self.current.take() {
+            self.used.push(page);
         }
 
-        if (self.size + size) >= self.capacity {
-            return (ptr::null_mut(), 0);
+        while let Some(&(_, fence_value)) = self.retired.front() {
+            if fence_value > completed_fence_value {
+                break;
+            }
+            let (mut page, _) = self.retired.pop_front().unwrap();
+            if page.capacity == self.page_size {
+                page.size = 0;
+                self.free.push(page);
+            } else {
+                page.heap.release();
+            }
         }
 
-        let cpu_addr = unsafe { self.cpu_base.offset(self.size as isize) as *mut c_void };
-        let gpu_addr = self.gpu_base + self.size as u64;
+        // A fresh page starts at offset 0, which satisfies any alignment, so `size` always fits.
+        let mut page = if size <= self.page_size {
+            match self.free.pop() {
+                Some(page) => page,
+                None => GpuMemoryHeap::new(device, self.page_size, D3D12_HEAP_TYPE_UPLOAD)?,
+            }
+        } else {
+            let capacity = (size as u64 + 0xffff) & !0xffff;
+            if capacity > u32::MAX as u64 {
+                return Err(Error::new(
+                    E_OUTOFMEMORY,
+                    "UploadRing::allocate",
+                    file!(),
+                    line!(),
+                ));
+            }
+            GpuMemoryHeap::new(device, capacity as u32, D3D12_HEAP_TYPE_UPLOAD)?
+        };
+        let (cpu_addr, gpu_addr, offset) = page.allocate(size, alignment).unwrap();
+        let heap = page.heap;
+        self.current = Some(page);
+        Ok((cpu_addr, gpu_addr, heap, offset as u64))
+    }
 
-        self.size += size;
-        (cpu_addr, gpu_addr)
+    // Pages used so far can be reused once the GPU reaches `fence_value`.
+    fn retire(&mut self, fence_value: u64) {
+        for page in self.used.drain(..).chain(self.current.take()) {
+            self.retired.push_back((page, fence_value));
+        }
     }
 }
@@ -0,0 +1,266 @@
+use crate::d3d12::{ResourceHandle, SubresourceTransition};
+use std::mem;
+use winapi::um::d3d12::*;
+
+// A transition whose `state_before` is not known while recording. It is resolved against the
+// global tracked state when the command list is submitted.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct PendingTransition {
+    pub resource: ResourceHandle,
+    pub subresource: u32,
+    pub state_after: D3D12_RESOURCE_STATES,
+}
+
+struct LocalState {
+    resource: ResourceHandle,
+    // `None` until the subresource is first transitioned by this command list.
+    states: Vec<Option<D3D12_RESOURCE_STATES>>,
+}
+
+// Resource states as seen by a single command list recorded independently of the others. The
+// first transition of every subresource becomes a `PendingTransition`; later ones are regular
+// barriers inside the command list.
+pub struct LocalStates {
+    resources: Vec<LocalState>,
+    pending: Vec<PendingTransition>,
+}
+
+impl LocalStates {
+    pub fn new() -> Self {
+        Self {
+            resources: Vec::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.resources.clear();
+        self.pending.clear();
+    }
+
+    // Returns the barriers that have to be recorded into the command list. Pending transitions
+    // are kept until `take_pending`.
+    pub fn transition(
+        &mut self,
+        resource: ResourceHandle,
+        num_subresources: u32,
+        subresource: u32,
+        state_after: D3D12_RESOURCE_STATES,
+    ) -> Vec<SubresourceTransition> {
+        assert!(
+            subresource == D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES
+                || subresource < num_subresources
+        );
+
+        let index = match self.resources.iter().position(|r| r.resource == resource) {
+            Some(index) => index,
+            None => {
+                self.resources.push(LocalState {
+                    resource,
+                    states: vec![None; num_subresources as usize],
+                });
+                self.resources.len() - 1
+            }
+        };
+        let states = &mut self.resources[index].states;
+        assert!(states.len() == num_subresources as usize);
+
+        let range = if subresource == D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES {
+            0..num_subresources
+        } else {
+            subresource..subresource + 1
+        };
+
+        let mut transitions = Vec::new();
+        if range.clone().all(|i| states[i as usize].is_none()) {
+            self.pending.push(PendingTransition {
+                resource,
+                subresource,
+                state_after,
+            });
+            for i in range {
+                states[i as usize] = Some(state_after);
+            }
+            return transitions;
+        }
+
+        let whole_resource = num_subresources == 1
+            || (subresource == D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES
+                && states.iter().all(|state| *state == states[0]));
+        if whole_resource {
+            let state_before = states[0].unwrap();
+            if state_before != state_after {
+                transitions.push(SubresourceTransition {
+                    subresource: D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES,
+                    state_before,
+                    state_after,
+                });
+            }
+            for state in states.iter_mut() {
+                *state = Some(state_after);
+            }
+            return transitions;
+        }
+
+        for i in range {
+            match states[i as usize] {
+                None => self.pending.push(PendingTransition {
+                    resource,
+                    subresource: i,
+                    state_after,
+                }),
+                Some(state_before) if state_before != state_after => {
+                    transitions.push(SubresourceTransition {
+                        subresource: i,
+                        state_before,
+                        state_after,
+                    })
+                }
+                Some(_) => {}
+            }
+            states[i as usize] = Some(state_after);
+        }
+        transitions
+    }
+
+    // `None` when the command list has not transitioned the subresource yet.
+    pub fn state(
+        &self,
+        resource: ResourceHandle,
+        subresource: u32,
+    ) -> Option<D3D12_RESOURCE_STATES> {
+        self.resources
+            .iter()
+            .find(|r| r.resource == resource)
+            .and_then(|r| r.states[subresource as usize])
+    }
+
+    pub fn take_pending(&mut self) -> Vec<PendingTransition> {
+        mem::replace(&mut self.pending, Vec::new())
+    }
+
+    // Final state of every subresource touched by the command list.
+    pub fn final_states(&self) -> Vec<(ResourceHandle, u32, D3D12_RESOURCE_STATES)> {
+        let mut final_states = Vec::new();
+        for r in &self.resources {
+            for (i, state) in r.states.iter().enumerate() {
+                if let Some(state) = state {
+                    final_states.push((r.resource, i as u32, *state));
+                }
+            }
+        }
+        final_states
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SRV: D3D12_RESOURCE_STATES = D3D12_RESOURCE_STATE_NON_PIXEL_SHADER_RESOURCE;
+    const UAV: D3D12_RESOURCE_STATES = D3D12_RESOURCE_STATE_UNORDERED_ACCESS;
+    const RTV: D3D12_RESOURCE_STATES = D3D12_RESOURCE_STATE_RENDER_TARGET;
+    const ALL: u32 = D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES;
+
+    fn barrier(
+        subresource: u32,
+        state_before: D3D12_RESOURCE_STATES,
+        state_after: D3D12_RESOURCE_STATES,
+    ) -> SubresourceTransition {
+        SubresourceTransition {
+            subresource,
+            state_before,
+            state_after,
+        }
+    }
+
+    fn pending(
+        resource: ResourceHandle,
+        subresource: u32,
+        state_after: D3D12_RESOURCE_STATES,
+    ) -> PendingTransition {
+        PendingTransition {
+            resource,
+            subresource,
+            state_after,
+        }
+    }
+
+    #[test]
+    fn first_touch_is_pending() {
+        let a = ResourceHandle::for_test(1);
+        let b = ResourceHandle::for_test(2);
+        let mut states = LocalStates::new();
+        assert_eq!(states.state(a, 0), None);
+
+        assert_eq!(states.transition(a, 1, 0, SRV), []);
+        assert_eq!(states.transition(b, 1, ALL, RTV), []);
+        assert_eq!(states.state(a, 0), Some(SRV));
+        assert_eq!(states.transition(a, 1, 0, UAV), [barrier(ALL, SRV, UAV)]);
+        assert_eq!(states.transition(a, 1, ALL, UAV), []);
+
+        assert_eq!(
+            states.take_pending(),
+            [pending(a, 0, SRV), pending(b, ALL, RTV)]
+        );
+        assert_eq!(states.take_pending(), []);
+        assert_eq!(states.final_states(), [(a, 0, UAV), (b, 0, RTV)]);
+
+        states.clear();
+        assert_eq!(states.state(a, 0), None);
+        assert_eq!(states.final_states(), []);
+    }
+
+    #[test]
+    fn whole_resource_collapses() {
+        let a = ResourceHandle::for_test(1);
+        let mut states = LocalStates::new();
+        assert_eq!(states.transition(a, 3, ALL, SRV), []);
+        assert_eq!(states.transition(a, 3, ALL, UAV), [barrier(ALL, SRV, UAV)]);
+        assert_eq!(states.transition(a, 3, ALL, UAV), []);
+
+        assert_eq!(states.take_pending(), [pending(a, ALL, SRV)]);
+        assert_eq!(
+            states.final_states(),
+            [(a, 0, UAV), (a, 1, UAV), (a, 2, UAV)]
+        );
+    }
+
+    #[test]
+    fn mixed_subresources() {
+        let a = ResourceHandle::for_test(1);
+        let mut states = LocalStates::new();
+        assert_eq!(states.transition(a, 3, 0, SRV), []);
+        assert_eq!(states.transition(a, 3, 0, UAV), [barrier(0, SRV, UAV)]);
+        assert_eq!(states.transition(a, 3, 2, RTV), []);
+        assert_eq!(states.transition(a, 3, 2, RTV), []);
+
+        assert_eq!(
+            states.take_pending(),
+            [pending(a, 0, SRV), pending(a, 2, RTV)]
+        );
+        assert_eq!(states.state(a, 1), None);
+        assert_eq!(states.final_states(), [(a, 0, UAV), (a, 2, RTV)]);
+    }
+
+    #[test]
+    fn all_subresources_over_partially_known_states() {
+        let a = ResourceHandle::for_test(1);
+        let mut states = LocalStates::new();
+        states.transition(a, 4, 0, SRV);
+        states.transition(a, 4, 1, RTV);
+        states.transition(a, 4, 3, SRV);
+        states.take_pending();
+
+        assert_eq!(states.transition(a, 4, ALL, SRV), [barrier(1, RTV, SRV)]);
+        assert_eq!(states.take_pending(), [pending(a, 2, SRV)]);
+        assert_eq!(
+            states.final_states(),
+            [(a, 0, SRV), (a, 1, SRV), (a, 2, SRV), (a, 3, SRV)]
+        );
+
+        // Now that every subresource is known and uniform, the next transition collapses.
+        assert_eq!(states.transition(a, 4, ALL, UAV), [barrier(ALL, SRV, UAV)]);
+        assert_eq!(states.take_pending(), []);
+    }
+}
@@ -0,0 +1,16 @@
+// Diagnostics of the d3d12 module (adapter fallbacks, state validation errors) are passed to the
+// hook in `ContextDesc::log_hook`; nothing is printed without one.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum LogLevel {
+    Info,
+    Warning,
+    Error,
+}
+
+pub type LogHook = fn(LogLevel, &str);
+
+pub(crate) fn log(hook: Option<LogHook>, level: LogLevel, message: &str) {
+    if let Some(hook) = hook {
+        hook(level, message);
+    }
+}
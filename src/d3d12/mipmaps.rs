@@ -0,0 +1,269 @@
+use winapi::shared::dxgiformat::*;
+use winapi::um::d3d12::*;
+
+pub fn mip_level_count(width: u32, height: u32) -> u16 {
+    let mut size = width.max(height);
+    let mut count = 1;
+    while size > 1 {
+        size >>= 1;
+        count += 1;
+    }
+    count
+}
+
+// `MipLevels` of `desc`, with 0 (the full chain) resolved for the dimension of the resource.
+pub fn mip_levels(desc: &D3D12_RESOURCE_DESC) -> u16 {
+    if desc.MipLevels != 0 {
+        return desc.MipLevels;
+    }
+    let width = desc.Width as u32;
+    match desc.Dimension {
+        D3D12_RESOURCE_DIMENSION_BUFFER => 1,
+        D3D12_RESOURCE_DIMENSION_TEXTURE1D => mip_level_count(width, 1),
+        D3D12_RESOURCE_DIMENSION_TEXTURE3D => {
+            mip_level_count(width, desc.Height.max(desc.DepthOrArraySize as u32))
+        }
+        _ => mip_level_count(width, desc.Height),
+    }
+}
+
+#[inline]
+pub fn mip_dimension(size: u32, level: u32) -> u32 {
+    (size >> level).max(1)
+}
+
+pub fn is_srgb_format(format: DXGI_FORMAT) -> bool {
+    match format {
+        DXGI_FORMAT_R8G8B8A8_UNORM_SRGB
+        | DXGI_FORMAT_B8G8R8A8_UNORM_SRGB
+        | DXGI_FORMAT_B8G8R8X8_UNORM_SRGB => true,
+        _ => false,
+    }
+}
+
+// Returns the non-sRGB format from the same format family. Typed UAVs can't use sRGB formats.
+pub fn linear_format(format: DXGI_FORMAT) -> DXGI_FORMAT {
+    match format {
+        DXGI_FORMAT_R8G8B8A8_UNORM_SRGB => DXGI_FORMAT_R8G8B8A8_UNORM,
+        DXGI_FORMAT_B8G8R8A8_UNORM_SRGB => DXGI_FORMAT_B8G8R8A8_UNORM,
+        DXGI_FORMAT_B8G8R8X8_UNORM_SRGB => DXGI_FORMAT_B8G8R8X8_UNORM,
+        _ => format,
+    }
+}
+
+#[inline]
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+#[inline]
+pub fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// Source texels (and their weights) that contribute to destination texel `x` when a dimension of
+// `src_size` texels is halved. Odd sizes use a 3-tap polyphase box filter so that every source
+// texel contributes equally to the whole mip. Must match `generate_mips.hlsl`.
+pub fn downsample_taps(src_size: u32, x: u32) -> ([u32; 3], [f32; 3], usize) {
+    if src_size == 1 {
+        ([0, 0, 0], [1.0, 0.0, 0.0], 1)
+    } else if (src_size & 1) == 0 {
+        ([2 * x, 2 * x + 1, 0], [0.5, 0.5, 0.0], 2)
+    } else {
+        let dst_size = (src_size / 2) as f32;
+        let src_size = src_size as f32;
+        let xf = x as f32;
+        (
+            [2 * x, 2 * x + 1, 2 * x + 2],
+            [
+                (dst_size - xf) / src_size,
+                dst_size / src_size,
+                (xf + 1.0) / src_size,
+            ],
+            3,
+        )
+    }
+}
+
+// CPU reference for `Context::cmd_generate_mips`. `texels` is the top mip in row-major order.
+// Returns all mip levels, including a copy of the top one.
+pub fn generate_mips_reference(
+    width: u32,
+    height: u32,
+    is_srgb: bool,
+    texels: &[[f32; 4]],
+) -> Vec<Vec<[f32; 4]>> {
+    assert!(texels.len() == (width * height) as usize);
+
+    let num_mips = mip_level_count(width, height) as u32;
+    let mut mips = Vec::with_capacity(num_mips as usize);
+    mips.push(texels.to_vec());
+
+    for mip in 1..num_mips {
+        let src_width = mip_dimension(width, mip - 1);
+        let src_height = mip_dimension(height, mip - 1);
+        let dst_width = mip_dimension(width, mip);
+        let dst_height = mip_dimension(height, mip);
+        let src = &mips[(mip - 1) as usize];
+
+        let mut dst = vec![[0.0; 4]; (dst_width * dst_height) as usize];
+        for y in 0..dst_height {
+            let (ty, wy, ny) = downsample_taps(src_height, y);
+            for x in 0..dst_width {
+                let (tx, wx, nx) = downsample_taps(src_width, x);
+
+                let mut sum = [0.0 as f32; 4];
+                for j in 0..ny {
+                    for i in 0..nx {
+                        let texel = src[(ty[j] * src_width + tx[i]) as usize];
+                        let w = wx[i] * wy[j];
+                        for c in 0..4 {
+                            let value = if is_srgb && c < 3 {
+                                srgb_to_linear(texel[c])
+                            } else {
+                                texel[c]
+                            };
+                            sum[c] += value * w;
+                        }
+                    }
+                }
+                if is_srgb {
+                    for c in 0..3 {
+                        sum[c] = linear_to_srgb(sum[c]);
+                    }
+                }
+                dst[(y * dst_width + x) as usize] = sum;
+            }
+        }
+        mips.push(dst);
+    }
+    mips
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-5, "{} != {}", a, b);
+    }
+
+    fn mean(texels: &[[f32; 4]], c: usize) -> f32 {
+        texels.iter().map(|t| t[c]).sum::<f32>() / texels.len() as f32
+    }
+
+    fn ramp(width: u32, height: u32) -> Vec<[f32; 4]> {
+        (0..width * height)
+            .map(|i| {
+                let v = i as f32 / (width * height) as f32;
+                [v, 1.0 - v, v * v, 0.5 * v]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn mip_chain_of_npot_sizes() {
+        assert_eq!(mip_level_count(1, 1), 1);
+        assert_eq!(mip_level_count(5, 3), 3);
+        assert_eq!(mip_level_count(640, 480), 10);
+        let mips = generate_mips_reference(5, 3, false, &ramp(5, 3));
+        let sizes: Vec<usize> = mips.iter().map(|mip| mip.len()).collect();
+        assert_eq!(sizes, [15, 2, 1]);
+    }
+
+    #[test]
+    fn odd_sizes_use_polyphase_weights() {
+        // Same weights as `downsample_taps` in generate_mips.hlsl: (dst - x, dst, x + 1) / src.
+        assert_eq!(downsample_taps(5, 0), ([0, 1, 2], [0.4, 0.4, 0.2], 3));
+        assert_eq!(downsample_taps(5, 1), ([2, 3, 4], [0.2, 0.4, 0.4], 3));
+        assert_eq!(downsample_taps(4, 1), ([2, 3, 0], [0.5, 0.5, 0.0], 2));
+        assert_eq!(downsample_taps(1, 0), ([0, 0, 0], [1.0, 0.0, 0.0], 1));
+
+        // Every source texel contributes the same total weight to the destination mip.
+        for &src_size in &[3, 5, 7, 9, 31] {
+            let dst_size = src_size / 2;
+            let mut contribution = vec![0.0; src_size as usize];
+            for x in 0..dst_size {
+                let (taps, weights, n) = downsample_taps(src_size, x);
+                assert_close(weights[..n].iter().sum(), 1.0);
+                for i in 0..n {
+                    contribution[taps[i] as usize] += weights[i];
+                }
+            }
+            for c in contribution {
+                assert_close(c, dst_size as f32 / src_size as f32);
+            }
+        }
+    }
+
+    #[test]
+    fn npot_mips_preserve_the_average() {
+        for &(width, height) in &[(5, 3), (7, 7), (6, 9), (13, 1)] {
+            let mips = generate_mips_reference(width, height, false, &ramp(width, height));
+            for c in 0..4 {
+                let top = mean(&mips[0], c);
+                for mip in &mips[1..] {
+                    assert_close(mean(mip, c), top);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn srgb_mips_are_averaged_in_linear_space() {
+        let texels = [[0.0, 1.0, 0.5, 0.0], [1.0, 1.0, 0.5, 1.0]];
+        let mips = generate_mips_reference(2, 1, true, &texels);
+        let expected = linear_to_srgb(0.5);
+        assert!(expected > 0.7);
+        assert_close(mips[1][0][0], expected);
+        assert_close(mips[1][0][1], 1.0);
+        assert_close(mips[1][0][2], 0.5);
+        // Alpha is linear.
+        assert_close(mips[1][0][3], 0.5);
+
+        let linear = generate_mips_reference(2, 1, false, &texels);
+        assert_close(linear[1][0][0], 0.5);
+    }
+
+    #[test]
+    fn srgb_npot_mips_average_linear_values() {
+        let (width, height) = (5, 3);
+        let texels = ramp(width, height);
+        let mips = generate_mips_reference(width, height, true, &texels);
+        let to_linear = |mip: &[[f32; 4]]| -> Vec<[f32; 4]> {
+            mip.iter()
+                .map(|t| {
+                    [
+                        srgb_to_linear(t[0]),
+                        srgb_to_linear(t[1]),
+                        srgb_to_linear(t[2]),
+                        t[3],
+                    ]
+                })
+                .collect()
+        };
+        let top = to_linear(&mips[0]);
+        for mip in &mips[1..] {
+            let mip = to_linear(mip);
+            for c in 0..4 {
+                assert!((mean(&mip, c) - mean(&top, c)).abs() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn srgb_conversion_round_trips() {
+        for i in 0..=20 {
+            let c = i as f32 / 20.0;
+            assert_close(linear_to_srgb(srgb_to_linear(c)), c);
+        }
+    }
+}
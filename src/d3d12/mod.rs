@@ -1,4 +1,44 @@
+// First, so that `hr!` can be used in every module.
+#[macro_use]
+mod error;
+mod adapters;
+mod barriers;
+mod capabilities;
+mod constant_buffers;
+mod context_desc;
+mod depth;
+mod descriptor_allocator;
+mod descriptor_cache;
 mod graphics;
+mod local_states;
+mod logging;
+mod mipmaps;
+mod queues;
+mod samplers;
+mod staging_ring;
+mod subresource_states;
+mod swapchain;
+mod validation;
+mod views;
 mod wrappers;
+pub use adapters::*;
+pub use barriers::*;
+pub use capabilities::*;
+pub use constant_buffers::*;
+pub use context_desc::*;
+pub use depth::*;
+pub use descriptor_allocator::*;
+pub use descriptor_cache::*;
+pub use error::*;
 pub use graphics::*;
+pub use local_states::*;
+pub use logging::*;
+pub use mipmaps::*;
+pub use queues::*;
+pub use samplers::*;
+pub use staging_ring::*;
+pub use subresource_states::*;
+pub use swapchain::*;
+pub use validation::*;
+pub use views::*;
 pub use wrappers::*;
@@ -0,0 +1,54 @@
+use winapi::um::d3d12::*;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum QueueType {
+    Graphics,
+    Compute,
+    Copy,
+}
+
+// A point on a queue's timeline, returned by `Context::signal_queue`. Another queue can wait for it
+// on the GPU (`Context::queue_wait`) or the CPU can poll or block on it.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct SyncPoint {
+    pub queue: QueueType,
+    pub value: u64,
+}
+
+impl QueueType {
+    #[inline]
+    pub fn index(self) -> usize {
+        match self {
+            QueueType::Graphics => 0,
+            QueueType::Compute => 1,
+            QueueType::Copy => 2,
+        }
+    }
+
+    pub fn command_list_type(self) -> D3D12_COMMAND_LIST_TYPE {
+        match self {
+            QueueType::Graphics => D3D12_COMMAND_LIST_TYPE_DIRECT,
+            QueueType::Compute => D3D12_COMMAND_LIST_TYPE_COMPUTE,
+            QueueType::Copy => D3D12_COMMAND_LIST_TYPE_COPY,
+        }
+    }
+
+    // Compute lists can't use (or transition from/to) graphics-only states, copy lists only the
+    // copy states and COMMON. A resource in an unsupported state has to be transitioned on the
+    // graphics queue (e.g. to COMMON) before it is handed over.
+    pub fn supports_state(self, state: D3D12_RESOURCE_STATES) -> bool {
+        let supported = match self {
+            QueueType::Graphics => return true,
+            QueueType::Compute => {
+                D3D12_RESOURCE_STATE_VERTEX_AND_CONSTANT_BUFFER
+                    | D3D12_RESOURCE_STATE_UNORDERED_ACCESS
+                    | D3D12_RESOURCE_STATE_NON_PIXEL_SHADER_RESOURCE
+                    | D3D12_RESOURCE_STATE_INDIRECT_ARGUMENT
+                    | D3D12_RESOURCE_STATE_COPY_DEST
+                    | D3D12_RESOURCE_STATE_COPY_SOURCE
+            }
+            QueueType::Copy => D3D12_RESOURCE_STATE_COPY_DEST | D3D12_RESOURCE_STATE_COPY_SOURCE,
+        };
+        (state & !supported) == 0
+    }
+}
@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+use winapi::um::d3d12::*;
+
+// Index of a sampler in the sampler heaps. Samplers are deduplicated by their description and live
+// as long as the context.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Sampler {
+    index: u32,
+}
+
+impl Sampler {
+    // Index into `Context::sampler_descriptor_table`.
+    #[inline]
+    pub fn index(self) -> u32 {
+        self.index
+    }
+}
+
+pub struct SamplerDesc;
+
+impl SamplerDesc {
+    pub fn new(
+        filter: D3D12_FILTER,
+        address_mode: D3D12_TEXTURE_ADDRESS_MODE,
+    ) -> D3D12_SAMPLER_DESC {
+        D3D12_SAMPLER_DESC {
+            Filter: filter,
+            AddressU: address_mode,
+            AddressV: address_mode,
+            AddressW: address_mode,
+            MipLODBias: 0.0,
+            MaxAnisotropy: 1,
+            ComparisonFunc: D3D12_COMPARISON_FUNC_NEVER,
+            BorderColor: [0.0; 4],
+            MinLOD: 0.0,
+            MaxLOD: D3D12_FLOAT32_MAX,
+        }
+    }
+
+    pub fn point_clamp() -> D3D12_SAMPLER_DESC {
+        Self::new(
+            D3D12_FILTER_MIN_MAG_MIP_POINT,
+            D3D12_TEXTURE_ADDRESS_MODE_CLAMP,
+        )
+    }
+
+    pub fn linear_clamp() -> D3D12_SAMPLER_DESC {
+        Self::new(
+            D3D12_FILTER_MIN_MAG_MIP_LINEAR,
+            D3D12_TEXTURE_ADDRESS_MODE_CLAMP,
+        )
+    }
+
+    pub fn linear_wrap() -> D3D12_SAMPLER_DESC {
+        Self::new(
+            D3D12_FILTER_MIN_MAG_MIP_LINEAR,
+            D3D12_TEXTURE_ADDRESS_MODE_WRAP,
+        )
+    }
+
+    pub fn anisotropic_wrap(max_anisotropy: u32) -> D3D12_SAMPLER_DESC {
+        let mut desc = Self::new(D3D12_FILTER_ANISOTROPIC, D3D12_TEXTURE_ADDRESS_MODE_WRAP);
+        desc.MaxAnisotropy = max_anisotropy;
+        desc
+    }
+
+    // For `SampleCmp` (e.g. shadow maps).
+    pub fn comparison(func: D3D12_COMPARISON_FUNC) -> D3D12_SAMPLER_DESC {
+        let mut desc = Self::new(
+            D3D12_FILTER_COMPARISON_MIN_MAG_LINEAR_MIP_POINT,
+            D3D12_TEXTURE_ADDRESS_MODE_CLAMP,
+        );
+        desc.ComparisonFunc = func;
+        desc
+    }
+}
+
+// D3D12_SAMPLER_DESC has float members, so it is hashed by its bit pattern.
+type SamplerKey = [u32; 13];
+
+fn sampler_key(desc: &D3D12_SAMPLER_DESC) -> SamplerKey {
+    [
+        desc.Filter,
+        desc.AddressU,
+        desc.AddressV,
+        desc.AddressW,
+        desc.MipLODBias.to_bits(),
+        desc.MaxAnisotropy,
+        desc.ComparisonFunc,
+        desc.BorderColor[0].to_bits(),
+        desc.BorderColor[1].to_bits(),
+        desc.BorderColor[2].to_bits(),
+        desc.BorderColor[3].to_bits(),
+        desc.MinLOD.to_bits(),
+        desc.MaxLOD.to_bits(),
+    ]
+}
+
+pub struct SamplerCache {
+    capacity: u32,
+    samplers: HashMap<SamplerKey, Sampler>,
+}
+
+impl SamplerCache {
+    pub fn new(capacity: u32) -> Self {
+        Self {
+            capacity,
+            samplers: HashMap::new(),
+        }
+    }
+
+    #[inline]
+    pub fn len(&self) -> u32 {
+        self.samplers.len() as u32
+    }
+
+    // The second value is `true` when `desc` was not seen before; the caller has to create the
+    // sampler descriptor at `index()`.
+    pub fn get_or_insert(&mut self, desc: &D3D12_SAMPLER_DESC) -> (Sampler, bool) {
+        let key = sampler_key(desc);
+        if let Some(sampler) = self.samplers.get(&key) {
+            return (*sampler, false);
+        }
+        assert!(
+            self.len() < self.capacity,
+            "too many unique samplers (max {})",
+            self.capacity
+        );
+        let sampler = Sampler { index: self.len() };
+        self.samplers.insert(key, sampler);
+        (sampler, true)
+    }
+}
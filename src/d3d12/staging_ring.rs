@@ -0,0 +1,180 @@
+use std::collections::VecDeque;
+
+// Offsets into a fixed-size staging buffer handed out in ring order. Memory allocated since the
+// previous `submit` is tagged with the fence value passed to the next one and handed back by
+// `reclaim` once that value has completed.
+pub struct StagingRing {
+    capacity: u64,
+    // Both grow monotonically; the offset into the buffer is taken modulo `capacity`.
+    head: u64,
+    tail: u64,
+    submitted_head: u64,
+    in_flight: VecDeque<(u64, u64)>,
+}
+
+impl StagingRing {
+    pub fn new(capacity: u64) -> Self {
+        assert!(capacity > 0);
+        Self {
+            capacity,
+            head: 0,
+            tail: 0,
+            submitted_head: 0,
+            in_flight: VecDeque::new(),
+        }
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    // `alignment` must be a power of two that divides the capacity. Returns `None` when the ring
+    // is full; reclaim completed memory (or wait for `oldest_fence_value`) and retry.
+    pub fn allocate(&mut self, size: u64, alignment: u64) -> Option<u64> {
+        assert!(size > 0 && size <= self.capacity);
+        assert!(alignment.is_power_of_two() && self.capacity % alignment == 0);
+
+        if self.tail == self.head {
+            // Empty, start over from the beginning of the buffer.
+            self.head = (self.head + self.capacity - 1) / self.capacity * self.capacity;
+            self.tail = self.head;
+            self.submitted_head = self.head;
+        }
+        let mut start = (self.head + alignment - 1) & !(alignment - 1);
+        if start % self.capacity + size > self.capacity {
+            // Doesn't fit before the end of the buffer, skip to the beginning.
+            start = (start / self.capacity + 1) * self.capacity;
+        }
+        let end = start + size;
+        if end - self.tail > self.capacity {
+            return None;
+        }
+        self.head = end;
+        Some(start % self.capacity)
+    }
+
+    pub fn submit(&mut self, fence_value: u64) {
+        if self.head > self.submitted_head {
+            self.in_flight.push_back((self.head, fence_value));
+            self.submitted_head = self.head;
+        }
+    }
+
+    pub fn reclaim(&mut self, completed_fence_value: u64) {
+        while let Some(&(end, fence_value)) = self.in_flight.front() {
+            if fence_value > completed_fence_value {
+                break;
+            }
+            self.tail = end;
+            self.in_flight.pop_front();
+        }
+    }
+
+    pub fn oldest_fence_value(&self) -> Option<u64> {
+        self.in_flight.front().map(|&(_, fence_value)| fence_value)
+    }
+
+    #[inline]
+    pub fn has_unsubmitted(&self) -> bool {
+        self.head > self.submitted_head
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alignment() {
+        let mut ring = StagingRing::new(256);
+        assert_eq!(ring.allocate(10, 1), Some(0));
+        assert_eq!(ring.allocate(16, 16), Some(16));
+        assert_eq!(ring.allocate(1, 64), Some(64));
+        assert_eq!(ring.allocate(3, 1), Some(65));
+    }
+
+    #[test]
+    fn wraps_around() {
+        let mut ring = StagingRing::new(256);
+        assert_eq!(ring.allocate(200, 1), Some(0));
+        ring.submit(1);
+        assert_eq!(ring.allocate(40, 1), Some(200));
+        ring.submit(2);
+        ring.reclaim(1);
+
+        // 240..272 would straddle the end of the buffer.
+        assert_eq!(ring.allocate(32, 1), Some(0));
+        assert_eq!(ring.allocate(32, 1), Some(32));
+        assert_eq!(ring.allocate(200, 1), None);
+        assert_eq!(ring.allocate(136, 1), Some(64));
+    }
+
+    #[test]
+    fn full() {
+        let mut ring = StagingRing::new(256);
+        assert_eq!(ring.allocate(200, 1), Some(0));
+        ring.submit(1);
+
+        // Skipping to the beginning would overwrite memory still in use.
+        assert_eq!(ring.allocate(100, 1), None);
+        assert_eq!(ring.allocate(56, 1), Some(200));
+        assert_eq!(ring.allocate(1, 1), None);
+
+        ring.reclaim(0);
+        assert_eq!(ring.allocate(1, 1), None);
+        ring.reclaim(1);
+        assert_eq!(ring.allocate(1, 1), Some(0));
+    }
+
+    #[test]
+    fn reclaim_in_order() {
+        let mut ring = StagingRing::new(256);
+        for fence_value in 1..=3 {
+            ring.allocate(64, 1).unwrap();
+            ring.submit(fence_value);
+        }
+        assert_eq!(ring.oldest_fence_value(), Some(1));
+        assert_eq!(ring.allocate(128, 1), None);
+
+        ring.reclaim(2);
+        assert_eq!(ring.oldest_fence_value(), Some(3));
+        assert_eq!(ring.allocate(128, 1), Some(0));
+        assert_eq!(ring.allocate(1, 1), None);
+
+        ring.reclaim(3);
+        assert_eq!(ring.oldest_fence_value(), None);
+        assert_eq!(ring.allocate(64, 1), Some(128));
+    }
+
+    #[test]
+    fn empty_ring_starts_over() {
+        let mut ring = StagingRing::new(256);
+        assert_eq!(ring.allocate(10, 1), Some(0));
+        ring.submit(1);
+        ring.reclaim(1);
+
+        // The whole buffer is free again, so a full-size allocation fits.
+        assert_eq!(ring.allocate(256, 1), Some(0));
+        ring.submit(2);
+        ring.reclaim(2);
+        assert_eq!(ring.allocate(8, 8), Some(0));
+    }
+
+    #[test]
+    fn submit_with_nothing_new() {
+        let mut ring = StagingRing::new(256);
+        ring.submit(1);
+        assert_eq!(ring.oldest_fence_value(), None);
+
+        ring.allocate(16, 1).unwrap();
+        assert!(ring.has_unsubmitted());
+        ring.submit(2);
+        ring.submit(3);
+        assert!(!ring.has_unsubmitted());
+        assert_eq!(ring.oldest_fence_value(), Some(2));
+
+        ring.reclaim(2);
+        assert_eq!(ring.oldest_fence_value(), None);
+    }
+}
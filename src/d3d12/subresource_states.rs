@@ -0,0 +1,262 @@
+use crate::d3d12::mip_levels;
+use winapi::shared::dxgiformat::*;
+use winapi::um::d3d12::*;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct SubresourceTransition {
+    pub subresource: u32,
+    pub state_before: D3D12_RESOURCE_STATES,
+    pub state_after: D3D12_RESOURCE_STATES,
+}
+
+// Tracked state of every subresource of a single resource. Stays `Uniform` as long as all
+// subresources agree and collapses back to it when they agree again.
+#[derive(Clone, PartialEq, Debug)]
+pub enum SubresourceStates {
+    Uniform(D3D12_RESOURCE_STATES),
+    PerSubresource(Vec<D3D12_RESOURCE_STATES>),
+}
+
+#[inline]
+pub fn calc_subresource(
+    mip_slice: u32,
+    array_slice: u32,
+    plane_slice: u32,
+    mip_levels: u32,
+    array_size: u32,
+) -> u32 {
+    mip_slice + array_slice * mip_levels + plane_slice * mip_levels * array_size
+}
+
+pub fn format_plane_count(format: DXGI_FORMAT) -> u32 {
+    match format {
+        DXGI_FORMAT_R24G8_TYPELESS
+        | DXGI_FORMAT_D24_UNORM_S8_UINT
+        | DXGI_FORMAT_R32G8X24_TYPELESS
+        | DXGI_FORMAT_D32_FLOAT_S8X24_UINT => 2,
+        _ => 1,
+    }
+}
+
+pub fn num_subresources(desc: &D3D12_RESOURCE_DESC) -> u32 {
+    let mip_levels = mip_levels(desc) as u32;
+    match desc.Dimension {
+        D3D12_RESOURCE_DIMENSION_BUFFER => 1,
+        D3D12_RESOURCE_DIMENSION_TEXTURE3D => mip_levels * format_plane_count(desc.Format),
+        _ => mip_levels * desc.DepthOrArraySize as u32 * format_plane_count(desc.Format),
+    }
+}
+
+impl SubresourceStates {
+    pub fn state(&self, subresource: u32) -> D3D12_RESOURCE_STATES {
+        match self {
+            SubresourceStates::Uniform(state) => *state,
+            SubresourceStates::PerSubresource(states) => {
+                assert!(subresource != D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES);
+                states[subresource as usize]
+            }
+        }
+    }
+
+    // Returns the transitions needed to move `subresource` (or all subresources when
+    // `D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES`) to `state_after` and updates the tracked state.
+    pub fn transition(
+        &mut self,
+        num_subresources: u32,
+        subresource: u32,
+        state_after: D3D12_RESOURCE_STATES,
+    ) -> Vec<SubresourceTransition> {
+        assert!(
+            subresource == D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES
+                || subresource < num_subresources
+        );
+
+        let mut transitions = Vec::new();
+        match self {
+            SubresourceStates::Uniform(state) => {
+                if *state == state_after {
+                    return transitions;
+                }
+                if subresource == D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES || num_subresources == 1 {
+                    transitions.push(SubresourceTransition {
+                        subresource: D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES,
+                        state_before: *state,
+                        state_after,
+                    });
+                    *state = state_after;
+                } else {
+                    transitions.push(SubresourceTransition {
+                        subresource,
+                        state_before: *state,
+                        state_after,
+                    });
+                    let mut states = vec![*state; num_subresources as usize];
+                    states[subresource as usize] = state_after;
+                    *self = SubresourceStates::PerSubresource(states);
+                }
+            }
+            SubresourceStates::PerSubresource(states) => {
+                assert!(states.len() == num_subresources as usize);
+                if subresource == D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES {
+                    for (i, state) in states.iter_mut().enumerate() {
+                        if *state != state_after {
+                            transitions.push(SubresourceTransition {
+                                subresource: i as u32,
+                                state_before: *state,
+                                state_after,
+                            });
+                            *state = state_after;
+                        }
+                    }
+                } else if states[subresource as usize] != state_after {
+                    transitions.push(SubresourceTransition {
+                        subresource,
+                        state_before: states[subresource as usize],
+                        state_after,
+                    });
+                    states[subresource as usize] = state_after;
+                }
+                if states.iter().all(|state| *state == states[0]) {
+                    *self = SubresourceStates::Uniform(states[0]);
+                }
+            }
+        }
+        transitions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COMMON: D3D12_RESOURCE_STATES = D3D12_RESOURCE_STATE_COMMON;
+    const SRV: D3D12_RESOURCE_STATES = D3D12_RESOURCE_STATE_NON_PIXEL_SHADER_RESOURCE;
+    const UAV: D3D12_RESOURCE_STATES = D3D12_RESOURCE_STATE_UNORDERED_ACCESS;
+    const RTV: D3D12_RESOURCE_STATES = D3D12_RESOURCE_STATE_RENDER_TARGET;
+    const ALL: u32 = D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES;
+
+    fn barrier(
+        subresource: u32,
+        state_before: D3D12_RESOURCE_STATES,
+        state_after: D3D12_RESOURCE_STATES,
+    ) -> SubresourceTransition {
+        SubresourceTransition {
+            subresource,
+            state_before,
+            state_after,
+        }
+    }
+
+    fn texture(
+        dimension: D3D12_RESOURCE_DIMENSION,
+        width: u64,
+        height: u32,
+        depth_or_array_size: u16,
+        mip_levels: u16,
+    ) -> D3D12_RESOURCE_DESC {
+        D3D12_RESOURCE_DESC {
+            Dimension: dimension,
+            Width: width,
+            Height: height,
+            DepthOrArraySize: depth_or_array_size,
+            MipLevels: mip_levels,
+            Format: DXGI_FORMAT_R8G8B8A8_UNORM,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn uniform_to_one_mip() {
+        let mut states = SubresourceStates::Uniform(SRV);
+        assert_eq!(states.transition(4, 1, UAV), [barrier(1, SRV, UAV)]);
+        assert_eq!(
+            states,
+            SubresourceStates::PerSubresource(vec![SRV, UAV, SRV, SRV])
+        );
+        assert_eq!(states.transition(4, 2, UAV), [barrier(2, SRV, UAV)]);
+        assert_eq!(states.transition(4, 2, UAV), []);
+        assert_eq!(states.state(2), UAV);
+        assert_eq!(states.state(3), SRV);
+    }
+
+    #[test]
+    fn uniform_to_all_subresources() {
+        let mut states = SubresourceStates::Uniform(COMMON);
+        assert_eq!(states.transition(6, ALL, SRV), [barrier(ALL, COMMON, SRV)]);
+        assert_eq!(states, SubresourceStates::Uniform(SRV));
+        assert_eq!(states.transition(6, ALL, SRV), []);
+        // A resource with a single subresource never goes per-subresource.
+        let mut states = SubresourceStates::Uniform(COMMON);
+        assert_eq!(states.transition(1, 0, SRV), [barrier(ALL, COMMON, SRV)]);
+        assert_eq!(states, SubresourceStates::Uniform(SRV));
+    }
+
+    #[test]
+    fn collapses_when_all_subresources_agree() {
+        let mut states = SubresourceStates::Uniform(SRV);
+        states.transition(3, 0, UAV);
+        states.transition(3, 2, UAV);
+        assert_eq!(states.transition(3, 1, UAV), [barrier(1, SRV, UAV)]);
+        assert_eq!(states, SubresourceStates::Uniform(UAV));
+
+        states.transition(3, 1, SRV);
+        assert_eq!(states.transition(3, 1, UAV), [barrier(1, SRV, UAV)]);
+        assert_eq!(states, SubresourceStates::Uniform(UAV));
+    }
+
+    #[test]
+    fn all_subresources_from_per_subresource_state() {
+        let mut states = SubresourceStates::PerSubresource(vec![SRV, UAV, RTV, UAV]);
+        assert_eq!(
+            states.transition(4, ALL, UAV),
+            [barrier(0, SRV, UAV), barrier(2, RTV, UAV)]
+        );
+        assert_eq!(states, SubresourceStates::Uniform(UAV));
+    }
+
+    #[test]
+    fn array_slices() {
+        // 2 mips, 3 slices: subresource = mip + slice * 2.
+        let desc = texture(D3D12_RESOURCE_DIMENSION_TEXTURE2D, 64, 64, 3, 2);
+        let num = num_subresources(&desc);
+        assert_eq!(num, 6);
+        let slice1 = calc_subresource(0, 1, 0, 2, 3);
+        let slice2_mip1 = calc_subresource(1, 2, 0, 2, 3);
+        assert_eq!((slice1, slice2_mip1), (2, 5));
+
+        let mut states = SubresourceStates::Uniform(COMMON);
+        assert_eq!(
+            states.transition(num, slice1, RTV),
+            [barrier(2, COMMON, RTV)]
+        );
+        assert_eq!(
+            states.transition(num, slice2_mip1, SRV),
+            [barrier(5, COMMON, SRV)]
+        );
+        assert_eq!(
+            states,
+            SubresourceStates::PerSubresource(vec![COMMON, COMMON, RTV, COMMON, COMMON, SRV])
+        );
+        assert_eq!(
+            states.transition(num, ALL, COMMON),
+            [barrier(2, RTV, COMMON), barrier(5, SRV, COMMON)]
+        );
+        assert_eq!(states, SubresourceStates::Uniform(COMMON));
+    }
+
+    #[test]
+    fn subresource_counts() {
+        let tex2d = texture(D3D12_RESOURCE_DIMENSION_TEXTURE2D, 256, 64, 4, 0);
+        assert_eq!(num_subresources(&tex2d), 9 * 4);
+        // Depth slices are not subresources.
+        let tex3d = texture(D3D12_RESOURCE_DIMENSION_TEXTURE3D, 16, 8, 32, 0);
+        assert_eq!(num_subresources(&tex3d), 6);
+        let tex1d = texture(D3D12_RESOURCE_DIMENSION_TEXTURE1D, 100, 1, 2, 0);
+        assert_eq!(num_subresources(&tex1d), 7 * 2);
+        let mut depth = texture(D3D12_RESOURCE_DIMENSION_TEXTURE2D, 16, 16, 1, 1);
+        depth.Format = DXGI_FORMAT_D24_UNORM_S8_UINT;
+        assert_eq!(num_subresources(&depth), 2);
+        let buffer = texture(D3D12_RESOURCE_DIMENSION_BUFFER, 1024, 1, 1, 1);
+        assert_eq!(num_subresources(&buffer), 1);
+    }
+}
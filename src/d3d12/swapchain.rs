@@ -0,0 +1,70 @@
+use winapi::shared::dxgiformat::*;
+use winapi::shared::dxgitype::*;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum SwapchainFormat {
+    Rgba8,
+    // RGBA8 buffers rendered through an sRGB view; flip model buffers can't be sRGB themselves.
+    Rgba8Srgb,
+    // R10G10B10A2 with the ST.2084 (PQ) curve and BT.2020 primaries.
+    Hdr10,
+    // FP16 linear BT.709, values above 1.0 are brighter than SDR white.
+    ScRgb,
+}
+
+impl SwapchainFormat {
+    pub fn buffer_format(self) -> DXGI_FORMAT {
+        match self {
+            SwapchainFormat::Rgba8 | SwapchainFormat::Rgba8Srgb => DXGI_FORMAT_R8G8B8A8_UNORM,
+            SwapchainFormat::Hdr10 => DXGI_FORMAT_R10G10B10A2_UNORM,
+            SwapchainFormat::ScRgb => DXGI_FORMAT_R16G16B16A16_FLOAT,
+        }
+    }
+
+    // Format of the back buffer RTVs, pipelines rendering to the back buffer have to use it.
+    pub fn rtv_format(self) -> DXGI_FORMAT {
+        match self {
+            SwapchainFormat::Rgba8Srgb => DXGI_FORMAT_R8G8B8A8_UNORM_SRGB,
+            _ => self.buffer_format(),
+        }
+    }
+
+    pub fn default_color_space(self) -> DXGI_COLOR_SPACE_TYPE {
+        match self {
+            SwapchainFormat::Rgba8 | SwapchainFormat::Rgba8Srgb => {
+                DXGI_COLOR_SPACE_RGB_FULL_G22_NONE_P709
+            }
+            SwapchainFormat::Hdr10 => DXGI_COLOR_SPACE_RGB_FULL_G2084_NONE_P2020,
+            SwapchainFormat::ScRgb => DXGI_COLOR_SPACE_RGB_FULL_G10_NONE_P709,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct SwapchainConfig {
+    pub buffer_count: u32,
+    pub format: SwapchainFormat,
+    // `None` uses the default color space of `format`.
+    pub color_space: Option<DXGI_COLOR_SPACE_TYPE>,
+    // Lets `end_frame(0, ..)` present without waiting for vblank on variable refresh displays.
+    // Ignored when the system doesn't support tearing.
+    pub allow_tearing: bool,
+}
+
+impl SwapchainConfig {
+    pub fn color_space(&self) -> DXGI_COLOR_SPACE_TYPE {
+        self.color_space
+            .unwrap_or_else(|| self.format.default_color_space())
+    }
+}
+
+impl Default for SwapchainConfig {
+    fn default() -> Self {
+        Self {
+            buffer_count: 4,
+            format: SwapchainFormat::Rgba8,
+            color_space: None,
+            allow_tearing: false,
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use winapi::um::d3d12::*;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ResourceUsage {
+    VertexOrConstantBuffer,
+    IndexBuffer,
+    RenderTarget,
+    UnorderedAccess,
+    DepthWrite,
+    DepthRead,
+    NonPixelShaderResource,
+    PixelShaderResource,
+    // Visible to both non-pixel and pixel stages; either state is accepted.
+    ShaderResource,
+    IndirectArgument,
+    CopyDest,
+    CopySource,
+    Present,
+}
+
+impl ResourceUsage {
+    pub fn required_states(self) -> D3D12_RESOURCE_STATES {
+        match self {
+            ResourceUsage::VertexOrConstantBuffer => {
+                D3D12_RESOURCE_STATE_VERTEX_AND_CONSTANT_BUFFER
+            }
+            ResourceUsage::IndexBuffer => D3D12_RESOURCE_STATE_INDEX_BUFFER,
+            ResourceUsage::RenderTarget => D3D12_RESOURCE_STATE_RENDER_TARGET,
+            ResourceUsage::UnorderedAccess => D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
+            ResourceUsage::DepthWrite => D3D12_RESOURCE_STATE_DEPTH_WRITE,
+            ResourceUsage::DepthRead => D3D12_RESOURCE_STATE_DEPTH_READ,
+            ResourceUsage::NonPixelShaderResource => D3D12_RESOURCE_STATE_NON_PIXEL_SHADER_RESOURCE,
+            ResourceUsage::PixelShaderResource => D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE,
+            ResourceUsage::ShaderResource => {
+                D3D12_RESOURCE_STATE_NON_PIXEL_SHADER_RESOURCE
+                    | D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE
+            }
+            ResourceUsage::IndirectArgument => D3D12_RESOURCE_STATE_INDIRECT_ARGUMENT,
+            ResourceUsage::CopyDest => D3D12_RESOURCE_STATE_COPY_DEST,
+            ResourceUsage::CopySource => D3D12_RESOURCE_STATE_COPY_SOURCE,
+            ResourceUsage::Present => D3D12_RESOURCE_STATE_PRESENT,
+        }
+    }
+
+    // Read-only usages accept any state that contains the required bit (read states can be
+    // combined); write usages need the exact state. Buffers in COMMON are implicitly promoted on
+    // first use, so they satisfy everything except render target and depth usages.
+    pub fn is_satisfied_by(self, state: D3D12_RESOURCE_STATES, is_buffer: bool) -> bool {
+        let required = self.required_states();
+        if is_buffer && state == D3D12_RESOURCE_STATE_COMMON {
+            return match self {
+                ResourceUsage::RenderTarget
+                | ResourceUsage::DepthWrite
+                | ResourceUsage::DepthRead => false,
+                _ => true,
+            };
+        }
+        match self {
+            ResourceUsage::RenderTarget
+            | ResourceUsage::UnorderedAccess
+            | ResourceUsage::DepthWrite
+            | ResourceUsage::CopyDest
+            | ResourceUsage::Present => state == required,
+            _ => (state & required) != 0,
+        }
+    }
+}
+
+pub fn resource_state_name(state: D3D12_RESOURCE_STATES) -> String {
+    const NAMES: [(D3D12_RESOURCE_STATES, &str); 14] = [
+        (
+            D3D12_RESOURCE_STATE_VERTEX_AND_CONSTANT_BUFFER,
+            "VERTEX_AND_CONSTANT_BUFFER",
+        ),
+        (D3D12_RESOURCE_STATE_INDEX_BUFFER, "INDEX_BUFFER"),
+        (D3D12_RESOURCE_STATE_RENDER_TARGET, "RENDER_TARGET"),
+        (D3D12_RESOURCE_STATE_UNORDERED_ACCESS, "UNORDERED_ACCESS"),
+        (D3D12_RESOURCE_STATE_DEPTH_WRITE, "DEPTH_WRITE"),
+        (D3D12_RESOURCE_STATE_DEPTH_READ, "DEPTH_READ"),
+        (
+            D3D12_RESOURCE_STATE_NON_PIXEL_SHADER_RESOURCE,
+            "NON_PIXEL_SHADER_RESOURCE",
+        ),
+        (
+            D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE,
+            "PIXEL_SHADER_RESOURCE",
+        ),
+        (D3D12_RESOURCE_STATE_STREAM_OUT, "STREAM_OUT"),
+        (D3D12_RESOURCE_STATE_INDIRECT_ARGUMENT, "INDIRECT_ARGUMENT"),
+        (D3D12_RESOURCE_STATE_COPY_DEST, "COPY_DEST"),
+        (D3D12_RESOURCE_STATE_COPY_SOURCE, "COPY_SOURCE"),
+        (D3D12_RESOURCE_STATE_RESOLVE_DEST, "RESOLVE_DEST"),
+        (D3D12_RESOURCE_STATE_RESOLVE_SOURCE, "RESOLVE_SOURCE"),
+    ];
+    if state == D3D12_RESOURCE_STATE_COMMON {
+        return "COMMON".to_string();
+    }
+    NAMES
+        .iter()
+        .filter(|(bit, _)| (state & bit) != 0)
+        .map(|(_, name)| *name)
+        .collect::<Vec<_>>()
+        .join(" | ")
+}
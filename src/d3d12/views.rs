@@ -0,0 +1,872 @@
+use crate::d3d12::DEFAULT_SHADER_4_COMPONENT_MAPPING;
+use std::mem;
+use winapi::shared::dxgiformat::*;
+use winapi::um::d3d12::*;
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum BufferView {
+    // StructuredBuffer<T>; `stride` is the size of `T`.
+    Structured {
+        first_element: u64,
+        num_elements: u32,
+        stride: u32,
+    },
+    // Buffer<T>; `format` is the format of one element.
+    Typed {
+        format: DXGI_FORMAT,
+        first_element: u64,
+        num_elements: u32,
+    },
+    // ByteAddressBuffer; offset and size are in bytes and must be multiples of 4.
+    Raw {
+        offset: u64,
+        size: u32,
+    },
+}
+
+impl BufferView {
+    pub fn structured<T>(num_elements: u32) -> Self {
+        BufferView::Structured {
+            first_element: 0,
+            num_elements,
+            stride: mem::size_of::<T>() as u32,
+        }
+    }
+
+    pub fn typed(format: DXGI_FORMAT, num_elements: u32) -> Self {
+        BufferView::Typed {
+            format,
+            first_element: 0,
+            num_elements,
+        }
+    }
+
+    pub fn raw(size: u32) -> Self {
+        BufferView::Raw { offset: 0, size }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum ViewDesc {
+    BufferSrv(BufferView),
+    BufferUav(BufferView),
+    ConstantBuffer { offset: u64, size: u32 },
+    TextureSrv(TextureView),
+    TextureUav(TextureView),
+    RenderTarget(TextureView),
+    DepthStencil(TextureView, D3D12_DSV_FLAGS),
+}
+
+impl ViewDesc {
+    pub fn heap_type(&self) -> D3D12_DESCRIPTOR_HEAP_TYPE {
+        match self {
+            ViewDesc::RenderTarget(_) => D3D12_DESCRIPTOR_HEAP_TYPE_RTV,
+            ViewDesc::DepthStencil(..) => D3D12_DESCRIPTOR_HEAP_TYPE_DSV,
+            _ => D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct TextureView {
+    // `DXGI_FORMAT_UNKNOWN` uses the format of the resource.
+    pub format: DXGI_FORMAT,
+    pub first_mip: u32,
+    // 0 selects all remaining mips. UAVs, RTVs and DSVs use only `first_mip`.
+    pub num_mips: u32,
+    pub first_slice: u32,
+    // 0 selects all remaining array slices (depth slices of a 3D texture).
+    pub num_slices: u32,
+}
+
+impl TextureView {
+    pub fn whole() -> Self {
+        Self {
+            format: DXGI_FORMAT_UNKNOWN,
+            first_mip: 0,
+            num_mips: 0,
+            first_slice: 0,
+            num_slices: 0,
+        }
+    }
+
+    pub fn mip(mip: u32) -> Self {
+        Self {
+            first_mip: mip,
+            num_mips: 1,
+            ..Self::whole()
+        }
+    }
+
+    pub fn with_format(self, format: DXGI_FORMAT) -> Self {
+        Self { format, ..self }
+    }
+}
+
+// Size of one element of a typed buffer.
+pub fn format_size_in_bytes(format: DXGI_FORMAT) -> Option<u32> {
+    let size = match format {
+        DXGI_FORMAT_R32G32B32A32_FLOAT
+        | DXGI_FORMAT_R32G32B32A32_UINT
+        | DXGI_FORMAT_R32G32B32A32_SINT => 16,
+        DXGI_FORMAT_R32G32B32_FLOAT | DXGI_FORMAT_R32G32B32_UINT | DXGI_FORMAT_R32G32B32_SINT => 12,
+        DXGI_FORMAT_R16G16B16A16_FLOAT
+        | DXGI_FORMAT_R16G16B16A16_UNORM
+        | DXGI_FORMAT_R16G16B16A16_UINT
+        | DXGI_FORMAT_R16G16B16A16_SNORM
+        | DXGI_FORMAT_R16G16B16A16_SINT
+        | DXGI_FORMAT_R32G32_FLOAT
+        | DXGI_FORMAT_R32G32_UINT
+        | DXGI_FORMAT_R32G32_SINT => 8,
+        DXGI_FORMAT_R10G10B10A2_UNORM
+        | DXGI_FORMAT_R10G10B10A2_UINT
+        | DXGI_FORMAT_R11G11B10_FLOAT
+        | DXGI_FORMAT_R8G8B8A8_UNORM
+        | DXGI_FORMAT_R8G8B8A8_UINT
+        | DXGI_FORMAT_R8G8B8A8_SNORM
+        | DXGI_FORMAT_R8G8B8A8_SINT
+        | DXGI_FORMAT_B8G8R8A8_UNORM
+        | DXGI_FORMAT_R16G16_FLOAT
+        | DXGI_FORMAT_R16G16_UNORM
+        | DXGI_FORMAT_R16G16_UINT
+        | DXGI_FORMAT_R16G16_SNORM
+        | DXGI_FORMAT_R16G16_SINT
+        | DXGI_FORMAT_R32_FLOAT
+        | DXGI_FORMAT_R32_UINT
+        | DXGI_FORMAT_R32_SINT => 4,
+        DXGI_FORMAT_R8G8_UNORM
+        | DXGI_FORMAT_R8G8_UINT
+        | DXGI_FORMAT_R8G8_SNORM
+        | DXGI_FORMAT_R8G8_SINT
+        | DXGI_FORMAT_R16_FLOAT
+        | DXGI_FORMAT_R16_UNORM
+        | DXGI_FORMAT_R16_UINT
+        | DXGI_FORMAT_R16_SNORM
+        | DXGI_FORMAT_R16_SINT => 2,
+        DXGI_FORMAT_R8_UNORM | DXGI_FORMAT_R8_UINT | DXGI_FORMAT_R8_SNORM | DXGI_FORMAT_R8_SINT => {
+            1
+        }
+        _ => return None,
+    };
+    Some(size)
+}
+
+// The typeless format a resource has to be created with so that it can be viewed as `format`.
+pub fn typeless_format(format: DXGI_FORMAT) -> Option<DXGI_FORMAT> {
+    let typeless = match format {
+        DXGI_FORMAT_R32G32B32A32_TYPELESS
+        | DXGI_FORMAT_R32G32B32A32_FLOAT
+        | DXGI_FORMAT_R32G32B32A32_UINT
+        | DXGI_FORMAT_R32G32B32A32_SINT => DXGI_FORMAT_R32G32B32A32_TYPELESS,
+        DXGI_FORMAT_R16G16B16A16_TYPELESS
+        | DXGI_FORMAT_R16G16B16A16_FLOAT
+        | DXGI_FORMAT_R16G16B16A16_UNORM
+        | DXGI_FORMAT_R16G16B16A16_UINT
+        | DXGI_FORMAT_R16G16B16A16_SNORM
+        | DXGI_FORMAT_R16G16B16A16_SINT => DXGI_FORMAT_R16G16B16A16_TYPELESS,
+        DXGI_FORMAT_R32G32_TYPELESS
+        | DXGI_FORMAT_R32G32_FLOAT
+        | DXGI_FORMAT_R32G32_UINT
+        | DXGI_FORMAT_R32G32_SINT => DXGI_FORMAT_R32G32_TYPELESS,
+        DXGI_FORMAT_R32G8X24_TYPELESS
+        | DXGI_FORMAT_D32_FLOAT_S8X24_UINT
+        | DXGI_FORMAT_R32_FLOAT_X8X24_TYPELESS
+        | DXGI_FORMAT_X32_TYPELESS_G8X24_UINT => DXGI_FORMAT_R32G8X24_TYPELESS,
+        DXGI_FORMAT_R10G10B10A2_TYPELESS
+        | DXGI_FORMAT_R10G10B10A2_UNORM
+        | DXGI_FORMAT_R10G10B10A2_UINT => DXGI_FORMAT_R10G10B10A2_TYPELESS,
+        DXGI_FORMAT_R8G8B8A8_TYPELESS
+        | DXGI_FORMAT_R8G8B8A8_UNORM
+        | DXGI_FORMAT_R8G8B8A8_UNORM_SRGB
+        | DXGI_FORMAT_R8G8B8A8_UINT
+        | DXGI_FORMAT_R8G8B8A8_SNORM
+        | DXGI_FORMAT_R8G8B8A8_SINT => DXGI_FORMAT_R8G8B8A8_TYPELESS,
+        DXGI_FORMAT_B8G8R8A8_TYPELESS
+        | DXGI_FORMAT_B8G8R8A8_UNORM
+        | DXGI_FORMAT_B8G8R8A8_UNORM_SRGB => DXGI_FORMAT_B8G8R8A8_TYPELESS,
+        DXGI_FORMAT_R16G16_TYPELESS
+        | DXGI_FORMAT_R16G16_FLOAT
+        | DXGI_FORMAT_R16G16_UNORM
+        | DXGI_FORMAT_R16G16_UINT
+        | DXGI_FORMAT_R16G16_SNORM
+        | DXGI_FORMAT_R16G16_SINT => DXGI_FORMAT_R16G16_TYPELESS,
+        DXGI_FORMAT_R32_TYPELESS
+        | DXGI_FORMAT_D32_FLOAT
+        | DXGI_FORMAT_R32_FLOAT
+        | DXGI_FORMAT_R32_UINT
+        | DXGI_FORMAT_R32_SINT => DXGI_FORMAT_R32_TYPELESS,
+        DXGI_FORMAT_R24G8_TYPELESS
+        | DXGI_FORMAT_D24_UNORM_S8_UINT
+        | DXGI_FORMAT_R24_UNORM_X8_TYPELESS
+        | DXGI_FORMAT_X24_TYPELESS_G8_UINT => DXGI_FORMAT_R24G8_TYPELESS,
+        DXGI_FORMAT_R8G8_TYPELESS
+        | DXGI_FORMAT_R8G8_UNORM
+        | DXGI_FORMAT_R8G8_UINT
+        | DXGI_FORMAT_R8G8_SNORM
+        | DXGI_FORMAT_R8G8_SINT => DXGI_FORMAT_R8G8_TYPELESS,
+        DXGI_FORMAT_R16_TYPELESS
+        | DXGI_FORMAT_R16_FLOAT
+        | DXGI_FORMAT_D16_UNORM
+        | DXGI_FORMAT_R16_UNORM
+        | DXGI_FORMAT_R16_UINT
+        | DXGI_FORMAT_R16_SNORM
+        | DXGI_FORMAT_R16_SINT => DXGI_FORMAT_R16_TYPELESS,
+        DXGI_FORMAT_R8_TYPELESS
+        | DXGI_FORMAT_R8_UNORM
+        | DXGI_FORMAT_R8_UINT
+        | DXGI_FORMAT_R8_SNORM
+        | DXGI_FORMAT_R8_SINT => DXGI_FORMAT_R8_TYPELESS,
+        _ => return None,
+    };
+    Some(typeless)
+}
+
+#[inline]
+pub fn is_typeless_format(format: DXGI_FORMAT) -> bool {
+    typeless_format(format) == Some(format)
+}
+
+pub fn is_depth_stencil_format(format: DXGI_FORMAT) -> bool {
+    match format {
+        DXGI_FORMAT_D32_FLOAT
+        | DXGI_FORMAT_D24_UNORM_S8_UINT
+        | DXGI_FORMAT_D32_FLOAT_S8X24_UINT
+        | DXGI_FORMAT_D16_UNORM => true,
+        _ => false,
+    }
+}
+
+// Without relaxed format casting a view has to use the resource format, or any format of the
+// family when the resource is typeless.
+pub fn is_view_format_compatible(resource_format: DXGI_FORMAT, view_format: DXGI_FORMAT) -> bool {
+    resource_format == view_format
+        || (is_typeless_format(resource_format)
+            && typeless_format(view_format) == Some(resource_format))
+}
+
+// (format, first element, number of elements, stride, raw)
+fn buffer_view_range(
+    desc: &D3D12_RESOURCE_DESC,
+    view: &BufferView,
+) -> (DXGI_FORMAT, u64, u32, u32, bool) {
+    assert!(
+        desc.Dimension == D3D12_RESOURCE_DIMENSION_BUFFER,
+        "buffer view of a texture"
+    );
+    let (format, first_element, num_elements, element_size, stride, raw) = match *view {
+        BufferView::Structured {
+            first_element,
+            num_elements,
+            stride,
+        } => {
+            assert!(
+                stride > 0 && stride % 4 == 0,
+                "invalid structure stride {}",
+                stride
+            );
+            (
+                DXGI_FORMAT_UNKNOWN,
+                first_element,
+                num_elements,
+                stride,
+                stride,
+                false,
+            )
+        }
+        BufferView::Typed {
+            format,
+            first_element,
+            num_elements,
+        } => {
+            let element_size = format_size_in_bytes(format)
+                .unwrap_or_else(|| panic!("format {} can't be used for a typed buffer", format));
+            (format, first_element, num_elements, element_size, 0, false)
+        }
+        BufferView::Raw { offset, size } => {
+            assert!(
+                offset % 4 == 0 && size % 4 == 0,
+                "raw buffer views must be 4-byte aligned"
+            );
+            (DXGI_FORMAT_R32_TYPELESS, offset / 4, size / 4, 4, 0, true)
+        }
+    };
+    assert!(num_elements > 0);
+    let end = first_element
+        .checked_add(num_elements as u64)
+        .and_then(|end| end.checked_mul(element_size as u64))
+        .unwrap_or_else(|| {
+            panic!(
+                "buffer view of {} elements at element {} overflows",
+                num_elements, first_element
+            )
+        });
+    assert!(
+        end <= desc.Width,
+        "buffer view ends at byte {} but the buffer is {} bytes",
+        end,
+        desc.Width
+    );
+    (format, first_element, num_elements, stride, raw)
+}
+
+pub fn buffer_srv_desc(
+    desc: &D3D12_RESOURCE_DESC,
+    view: &BufferView,
+) -> D3D12_SHADER_RESOURCE_VIEW_DESC {
+    let (format, first_element, num_elements, stride, raw) = buffer_view_range(desc, view);
+    D3D12_SHADER_RESOURCE_VIEW_DESC {
+        Format: format,
+        ViewDimension: D3D12_SRV_DIMENSION_BUFFER,
+        Shader4ComponentMapping: DEFAULT_SHADER_4_COMPONENT_MAPPING,
+        u: unsafe {
+            let mut u: D3D12_SHADER_RESOURCE_VIEW_DESC_u = mem::zeroed();
+            *u.Buffer_mut() = D3D12_BUFFER_SRV {
+                FirstElement: first_element,
+                NumElements: num_elements,
+                StructureByteStride: stride,
+                Flags: if raw {
+                    D3D12_BUFFER_SRV_FLAG_RAW
+                } else {
+                    D3D12_BUFFER_SRV_FLAG_NONE
+                },
+            };
+            u
+        },
+    }
+}
+
+pub fn buffer_uav_desc(
+    desc: &D3D12_RESOURCE_DESC,
+    view: &BufferView,
+) -> D3D12_UNORDERED_ACCESS_VIEW_DESC {
+    assert!(
+        desc.Flags & D3D12_RESOURCE_FLAG_ALLOW_UNORDERED_ACCESS != 0,
+        "UAV of a buffer created without ALLOW_UNORDERED_ACCESS"
+    );
+    let (format, first_element, num_elements, stride, raw) = buffer_view_range(desc, view);
+    D3D12_UNORDERED_ACCESS_VIEW_DESC {
+        Format: format,
+        ViewDimension: D3D12_UAV_DIMENSION_BUFFER,
+        u: unsafe {
+            let mut u: D3D12_UNORDERED_ACCESS_VIEW_DESC_u = mem::zeroed();
+            *u.Buffer_mut() = D3D12_BUFFER_UAV {
+                FirstElement: first_element,
+                NumElements: num_elements,
+                StructureByteStride: stride,
+                CounterOffsetInBytes: 0,
+                Flags: if raw {
+                    D3D12_BUFFER_UAV_FLAG_RAW
+                } else {
+                    D3D12_BUFFER_UAV_FLAG_NONE
+                },
+            };
+            u
+        },
+    }
+}
+
+pub fn constant_buffer_view_desc(
+    desc: &D3D12_RESOURCE_DESC,
+    buffer_location: D3D12_GPU_VIRTUAL_ADDRESS,
+    offset: u64,
+    size: u32,
+) -> D3D12_CONSTANT_BUFFER_VIEW_DESC {
+    assert!(
+        desc.Dimension == D3D12_RESOURCE_DIMENSION_BUFFER,
+        "constant buffer view of a texture"
+    );
+    let alignment = D3D12_CONSTANT_BUFFER_DATA_PLACEMENT_ALIGNMENT as u64;
+    assert!(
+        offset % alignment == 0 && size as u64 % alignment == 0,
+        "constant buffer views must be 256-byte aligned"
+    );
+    assert!(size > 0 && size <= 64 * 1024);
+    assert!(offset + size as u64 <= desc.Width);
+    D3D12_CONSTANT_BUFFER_VIEW_DESC {
+        BufferLocation: buffer_location + offset,
+        SizeInBytes: size,
+    }
+}
+
+struct TextureRange {
+    format: DXGI_FORMAT,
+    first_mip: u32,
+    num_mips: u32,
+    first_slice: u32,
+    num_slices: u32,
+    is_array: bool,
+    is_multisampled: bool,
+}
+
+fn texture_view_range(desc: &D3D12_RESOURCE_DESC, view: &TextureView) -> TextureRange {
+    assert!(
+        desc.Dimension != D3D12_RESOURCE_DIMENSION_BUFFER,
+        "texture view of a buffer"
+    );
+    let format = if view.format == DXGI_FORMAT_UNKNOWN {
+        desc.Format
+    } else {
+        assert!(
+            is_view_format_compatible(desc.Format, view.format),
+            "view format {} is incompatible with resource format {}",
+            view.format,
+            desc.Format
+        );
+        view.format
+    };
+    assert!(
+        !is_typeless_format(format),
+        "views of typeless resources need an explicit format"
+    );
+
+    let mip_levels = desc.MipLevels as u32;
+    assert!(view.first_mip < mip_levels);
+    let num_mips = if view.num_mips == 0 {
+        mip_levels - view.first_mip
+    } else {
+        view.num_mips
+    };
+    assert!(view.first_mip + num_mips <= mip_levels);
+
+    // The slices of a 3D texture are the depth slices of `first_mip`.
+    let array_size = if desc.Dimension == D3D12_RESOURCE_DIMENSION_TEXTURE3D {
+        (desc.DepthOrArraySize as u32 >> view.first_mip).max(1)
+    } else {
+        desc.DepthOrArraySize as u32
+    };
+    assert!(view.first_slice < array_size);
+    let num_slices = if view.num_slices == 0 {
+        array_size - view.first_slice
+    } else {
+        view.num_slices
+    };
+    assert!(view.first_slice + num_slices <= array_size);
+
+    TextureRange {
+        format,
+        first_mip: view.first_mip,
+        num_mips,
+        first_slice: view.first_slice,
+        num_slices,
+        is_array: desc.Dimension != D3D12_RESOURCE_DIMENSION_TEXTURE3D && array_size > 1,
+        is_multisampled: desc.SampleDesc.Count > 1,
+    }
+}
+
+pub fn texture_srv_desc(
+    desc: &D3D12_RESOURCE_DESC,
+    view: &TextureView,
+) -> D3D12_SHADER_RESOURCE_VIEW_DESC {
+    assert!(
+        desc.Flags & D3D12_RESOURCE_FLAG_DENY_SHADER_RESOURCE == 0,
+        "SRV of a texture created with DENY_SHADER_RESOURCE"
+    );
+    let r = texture_view_range(desc, view);
+    assert!(
+        !is_depth_stencil_format(r.format),
+        "depth formats can't be sampled, create the texture typeless and view it as R32_FLOAT etc."
+    );
+    let mut u: D3D12_SHADER_RESOURCE_VIEW_DESC_u = unsafe { mem::zeroed() };
+    let view_dimension = unsafe {
+        match (desc.Dimension, r.is_array, r.is_multisampled) {
+            (D3D12_RESOURCE_DIMENSION_TEXTURE1D, false, _) => {
+                let t = u.Texture1D_mut();
+                t.MostDetailedMip = r.first_mip;
+                t.MipLevels = r.num_mips;
+                D3D12_SRV_DIMENSION_TEXTURE1D
+            }
+            (D3D12_RESOURCE_DIMENSION_TEXTURE1D, true, _) => {
+                let t = u.Texture1DArray_mut();
+                t.MostDetailedMip = r.first_mip;
+                t.MipLevels = r.num_mips;
+                t.FirstArraySlice = r.first_slice;
+                t.ArraySize = r.num_slices;
+                D3D12_SRV_DIMENSION_TEXTURE1DARRAY
+            }
+            (D3D12_RESOURCE_DIMENSION_TEXTURE2D, false, false) => {
+                let t = u.Texture2D_mut();
+                t.MostDetailedMip = r.first_mip;
+                t.MipLevels = r.num_mips;
+                D3D12_SRV_DIMENSION_TEXTURE2D
+            }
+            (D3D12_RESOURCE_DIMENSION_TEXTURE2D, true, false) => {
+                let t = u.Texture2DArray_mut();
+                t.MostDetailedMip = r.first_mip;
+                t.MipLevels = r.num_mips;
+                t.FirstArraySlice = r.first_slice;
+                t.ArraySize = r.num_slices;
+                D3D12_SRV_DIMENSION_TEXTURE2DARRAY
+            }
+            (D3D12_RESOURCE_DIMENSION_TEXTURE2D, false, true) => D3D12_SRV_DIMENSION_TEXTURE2DMS,
+            (D3D12_RESOURCE_DIMENSION_TEXTURE2D, true, true) => {
+                let t = u.Texture2DMSArray_mut();
+                t.FirstArraySlice = r.first_slice;
+                t.ArraySize = r.num_slices;
+                D3D12_SRV_DIMENSION_TEXTURE2DMSARRAY
+            }
+            _ => {
+                let t = u.Texture3D_mut();
+                t.MostDetailedMip = r.first_mip;
+                t.MipLevels = r.num_mips;
+                D3D12_SRV_DIMENSION_TEXTURE3D
+            }
+        }
+    };
+    D3D12_SHADER_RESOURCE_VIEW_DESC {
+        Format: r.format,
+        ViewDimension: view_dimension,
+        Shader4ComponentMapping: DEFAULT_SHADER_4_COMPONENT_MAPPING,
+        u,
+    }
+}
+
+pub fn texture_uav_desc(
+    desc: &D3D12_RESOURCE_DESC,
+    view: &TextureView,
+) -> D3D12_UNORDERED_ACCESS_VIEW_DESC {
+    assert!(
+        desc.Flags & D3D12_RESOURCE_FLAG_ALLOW_UNORDERED_ACCESS != 0,
+        "UAV of a texture created without ALLOW_UNORDERED_ACCESS"
+    );
+    let r = texture_view_range(desc, view);
+    assert!(!r.is_multisampled, "UAV of a multisampled texture");
+    let mut u: D3D12_UNORDERED_ACCESS_VIEW_DESC_u = unsafe { mem::zeroed() };
+    let view_dimension = unsafe {
+        match (desc.Dimension, r.is_array) {
+            (D3D12_RESOURCE_DIMENSION_TEXTURE1D, false) => {
+                u.Texture1D_mut().MipSlice = r.first_mip;
+                D3D12_UAV_DIMENSION_TEXTURE1D
+            }
+            (D3D12_RESOURCE_DIMENSION_TEXTURE1D, true) => {
+                let t = u.Texture1DArray_mut();
+                t.MipSlice = r.first_mip;
+                t.FirstArraySlice = r.first_slice;
+                t.ArraySize = r.num_slices;
+                D3D12_UAV_DIMENSION_TEXTURE1DARRAY
+            }
+            (D3D12_RESOURCE_DIMENSION_TEXTURE2D, false) => {
+                u.Texture2D_mut().MipSlice = r.first_mip;
+                D3D12_UAV_DIMENSION_TEXTURE2D
+            }
+            (D3D12_RESOURCE_DIMENSION_TEXTURE2D, true) => {
+                let t = u.Texture2DArray_mut();
+                t.MipSlice = r.first_mip;
+                t.FirstArraySlice = r.first_slice;
+                t.ArraySize = r.num_slices;
+                D3D12_UAV_DIMENSION_TEXTURE2DARRAY
+            }
+            _ => {
+                let t = u.Texture3D_mut();
+                t.MipSlice = r.first_mip;
+                t.FirstWSlice = r.first_slice;
+                t.WSize = r.num_slices;
+                D3D12_UAV_DIMENSION_TEXTURE3D
+            }
+        }
+    };
+    D3D12_UNORDERED_ACCESS_VIEW_DESC {
+        Format: r.format,
+        ViewDimension: view_dimension,
+        u,
+    }
+}
+
+pub fn render_target_view_desc(
+    desc: &D3D12_RESOURCE_DESC,
+    view: &TextureView,
+) -> D3D12_RENDER_TARGET_VIEW_DESC {
+    assert!(
+        desc.Flags & D3D12_RESOURCE_FLAG_ALLOW_RENDER_TARGET != 0,
+        "RTV of a texture created without ALLOW_RENDER_TARGET"
+    );
+    let r = texture_view_range(desc, view);
+    let mut u: D3D12_RENDER_TARGET_VIEW_DESC_u = unsafe { mem::zeroed() };
+    let view_dimension = unsafe {
+        match (desc.Dimension, r.is_array, r.is_multisampled) {
+            (D3D12_RESOURCE_DIMENSION_TEXTURE1D, false, _) => {
+                u.Texture1D_mut().MipSlice = r.first_mip;
+                D3D12_RTV_DIMENSION_TEXTURE1D
+            }
+            (D3D12_RESOURCE_DIMENSION_TEXTURE1D, true, _) => {
+                let t = u.Texture1DArray_mut();
+                t.MipSlice = r.first_mip;
+                t.FirstArraySlice = r.first_slice;
+                t.ArraySize = r.num_slices;
+                D3D12_RTV_DIMENSION_TEXTURE1DARRAY
+            }
+            (D3D12_RESOURCE_DIMENSION_TEXTURE2D, false, false) => {
+                u.Texture2D_mut().MipSlice = r.first_mip;
+                D3D12_RTV_DIMENSION_TEXTURE2D
+            }
+            (D3D12_RESOURCE_DIMENSION_TEXTURE2D, true, false) => {
+                let t = u.Texture2DArray_mut();
+                t.MipSlice = r.first_mip;
+                t.FirstArraySlice = r.first_slice;
+                t.ArraySize = r.num_slices;
+                D3D12_RTV_DIMENSION_TEXTURE2DARRAY
+            }
+            (D3D12_RESOURCE_DIMENSION_TEXTURE2D, false, true) => D3D12_RTV_DIMENSION_TEXTURE2DMS,
+            (D3D12_RESOURCE_DIMENSION_TEXTURE2D, true, true) => {
+                let t = u.Texture2DMSArray_mut();
+                t.FirstArraySlice = r.first_slice;
+                t.ArraySize = r.num_slices;
+                D3D12_RTV_DIMENSION_TEXTURE2DMSARRAY
+            }
+            _ => {
+                let t = u.Texture3D_mut();
+                t.MipSlice = r.first_mip;
+                t.FirstWSlice = r.first_slice;
+                t.WSize = r.num_slices;
+                D3D12_RTV_DIMENSION_TEXTURE3D
+            }
+        }
+    };
+    D3D12_RENDER_TARGET_VIEW_DESC {
+        Format: r.format,
+        ViewDimension: view_dimension,
+        u,
+    }
+}
+
+pub fn depth_stencil_view_desc(
+    desc: &D3D12_RESOURCE_DESC,
+    view: &TextureView,
+    flags: D3D12_DSV_FLAGS,
+) -> D3D12_DEPTH_STENCIL_VIEW_DESC {
+    assert!(
+        desc.Flags & D3D12_RESOURCE_FLAG_ALLOW_DEPTH_STENCIL != 0,
+        "DSV of a texture created without ALLOW_DEPTH_STENCIL"
+    );
+    let r = texture_view_range(desc, view);
+    assert!(
+        is_depth_stencil_format(r.format),
+        "format {} can't be used for a depth-stencil view",
+        r.format
+    );
+    let mut u: D3D12_DEPTH_STENCIL_VIEW_DESC_u = unsafe { mem::zeroed() };
+    let view_dimension = unsafe {
+        match (desc.Dimension, r.is_array, r.is_multisampled) {
+            (D3D12_RESOURCE_DIMENSION_TEXTURE1D, false, _) => {
+                u.Texture1D_mut().MipSlice = r.first_mip;
+                D3D12_DSV_DIMENSION_TEXTURE1D
+            }
+            (D3D12_RESOURCE_DIMENSION_TEXTURE1D, true, _) => {
+                let t = u.Texture1DArray_mut();
+                t.MipSlice = r.first_mip;
+                t.FirstArraySlice = r.first_slice;
+                t.ArraySize = r.num_slices;
+                D3D12_DSV_DIMENSION_TEXTURE1DARRAY
+            }
+            (D3D12_RESOURCE_DIMENSION_TEXTURE2D, false, false) => {
+                u.Texture2D_mut().MipSlice = r.first_mip;
+                D3D12_DSV_DIMENSION_TEXTURE2D
+            }
+            (D3D12_RESOURCE_DIMENSION_TEXTURE2D, true, false) => {
+                let t = u.Texture2DArray_mut();
+                t.MipSlice = r.first_mip;
+                t.FirstArraySlice = r.first_slice;
+                t.ArraySize = r.num_slices;
+                D3D12_DSV_DIMENSION_TEXTURE2DARRAY
+            }
+            (D3D12_RESOURCE_DIMENSION_TEXTURE2D, false, true) => D3D12_DSV_DIMENSION_TEXTURE2DMS,
+            (D3D12_RESOURCE_DIMENSION_TEXTURE2D, true, true) => {
+                let t = u.Texture2DMSArray_mut();
+                t.FirstArraySlice = r.first_slice;
+                t.ArraySize = r.num_slices;
+                D3D12_DSV_DIMENSION_TEXTURE2DMSARRAY
+            }
+            _ => panic!("depth-stencil view of a 3D texture"),
+        }
+    };
+    D3D12_DEPTH_STENCIL_VIEW_DESC {
+        Format: r.format,
+        ViewDimension: view_dimension,
+        Flags: flags,
+        u,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use winapi::shared::dxgitype::DXGI_SAMPLE_DESC;
+
+    fn buffer(width: u64) -> D3D12_RESOURCE_DESC {
+        D3D12_RESOURCE_DESC {
+            Dimension: D3D12_RESOURCE_DIMENSION_BUFFER,
+            Width: width,
+            Height: 1,
+            DepthOrArraySize: 1,
+            MipLevels: 1,
+            ..Default::default()
+        }
+    }
+
+    fn texture_3d(size: u32, mip_levels: u16) -> D3D12_RESOURCE_DESC {
+        D3D12_RESOURCE_DESC {
+            Dimension: D3D12_RESOURCE_DIMENSION_TEXTURE3D,
+            Width: size as u64,
+            Height: size,
+            DepthOrArraySize: size as u16,
+            MipLevels: mip_levels,
+            Format: DXGI_FORMAT_R8G8B8A8_UNORM,
+            SampleDesc: DXGI_SAMPLE_DESC {
+                Count: 1,
+                Quality: 0,
+            },
+            Flags: D3D12_RESOURCE_FLAG_ALLOW_UNORDERED_ACCESS,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn typeless_formats() {
+        assert_eq!(
+            typeless_format(DXGI_FORMAT_R8G8B8A8_UNORM_SRGB),
+            Some(DXGI_FORMAT_R8G8B8A8_TYPELESS)
+        );
+        assert_eq!(
+            typeless_format(DXGI_FORMAT_D32_FLOAT),
+            Some(DXGI_FORMAT_R32_TYPELESS)
+        );
+        assert_eq!(
+            typeless_format(DXGI_FORMAT_R24_UNORM_X8_TYPELESS),
+            Some(DXGI_FORMAT_R24G8_TYPELESS)
+        );
+        assert_eq!(typeless_format(DXGI_FORMAT_BC1_UNORM), None);
+
+        assert!(is_typeless_format(DXGI_FORMAT_R32_TYPELESS));
+        assert!(!is_typeless_format(DXGI_FORMAT_R32_FLOAT));
+        assert!(!is_typeless_format(DXGI_FORMAT_R24_UNORM_X8_TYPELESS));
+    }
+
+    #[test]
+    fn view_format_compatibility() {
+        assert!(is_view_format_compatible(
+            DXGI_FORMAT_R16G16_FLOAT,
+            DXGI_FORMAT_R16G16_FLOAT
+        ));
+        assert!(is_view_format_compatible(
+            DXGI_FORMAT_R16G16_TYPELESS,
+            DXGI_FORMAT_R16G16_UINT
+        ));
+        assert!(!is_view_format_compatible(
+            DXGI_FORMAT_R16G16_TYPELESS,
+            DXGI_FORMAT_R32_UINT
+        ));
+        // Typed resources can't be cast without relaxed format casting.
+        assert!(!is_view_format_compatible(
+            DXGI_FORMAT_R16G16_FLOAT,
+            DXGI_FORMAT_R16G16_UINT
+        ));
+
+        assert!(is_view_format_compatible(
+            DXGI_FORMAT_R32_TYPELESS,
+            DXGI_FORMAT_D32_FLOAT
+        ));
+        assert!(is_view_format_compatible(
+            DXGI_FORMAT_R32_TYPELESS,
+            DXGI_FORMAT_R32_FLOAT
+        ));
+        assert!(!is_view_format_compatible(
+            DXGI_FORMAT_D32_FLOAT,
+            DXGI_FORMAT_R32_FLOAT
+        ));
+
+        assert!(is_view_format_compatible(
+            DXGI_FORMAT_R8G8B8A8_TYPELESS,
+            DXGI_FORMAT_R8G8B8A8_UNORM_SRGB
+        ));
+        assert!(!is_view_format_compatible(
+            DXGI_FORMAT_R8G8B8A8_UNORM,
+            DXGI_FORMAT_R8G8B8A8_UNORM_SRGB
+        ));
+        assert!(!is_view_format_compatible(
+            DXGI_FORMAT_R8G8B8A8_TYPELESS,
+            DXGI_FORMAT_B8G8R8A8_UNORM_SRGB
+        ));
+    }
+
+    #[test]
+    fn buffer_view_ranges() {
+        assert_eq!(
+            buffer_view_range(&buffer(256), &BufferView::structured::<[f32; 4]>(16)),
+            (DXGI_FORMAT_UNKNOWN, 0, 16, 16, false)
+        );
+        assert_eq!(
+            buffer_view_range(
+                &buffer(256),
+                &BufferView::Typed {
+                    format: DXGI_FORMAT_R32_UINT,
+                    first_element: 60,
+                    num_elements: 4,
+                }
+            ),
+            (DXGI_FORMAT_R32_UINT, 60, 4, 0, false)
+        );
+        assert_eq!(
+            buffer_view_range(
+                &buffer(256),
+                &BufferView::Raw {
+                    offset: 128,
+                    size: 128
+                }
+            ),
+            (DXGI_FORMAT_R32_TYPELESS, 32, 32, 0, true)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "buffer view ends at byte 260")]
+    fn buffer_view_past_the_end() {
+        buffer_view_range(
+            &buffer(256),
+            &BufferView::Typed {
+                format: DXGI_FORMAT_R32_UINT,
+                first_element: 61,
+                num_elements: 4,
+            },
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "overflows")]
+    fn buffer_view_overflow() {
+        buffer_view_range(
+            &buffer(256),
+            &BufferView::Structured {
+                first_element: u64::MAX / 16,
+                num_elements: 1,
+                stride: 16,
+            },
+        );
+    }
+
+    #[test]
+    fn texture_3d_slices_of_a_mip() {
+        let desc = texture_3d(16, 3);
+        let uav = texture_uav_desc(&desc, &TextureView::mip(2));
+        let t = unsafe { uav.u.Texture3D() };
+        assert_eq!((t.MipSlice, t.FirstWSlice, t.WSize), (2, 0, 4));
+
+        let view = TextureView {
+            first_slice: 2,
+            num_slices: 2,
+            ..TextureView::mip(2)
+        };
+        let uav = texture_uav_desc(&desc, &view);
+        let t = unsafe { uav.u.Texture3D() };
+        assert_eq!((t.MipSlice, t.FirstWSlice, t.WSize), (2, 2, 2));
+    }
+
+    #[test]
+    #[should_panic]
+    fn texture_3d_slices_past_the_mip_depth() {
+        let view = TextureView {
+            first_slice: 2,
+            num_slices: 4,
+            ..TextureView::mip(2)
+        };
+        texture_uav_desc(&texture_3d(16, 3), &view);
+    }
+}
@@ -1,10 +1,10 @@
+use crate::d3d12::Result;
 use std::mem;
 use std::ops::Deref;
 use std::option::Option;
 use std::ptr;
 use winapi::um::d3d12::*;
 use winapi::um::unknwnbase::IUnknown;
-use winapi::um::winnt::HRESULT;
 use winapi::Interface;
 
 pub const DEFAULT_SHADER_4_COMPONENT_MAPPING: u32 =
@@ -62,10 +62,38 @@ impl<T: Interface> WeakPtr<T> {
     }
 }
 
+// Releases the objects added to it when dropped, unless it is disarmed first. Used to clean up
+// when a constructor that creates several objects fails part way.
+pub(crate) struct ReleaseGuard(Vec<WeakPtr<IUnknown>>);
+
+impl ReleaseGuard {
+    pub fn new() -> Self {
+        Self(Vec::new())
+    }
+
+    pub fn add<T: Interface>(&mut self, ptr: WeakPtr<T>) -> WeakPtr<T> {
+        self.0.push(WeakPtr(ptr.as_raw() as *mut IUnknown));
+        ptr
+    }
+
+    pub fn disarm(mut self) {
+        self.0.clear();
+    }
+}
+
+impl Drop for ReleaseGuard {
+    fn drop(&mut self) {
+        for ptr in self.0.iter_mut().rev() {
+            ptr.release();
+        }
+    }
+}
+
 pub type Device = WeakPtr<ID3D12Device2>;
 pub type CommandQueue = WeakPtr<ID3D12CommandQueue>;
 pub type GraphicsCommandList = WeakPtr<ID3D12GraphicsCommandList1>;
 pub type Resource = WeakPtr<ID3D12Resource>;
+pub type Heap = WeakPtr<ID3D12Heap>;
 
 impl Device {
     #[inline]
@@ -85,6 +113,68 @@ impl Device {
         };
         unsafe { self.CreateShaderResourceView(resource, desc, dest_descriptor) };
     }
+
+    #[inline]
+    pub fn create_unordered_access_view(
+        &self,
+        resource: Option<Resource>,
+        counter_resource: Option<Resource>,
+        desc: Option<&D3D12_UNORDERED_ACCESS_VIEW_DESC>,
+        dest_descriptor: D3D12_CPU_DESCRIPTOR_HANDLE,
+    ) {
+        let resource = match resource {
+            Some(r) => r.as_raw(),
+            None => ptr::null_mut(),
+        };
+        let counter_resource = match counter_resource {
+            Some(r) => r.as_raw(),
+            None => ptr::null_mut(),
+        };
+        let desc = match desc {
+            Some(d) => d as *const _,
+            None => ptr::null(),
+        };
+        unsafe {
+            self.CreateUnorderedAccessView(resource, counter_resource, desc, dest_descriptor)
+        };
+    }
+
+    #[inline]
+    pub fn create_constant_buffer_view(
+        &self,
+        desc: &D3D12_CONSTANT_BUFFER_VIEW_DESC,
+        dest_descriptor: D3D12_CPU_DESCRIPTOR_HANDLE,
+    ) {
+        unsafe { self.CreateConstantBufferView(desc, dest_descriptor) };
+    }
+
+    #[inline]
+    pub fn create_render_target_view(
+        &self,
+        resource: Resource,
+        desc: Option<&D3D12_RENDER_TARGET_VIEW_DESC>,
+        dest_descriptor: D3D12_CPU_DESCRIPTOR_HANDLE,
+    ) {
+        let desc = match desc {
+            Some(d) => d as *const _,
+            None => ptr::null(),
+        };
+        unsafe { self.CreateRenderTargetView(resource.as_raw(), desc, dest_descriptor) };
+    }
+
+    #[inline]
+    pub fn create_depth_stencil_view(
+        &self,
+        resource: Resource,
+        desc: Option<&D3D12_DEPTH_STENCIL_VIEW_DESC>,
+        dest_descriptor: D3D12_CPU_DESCRIPTOR_HANDLE,
+    ) {
+        let desc = match desc {
+            Some(d) => d as *const _,
+            None => ptr::null(),
+        };
+        unsafe { self.CreateDepthStencilView(resource.as_raw(), desc, dest_descriptor) };
+    }
 }
 
 impl GraphicsCommandList {
@@ -134,6 +224,32 @@ impl GraphicsCommandList {
         };
     }
 
+    #[inline]
+    pub fn clear_depth_stencil_view(
+        &self,
+        depth_stencil_view: D3D12_CPU_DESCRIPTOR_HANDLE,
+        clear_flags: D3D12_CLEAR_FLAGS,
+        depth: f32,
+        stencil: u8,
+        rects: &[D3D12_RECT],
+    ) {
+        let (num_rects, rects) = if rects.is_empty() {
+            (0 as u32, ptr::null())
+        } else {
+            (rects.len() as u32, rects.as_ptr() as *const _)
+        };
+        unsafe {
+            self.ClearDepthStencilView(
+                depth_stencil_view,
+                clear_flags,
+                depth,
+                stencil,
+                num_rects,
+                rects,
+            )
+        };
+    }
+
     #[inline]
     pub fn copy_buffer_region(
         &self,
@@ -154,6 +270,45 @@ impl GraphicsCommandList {
         };
     }
 
+    #[inline]
+    pub fn copy_texture_region(
+        &self,
+        dst: &D3D12_TEXTURE_COPY_LOCATION,
+        dst_x: u32,
+        dst_y: u32,
+        dst_z: u32,
+        src: &D3D12_TEXTURE_COPY_LOCATION,
+        src_box: Option<&D3D12_BOX>,
+    ) {
+        let src_box = match src_box {
+            Some(b) => b as *const _,
+            None => ptr::null(),
+        };
+        unsafe { self.CopyTextureRegion(dst, dst_x, dst_y, dst_z, src, src_box) };
+    }
+
+    #[inline]
+    pub fn resource_barrier(&self, barriers: &[D3D12_RESOURCE_BARRIER]) {
+        assert!(!barriers.is_empty());
+        unsafe { self.ResourceBarrier(barriers.len() as u32, barriers.as_ptr()) };
+    }
+
+    #[inline]
+    pub fn dispatch(
+        &self,
+        thread_group_count_x: u32,
+        thread_group_count_y: u32,
+        thread_group_count_z: u32,
+    ) {
+        unsafe {
+            self.Dispatch(
+                thread_group_count_x,
+                thread_group_count_y,
+                thread_group_count_z,
+            )
+        };
+    }
+
     #[inline]
     pub fn ia_set_vertex_buffers(&self, start_slot: u32, views: &[D3D12_VERTEX_BUFFER_VIEW]) {
         assert!(!views.is_empty());
@@ -162,6 +317,11 @@ impl GraphicsCommandList {
         };
     }
 
+    #[inline]
+    pub fn ia_set_index_buffer(&self, view: &D3D12_INDEX_BUFFER_VIEW) {
+        unsafe { self.IASetIndexBuffer(view) };
+    }
+
     #[inline]
     pub fn ia_set_primitive_topology(&self, primitive_topology: D3D12_PRIMITIVE_TOPOLOGY) {
         unsafe { self.IASetPrimitiveTopology(primitive_topology) };
@@ -185,6 +345,26 @@ impl GraphicsCommandList {
         };
     }
 
+    #[inline]
+    pub fn draw_indexed_instanced(
+        &self,
+        index_count_per_instance: u32,
+        instance_count: u32,
+        start_index_location: u32,
+        base_vertex_location: i32,
+        start_instance_location: u32,
+    ) {
+        unsafe {
+            self.DrawIndexedInstanced(
+                index_count_per_instance,
+                instance_count,
+                start_index_location,
+                base_vertex_location,
+                start_instance_location,
+            )
+        };
+    }
+
     #[inline]
     pub fn set_graphics_root_shader_resource_view(
         &self,
@@ -194,6 +374,24 @@ impl GraphicsCommandList {
         unsafe { self.SetGraphicsRootShaderResourceView(root_parameter_index, buffer_location) };
     }
 
+    #[inline]
+    pub fn set_graphics_root_constant_buffer_view(
+        &self,
+        root_parameter_index: u32,
+        buffer_location: D3D12_GPU_VIRTUAL_ADDRESS,
+    ) {
+        unsafe { self.SetGraphicsRootConstantBufferView(root_parameter_index, buffer_location) };
+    }
+
+    #[inline]
+    pub fn set_compute_root_constant_buffer_view(
+        &self,
+        root_parameter_index: u32,
+        buffer_location: D3D12_GPU_VIRTUAL_ADDRESS,
+    ) {
+        unsafe { self.SetComputeRootConstantBufferView(root_parameter_index, buffer_location) };
+    }
+
     #[inline]
     pub fn set_graphics_root_descriptor_table(
         &self,
@@ -239,10 +437,36 @@ impl GraphicsCommandList {
     }
 
     #[inline]
-    pub fn close(&self) -> HRESULT {
-        let hr = unsafe { self.Close() };
-        assert_eq!(hr, 0);
-        hr
+    pub fn set_compute_root_descriptor_table(
+        &self,
+        root_parameter_index: u32,
+        base_descriptor: D3D12_GPU_DESCRIPTOR_HANDLE,
+    ) {
+        unsafe { self.SetComputeRootDescriptorTable(root_parameter_index, base_descriptor) };
+    }
+
+    #[inline]
+    pub fn set_compute_root_32bit_constants<T>(
+        &self,
+        root_parameter_index: u32,
+        src_data: &[T],
+        dest_offset_in_32bit_values: u32,
+    ) {
+        assert_eq!(mem::size_of::<T>(), 4);
+        assert!(!src_data.is_empty());
+        unsafe {
+            self.SetComputeRoot32BitConstants(
+                root_parameter_index,
+                src_data.len() as u32,
+                src_data.as_ptr() as *const _,
+                dest_offset_in_32bit_values,
+            )
+        };
+    }
+
+    #[inline]
+    pub fn close(&self, device: Device) -> Result<()> {
+        hr!(self.Close(), device)
     }
 }
 
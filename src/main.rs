@@ -2,6 +2,8 @@ use glam::f32::*;
 use std::ffi::CString;
 use std::mem;
 use std::ptr;
+use std::thread;
+use std::time::Duration;
 use winapi::shared::dxgiformat::*;
 use winapi::shared::dxgitype::DXGI_SAMPLE_DESC;
 use winapi::shared::windef::HWND;
@@ -23,6 +25,7 @@ struct App {
     frame_stats: util::FrameStats,
     d3d: d3d12::Context,
     pso: d3d12::PipelineHandle,
+    depth_buffer: d3d12::ResourceHandle,
     vertex_buffer: d3d12::ResourceHandle,
     index_buffer: d3d12::ResourceHandle,
     transform_buffer: d3d12::ResourceHandle,
@@ -35,21 +38,36 @@ impl App {
     fn new() -> Self {
         let app_name = CString::new("d3d12_simple").unwrap();
         let window = util::create_window(&app_name, 1920, 1080);
-        let mut d3d = d3d12::Context::new(window);
+        let mut d3d = vhr!(d3d12::Context::new(
+            window,
+            &d3d12::ContextDesc::default()
+                .adapter(d3d12::AdapterPreference::from_command_line().unwrap_or_default())
+                .log_hook(log),
+        ));
         let cmdlist = d3d.cmdlist;
 
-        d3d.begin_frame();
+        vhr!(d3d.begin_frame());
 
-        let pso = d3d.create_graphics_pipeline(
+        let pso = vhr!(d3d.create_graphics_pipeline(
             &mut D3D12_GRAPHICS_PIPELINE_STATE_DESC {
                 RasterizerState: d3d12::RasterizerDesc::default(),
                 BlendState: d3d12::BlendDesc::default(),
-                RTVFormats: [DXGI_FORMAT_R8G8B8A8_UNORM, 0, 0, 0, 0, 0, 0, 0],
+                RTVFormats: [
+                    d3d.swapchain_config().format.rtv_format(),
+                    0,
+                    0,
+                    0,
+                    0,
+                    0,
+                    0,
+                    0,
+                ],
                 DepthStencilState: {
                     let mut desc = d3d12::DepthStencilDesc::default();
-                    desc.DepthEnable = 0;
+                    desc.DepthFunc = D3D12_COMPARISON_FUNC_LESS_EQUAL;
                     desc
                 },
+                DSVFormat: d3d12::DepthFormat::D32.dsv_format(),
                 NumRenderTargets: 1,
                 SampleMask: 0xffffffff,
                 SampleDesc: DXGI_SAMPLE_DESC {
@@ -61,20 +79,22 @@ impl App {
             },
             "test.vs.cso",
             "test.ps.cso",
-        );
+        ));
 
+        let depth_buffer = vhr!(d3d.create_window_depth_buffer(d3d12::DepthFormat::D32, false));
         let (vertex_buffer, vertex_buffer_srv) = Self::create_vertex_buffer(&mut d3d);
         let (index_buffer, index_buffer_srv) = Self::create_index_buffer(&mut d3d);
         let (transform_buffer, transform_buffer_srv) = Self::create_transform_buffer(&mut d3d);
 
-        d3d.end_frame(0);
-        d3d.wait_for_gpu();
+        vhr!(d3d.end_frame(0));
+        vhr!(d3d.wait_for_gpu());
 
         Self {
             app_name,
             d3d,
             frame_stats: util::FrameStats::new(),
             pso,
+            depth_buffer,
             vertex_buffer,
             vertex_buffer_srv,
             index_buffer,
@@ -85,7 +105,7 @@ impl App {
     }
 
     fn destroy(&mut self) {
-        self.d3d.wait_for_gpu();
+        vhr!(self.d3d.wait_for_gpu());
         self.d3d.destroy();
     }
 
@@ -132,24 +152,10 @@ impl App {
             data.as_ptr() as *const u8,
             data.len() * mem::size_of::<Vertex>(),
         );
-        let buffer = d3d.resource(buffer_handle);
-        let buffer_srv = d3d.allocate_cpu_descriptors(D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV, 1);
-
-        d3d.device.create_shader_resource_view(
-            Some(buffer),
-            Some(&D3D12_SHADER_RESOURCE_VIEW_DESC {
-                Format: DXGI_FORMAT_UNKNOWN,
-                ViewDimension: D3D12_SRV_DIMENSION_BUFFER,
-                Shader4ComponentMapping: d3d12::DEFAULT_SHADER_4_COMPONENT_MAPPING,
-                u: unsafe {
-                    let mut u: D3D12_SHADER_RESOURCE_VIEW_DESC_u = mem::zeroed();
-                    u.Buffer_mut().NumElements = data.len() as u32;
-                    u.Buffer_mut().StructureByteStride = mem::size_of::<Vertex>() as u32;
-                    u
-                },
-            }),
-            buffer_srv,
-        );
+        let buffer_srv = vhr!(d3d.create_buffer_srv(
+            buffer_handle,
+            &d3d12::BufferView::structured::<Vertex>(data.len() as u32),
+        ));
 
         (buffer_handle, buffer_srv)
     }
@@ -164,23 +170,10 @@ impl App {
             data.as_ptr() as *const u8,
             data.len() * mem::size_of::<u32>(),
         );
-        let buffer = d3d.resource(buffer_handle);
-        let buffer_srv = d3d.allocate_cpu_descriptors(D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV, 1);
-
-        d3d.device.create_shader_resource_view(
-            Some(buffer),
-            Some(&D3D12_SHADER_RESOURCE_VIEW_DESC {
-                Format: DXGI_FORMAT_R32_UINT,
-                ViewDimension: D3D12_SRV_DIMENSION_BUFFER,
-                Shader4ComponentMapping: d3d12::DEFAULT_SHADER_4_COMPONENT_MAPPING,
-                u: unsafe {
-                    let mut u: D3D12_SHADER_RESOURCE_VIEW_DESC_u = mem::zeroed();
-                    u.Buffer_mut().NumElements = data.len() as u32;
-                    u
-                },
-            }),
-            buffer_srv,
-        );
+        let buffer_srv = vhr!(d3d.create_buffer_srv(
+            buffer_handle,
+            &d3d12::BufferView::typed(DXGI_FORMAT_R32_UINT, data.len() as u32),
+        ));
 
         (buffer_handle, buffer_srv)
     }
@@ -198,24 +191,10 @@ impl App {
             data.as_ptr() as *const u8,
             data.len() * mem::size_of::<Mat4>(),
         );
-        let buffer = d3d.resource(buffer_handle);
-        let buffer_srv = d3d.allocate_cpu_descriptors(D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV, 1);
-
-        d3d.device.create_shader_resource_view(
-            Some(buffer),
-            Some(&D3D12_SHADER_RESOURCE_VIEW_DESC {
-                Format: DXGI_FORMAT_UNKNOWN,
-                ViewDimension: D3D12_SRV_DIMENSION_BUFFER,
-                Shader4ComponentMapping: d3d12::DEFAULT_SHADER_4_COMPONENT_MAPPING,
-                u: unsafe {
-                    let mut u: D3D12_SHADER_RESOURCE_VIEW_DESC_u = mem::zeroed();
-                    u.Buffer_mut().NumElements = data.len() as u32;
-                    u.Buffer_mut().StructureByteStride = mem::size_of::<Mat4>() as u32;
-                    u
-                },
-            }),
-            buffer_srv,
-        );
+        let buffer_srv = vhr!(d3d.create_buffer_srv(
+            buffer_handle,
+            &d3d12::BufferView::structured::<Mat4>(data.len() as u32),
+        ));
 
         (buffer_handle, buffer_srv)
     }
@@ -225,23 +204,22 @@ impl App {
         data: *const u8,
         data_size: usize,
     ) -> d3d12::ResourceHandle {
-        let buffer_handle = d3d.create_committed_resource(
+        let buffer_handle = vhr!(d3d.create_committed_resource(
             D3D12_HEAP_TYPE_DEFAULT,
             D3D12_HEAP_FLAG_NONE,
             &d3d12::ResourceDesc::buffer(data_size as u64),
             D3D12_RESOURCE_STATE_COPY_DEST,
             None,
-        );
+        ));
         let buffer = d3d.resource(buffer_handle);
 
         let (cpu_addr, upload_buffer, upload_offset) =
-            d3d.allocate_upload_buffer_region(data_size as u32);
+            vhr!(d3d.allocate_upload_buffer_region(data_size as u32, 256));
         let cpu_addr = cpu_addr as *mut u8;
 
         unsafe { ptr::copy(data, cpu_addr, data_size) };
 
-        d3d.cmdlist
-            .copy_buffer_region(buffer, 0, upload_buffer, upload_offset, data_size as u64);
+        d3d.cmd_copy_buffer_region(buffer, 0, upload_buffer, upload_offset, data_size as u64);
         d3d.cmd_transition_barrier(
             buffer_handle,
             D3D12_RESOURCE_STATE_NON_PIXEL_SHADER_RESOURCE,
@@ -253,7 +231,7 @@ impl App {
     fn draw(&mut self) {
         let d3d = &mut self.d3d;
         let (back_buffer, back_buffer_rtv) = d3d.back_buffer();
-        let cmdlist = d3d.begin_frame();
+        let cmdlist = vhr!(d3d.begin_frame());
 
         cmdlist.rs_set_viewports(&[D3D12_VIEWPORT {
             TopLeftX: 0.0,
@@ -270,31 +248,73 @@ impl App {
             bottom: d3d.resolution[1] as i32,
         }]);
         d3d.cmd_transition_barrier(back_buffer, D3D12_RESOURCE_STATE_RENDER_TARGET);
-        cmdlist.om_set_render_target(back_buffer_rtv, None);
-        cmdlist.clear_render_target_view(back_buffer_rtv, &[0.2 as f32, 0.4, 0.8, 1.0], &[]);
+        let depth_buffer_dsv = vhr!(d3d.depth_stencil_view(self.depth_buffer));
+        d3d.cmd_set_render_target(
+            back_buffer,
+            back_buffer_rtv,
+            Some((self.depth_buffer, depth_buffer_dsv)),
+        );
+        d3d.cmd_clear_render_target_view(
+            back_buffer,
+            back_buffer_rtv,
+            &[0.2 as f32, 0.4, 0.8, 1.0],
+            &[],
+        );
+        d3d.cmd_clear_depth_stencil_view(
+            self.depth_buffer,
+            depth_buffer_dsv,
+            D3D12_CLEAR_FLAG_DEPTH,
+            1.0,
+            0,
+            &[],
+        );
         cmdlist.ia_set_primitive_topology(D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST);
 
         d3d.cmd_set_graphics_pipeline(self.pso);
-        cmdlist.set_graphics_root_descriptor_table(1, {
-            let table_base = d3d.copy_descriptors_to_gpu_heap(1, self.vertex_buffer_srv);
-            d3d.copy_descriptors_to_gpu_heap(1, self.index_buffer_srv);
-            d3d.copy_descriptors_to_gpu_heap(1, self.transform_buffer_srv);
-            table_base
-        });
+        let table_base = d3d.copy_descriptor_table_to_gpu_heap(&[
+            self.vertex_buffer_srv,
+            self.index_buffer_srv,
+            self.transform_buffer_srv,
+        ]);
+        d3d.cmd_set_graphics_root_descriptor_table(
+            1,
+            table_base,
+            &[
+                (
+                    self.vertex_buffer,
+                    d3d12::ResourceUsage::NonPixelShaderResource,
+                ),
+                (
+                    self.index_buffer,
+                    d3d12::ResourceUsage::NonPixelShaderResource,
+                ),
+                (
+                    self.transform_buffer,
+                    d3d12::ResourceUsage::NonPixelShaderResource,
+                ),
+            ],
+        );
 
         cmdlist.set_graphics_root_32bit_constants(0, &[3, 1, 0], 0);
-        cmdlist.draw_instanced(3, 1, 0, 0);
+        d3d.cmd_draw_instanced(3, 1, 0, 0);
 
         cmdlist.set_graphics_root_32bit_constants(0, &[8, 5, 1], 0);
-        cmdlist.draw_instanced(3, 1, 0, 0);
+        d3d.cmd_draw_instanced(3, 1, 0, 0);
 
         d3d.cmd_transition_barrier(back_buffer, D3D12_RESOURCE_STATE_PRESENT);
 
-        d3d.end_frame(0);
+        vhr!(d3d.end_frame(0));
     }
 
     fn run(&mut self) {
         while util::handle_window_messages() {
+            if self.d3d.is_minimized() {
+                thread::sleep(Duration::from_millis(10));
+                continue;
+            }
+            // Window depth buffers are resized by the context and the viewport follows
+            // `resolution`, so nothing else has to be rebuilt on a resize.
+            let _resized = vhr!(self.d3d.handle_window_resize());
             self.frame_stats.update(self.d3d.window, &self.app_name);
             self.draw();
         }
@@ -302,6 +322,10 @@ impl App {
     }
 }
 
+fn log(level: d3d12::LogLevel, message: &str) {
+    eprintln!("[d3d12 {:?}] {}", level, message);
+}
+
 fn main() {
     App::new().run();
 }
@@ -0,0 +1,76 @@
+#define RSIGNATURE \
+    "RootConstants(b0, num32BitConstants = 3), " \
+    "DescriptorTable(SRV(t0), UAV(u0))"
+
+struct Constants0 {
+    uint src_width;
+    uint src_height;
+    uint is_srgb;
+};
+
+ConstantBuffer<Constants0> cbv_0 : register(b0);
+Texture2D<float4> srv_src_mip : register(t0);
+RWTexture2D<float4> uav_dst_mip : register(u0);
+
+float3 srgb_to_linear(float3 c) {
+    return c <= 0.04045f ? c / 12.92f : pow((c + 0.055f) / 1.055f, 2.4f);
+}
+
+float3 linear_to_srgb(float3 c) {
+    return c <= 0.0031308f ? c * 12.92f : 1.055f * pow(c, 1.0f / 2.4f) - 0.055f;
+}
+
+// Must match `downsample_taps` in mipmaps.rs.
+uint downsample_taps(uint src_size, uint x, out uint taps[3], out float weights[3]) {
+    taps[0] = 2 * x;
+    taps[1] = 2 * x + 1;
+    taps[2] = 2 * x + 2;
+    if (src_size == 1) {
+        taps[0] = 0;
+        weights[0] = 1.0f;
+        weights[1] = 0.0f;
+        weights[2] = 0.0f;
+        return 1;
+    } else if ((src_size & 1) == 0) {
+        weights[0] = 0.5f;
+        weights[1] = 0.5f;
+        weights[2] = 0.0f;
+        return 2;
+    } else {
+        float dst_size = (float)(src_size / 2);
+        weights[0] = (dst_size - x) / src_size;
+        weights[1] = dst_size / src_size;
+        weights[2] = (x + 1.0f) / src_size;
+        return 3;
+    }
+}
+
+[RootSignature(RSIGNATURE)]
+[numthreads(8, 8, 1)]
+void main_cs(uint3 dispatch_id : SV_DispatchThreadID) {
+    uint dst_width = max(cbv_0.src_width >> 1, 1);
+    uint dst_height = max(cbv_0.src_height >> 1, 1);
+    if (dispatch_id.x >= dst_width || dispatch_id.y >= dst_height) {
+        return;
+    }
+
+    uint tx[3], ty[3];
+    float wx[3], wy[3];
+    uint nx = downsample_taps(cbv_0.src_width, dispatch_id.x, tx, wx);
+    uint ny = downsample_taps(cbv_0.src_height, dispatch_id.y, ty, wy);
+
+    float4 sum = 0.0f;
+    for (uint j = 0; j < ny; ++j) {
+        for (uint i = 0; i < nx; ++i) {
+            float4 texel = srv_src_mip.Load(int3(tx[i], ty[j], 0));
+            if (cbv_0.is_srgb) {
+                texel.rgb = srgb_to_linear(texel.rgb);
+            }
+            sum += texel * (wx[i] * wy[j]);
+        }
+    }
+    if (cbv_0.is_srgb) {
+        sum.rgb = linear_to_srgb(sum.rgb);
+    }
+    uav_dst_mip[dispatch_id.xy] = sum;
+}
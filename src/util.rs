@@ -10,16 +10,18 @@ use winapi::um::winuser::{
     AdjustWindowRect, CreateWindowExA, DefWindowProcA, DispatchMessageA, LoadCursorA, PeekMessageA,
     PostQuitMessage, RegisterClassA, SetProcessDPIAware, SetWindowTextA, CW_USEDEFAULT, IDC_ARROW,
     MSG, PM_REMOVE, VK_ESCAPE, WM_DESTROY, WM_KEYDOWN, WM_QUIT, WNDCLASSA, WS_CAPTION,
-    WS_MINIMIZEBOX, WS_OVERLAPPED, WS_SYSMENU, WS_VISIBLE,
+    WS_MAXIMIZEBOX, WS_MINIMIZEBOX, WS_OVERLAPPED, WS_SYSMENU, WS_THICKFRAME, WS_VISIBLE,
 };
 
+// Unwraps a `d3d12::Result`, panicking with the decoded error. For the app only; the d3d12
+// module returns its errors.
 #[macro_export]
 macro_rules! vhr {
-    ($name:expr) => {
-        unsafe {
-            let hr = $name;
-            assert_eq!(hr, 0);
-        };
+    ($result:expr) => {
+        match $result {
+            Ok(value) => value,
+            Err(err) => panic!("{}", err),
+        }
     };
 }
 
@@ -74,7 +76,8 @@ pub fn create_window(name: &CString, width: u32, height: u32) -> HWND {
         RegisterClassA(&winclass);
     }
 
-    let style = WS_OVERLAPPED | WS_SYSMENU | WS_CAPTION | WS_MINIMIZEBOX;
+    let style =
+        WS_OVERLAPPED | WS_SYSMENU | WS_CAPTION | WS_MINIMIZEBOX | WS_MAXIMIZEBOX | WS_THICKFRAME;
     let mut rect = RECT {
         left: 0,
         top: 0,